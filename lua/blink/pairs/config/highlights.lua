@@ -3,6 +3,7 @@
 --- @field cmdline boolean Requires `require('vim._extui').enable({})`
 --- @field groups string[] | fun(match: blink.pairs.Match): string Highlight groups for matched pairs, in order that they'll appear based on depth, or a function that returns a highlight group for a given match
 --- @field unmatched_group string Highlight group for unmatched pairs
+--- @field depth_mode 'shared' | 'per_type' Whether the depth used for the highlight groups is shared by all pairs, or counted separately for each type of pair, e.g. `{}` nested in `()` starts from the first group
 --- @field priority number
 --- @field ns integer
 --- @field matchparen blink.pairs.MatchparenConfig
@@ -26,6 +27,7 @@ local highlights = {
       'BlinkPairsBlue',
     },
     unmatched_group = 'BlinkPairsUnmatched',
+    depth_mode = 'shared',
     priority = 200,
     ns = vim.api.nvim_create_namespace('blink.pairs'),
     matchparen = {
@@ -44,6 +46,11 @@ function highlights.validate(config)
     cmdline = { config.cmdline, 'boolean' },
     unmatched_group = { config.unmatched_group, 'string' },
     groups = { config.groups, { 'table', 'function' } },
+    depth_mode = {
+      config.depth_mode,
+      function(val) return vim.tbl_contains({ 'shared', 'per_type' }, val) end,
+      'one of "shared", "per_type"',
+    },
     priority = { config.priority, 'number' },
     ns = { config.ns, 'number' },
     matchparen = { config.matchparen, 'table', true },
@@ -2,6 +2,7 @@
 --- @field enabled boolean
 --- @field cmdline boolean
 --- @field disabled_filetypes string[]
+--- @field engine 'lua' | 'rust' Decides the actions from the current line ('lua') or from the parsed buffer ('rust'), which leaves spaces in strings and comments alone and closes unterminated strings
 --- @field wrap blink.pairs.WrapDefinitions
 --- @field pairs blink.pairs.RuleDefinitions
 
@@ -34,6 +35,7 @@ local mappings = {
     enabled = true,
     cmdline = true,
     disabled_filetypes = {},
+    engine = 'lua',
     wrap = {
       -- move closing pair via motion
       ['<C-b>'] = 'motion',
@@ -149,6 +151,11 @@ function mappings.validate(config)
     enabled = { config.enabled, 'boolean' },
     cmdline = { config.cmdline, 'boolean' },
     disabled_filetypes = { config.disabled_filetypes, 'table' },
+    engine = {
+      config.engine,
+      function(val) return val == 'lua' or val == 'rust' end,
+      'one of "lua", "rust"',
+    },
     wrap = { config.wrap, 'table' },
     pairs = { config.pairs, 'table' },
   }, config)
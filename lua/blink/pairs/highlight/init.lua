@@ -10,7 +10,7 @@ function highlighter.register(config)
   --- @type fun(match: blink.pairs.Match): string
   --- @diagnostic disable-next-line: assign-type-mismatch
   local get_match_highlight = type(config.groups) == 'function' and config.groups
-    or function(match) return config.groups[match.stack_height % #config.groups + 1] end
+    or function(match) return config.groups[(match.depth or match.stack_height) % #config.groups + 1] end
 
   local watcher_attach = require('blink.pairs.watcher').attach
   local get_line_matches = require('blink.pairs.rust').get_line_matches
@@ -90,6 +90,7 @@ function highlighter.register(config)
       rendered[line_number] = true
 
       local matches = get_line_matches(bufnr, line_number)
+      if not matches then return end
       for i = 1, #matches do
         local match = matches[i]
         nvim_buf_set_extmark(bufnr, ns, line_number, match.col, {
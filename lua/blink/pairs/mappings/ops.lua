@@ -50,6 +50,9 @@ function ops.on_key(key, rules)
     if not mappings.is_enabled() then return key end
 
     local ctx = require('blink.pairs.context').new()
+    local keys = ops.decide(ctx, key, rules, key)
+    if keys ~= nil then return keys end
+
     local active_rules = rule_lib.get_all_active(ctx, rules)
 
     for _, rule in ipairs(active_rules) do
@@ -85,6 +88,55 @@ function ops.on_key(key, rules)
   end
 end
 
+--- Decides the action with the rust engine, which uses the parsed buffer rather than only the current line
+--- @param ctx blink.pairs.Context
+--- @param key string
+--- @param rules blink.pairs.Rule[] Must be sorted by priority
+--- @param default string Characters to feed to neovim when no rule applies
+--- @return string? keycodes nil when the engine is disabled or the buffer hasn't been parsed, i.e. in cmdline mode
+function ops.decide(ctx, key, rules, default)
+  if require('blink.pairs.config').mappings.engine ~= 'rust' then return end
+  if ctx.mode:match('c') then return end
+
+  local action = ctx.parser.get_autopair_action(
+    ctx.bufnr,
+    ctx.cursor.row - 1,
+    ctx.cursor.col,
+    key,
+    rules,
+    function(idx, mode)
+      local rule = rules[idx]
+      if mode == nil then return rule.when(ctx) end
+      return rule[mode](ctx)
+    end
+  )
+  if action == nil then return end
+
+  if action.kind == 'insert' then return action.text end
+  -- | -> (|)
+  if action.kind == 'insert_pair' then
+    return action.opening .. action.closing .. ops.shift_keycode(-#action.closing)
+  end
+  -- |) -> )|
+  if action.kind == 'skip_over' then return ops.shift_keycode(action.len) end
+  -- (|) -> |
+  if action.kind == 'delete_pair' then
+    return ops.shift_keycode(action.after) .. string.rep('<BS>', action.before + action.after)
+  end
+  -- "(|)" -> "( | )"
+  if action.kind == 'expand_spaces' then return '<Space><Space>' .. ops.shift_keycode(-1) end
+  -- (|) ->
+  -- (
+  --   |
+  -- )
+  if action.kind == 'newline_split' then
+    if action.trim_spaces then return ops.shift_keycode(1) .. '<BS><BS>' .. '<CR><C-o>O' end
+    return '<CR><C-o>O'
+  end
+
+  return default
+end
+
 --- @param amount number
 --- @return string keycodes Characters to feed to neovim to move the cursor forward or backward
 function ops.shift_keycode(amount)
@@ -174,6 +226,9 @@ function ops.backspace(rules)
     if not mappings.is_enabled() then return '<BS>' end
 
     local ctx = require('blink.pairs.context').new()
+    local keys = ops.decide(ctx, '<BS>', rules, '<BS>')
+    if keys ~= nil then return keys end
+
     local rule, surrounding_space = rule_lib.get_surrounding(ctx, rules, 'backspace')
     if rule == nil then return '<BS>' end
 
@@ -193,6 +248,9 @@ function ops.enter(rules)
     if not mappings.is_enabled() then return '<C-]><CR>' end
 
     local ctx = require('blink.pairs.context').new()
+    local keys = ops.decide(ctx, '<CR>', rules, '<C-]><CR>')
+    if keys ~= nil then return keys end
+
     local rule, surrounding_space = rule_lib.get_surrounding(ctx, rules, 'enter')
     if rule == nil then return '<C-]><CR>' end
 
@@ -213,6 +271,9 @@ function ops.space(rules)
     if not mappings.is_enabled() then return '<C-]><Space>' end
 
     local ctx = require('blink.pairs.context').new()
+    local keys = ops.decide(ctx, '<Space>', rules, '<C-]><Space>')
+    if keys ~= nil then return keys end
+
     local rule = rule_lib.get_surrounding(ctx, rules, 'space')
     if rule == nil then return '<C-]><Space>' end
 
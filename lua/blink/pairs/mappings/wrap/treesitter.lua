@@ -41,6 +41,7 @@ function treesitter.wrap(direction)
   local close_end = close_match.col + #close_char
 
   local nodes = treesitter.get_wrap_nodes(bufnr, row, col)
+    or treesitter.get_expression_ends(bufnr, close_match.line, close_end)
   if not nodes then return end
 
   local targets = {}
@@ -96,6 +97,20 @@ function treesitter.get_wrap_nodes(bufnr, row, col)
   return nodes
 end
 
+--- Fallback without a treesitter parser, using the ends of the expressions after the closing
+--- on the same line, as determined by the pairs parser
+--- @return { end_row: integer, end_col: integer }[]?
+function treesitter.get_expression_ends(bufnr, row, col)
+  local nodes = {}
+  local pos = rust.expression_end_after(bufnr, row, col)
+  while pos and pos.line == row do
+    table.insert(nodes, { end_row = pos.line, end_col = pos.col })
+    pos = rust.expression_end_after(bufnr, pos.line, pos.col)
+  end
+  if #nodes == 0 then return nil end
+  return nodes
+end
+
 --- @param direction 'fwd' | 'rev'
 --- @param ts_state blink.pairs.TsWrapState
 function treesitter.wrap_move(direction, ts_state)
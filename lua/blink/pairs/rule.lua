@@ -35,8 +35,11 @@ function M.is_in_span(span_name)
   local bufnr = vim.api.nvim_get_current_buf()
   local cursor = vim.api.nvim_win_get_cursor(0)
 
-  local span_at = require('blink.pairs.rust').get_span_at(bufnr, cursor[1] - 1, cursor[2])
-  return span_at == span_name
+  local spans = require('blink.pairs.rust').get_span_at(bufnr, cursor[1] - 1, cursor[2]) or {}
+  for _, span in ipairs(spans) do
+    if span.span == span_name then return true end
+  end
+  return false
 end
 
 --- Takes a table of user friendly rule definitions and converts it to a table of rules
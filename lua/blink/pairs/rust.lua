@@ -16,17 +16,184 @@ package.cpath = package.cpath
   .. '../../../target/release/?'
   .. get_lib_extension()
 
+--- Exports return `nil, err` when called incorrectly, and `nil, nil` when there's no result
 --- @class blink.pairs.Parser
---- @field parse_buffer fun(bufnr: number?, shiftwidth: number, filetype: string, lines: string[], start_line: number?, old_end_line: number?, new_end_line: number?): boolean
+--- @field parse_buffer fun(bufnr: number?, shiftwidth: number, filetype: string, lines: string[], start_line: number?, old_end_line: number?, new_end_line: number?, version: number?, depth_mode: 'shared' | 'per_type' | nil): boolean?, blink.pairs.Error?
 --- @field supports_filetype fun(filetype: string): boolean
---- @field get_line_matches fun(bufnr: number, line_number: number, token_type: number?): blink.pairs.Match[]
---- @field get_span_at fun(bufnr: number, row: number, col: number): string?
---- @field get_match_at fun(bufnr: number, row: number, col: number): blink.pairs.Match?
---- @field get_match_pair fun(bufnr: number, row: number, col: number): blink.pairs.MatchWithLine[]?
---- @field get_surrounding_match_pair fun(bufnr: number, row: number, col: number): blink.pairs.MatchWithLine[]?
---- @field get_unmatched_opening_before fun(bufnr: number, opening: string, closing: string, row: number, col: number): blink.pairs.MatchWithLine?
---- @field get_unmatched_closing_after fun(bufnr: number, opening: string, closing: string, row: number, col: number): blink.pairs.MatchWithLine?
---- @field get_indent_levels fun(bufnr: number, start_line: number, end_line: number): number[]
+--- @field get_line_matches fun(bufnr: number, line_number: number, token_type: blink.pairs.TokenType?): blink.pairs.Match[]?, blink.pairs.Error?
+--- @field get_range_matches fun(bufnr: number, start_line: number, end_line: number, filter: number?): blink.pairs.RangeMatches?, blink.pairs.Error? Matches in the range (exclusive), where the filter has bit `n` set for each `blink.pairs.TokenType` to include, plus 128 for matched and 256 for unmatched matches. Defaults to delimiters
+--- @field get_span_at fun(bufnr: number, row: number, col: number): blink.pairs.SpanRange[]?, blink.pairs.Error? Spans containing the position, from the outermost to the innermost
+--- @field get_spans_in_range fun(bufnr: number, start_line: number, end_line: number): blink.pairs.SpanRange[]?, blink.pairs.Error? Spans overlapping the range (exclusive), ordered by their start
+--- @field get_match_at fun(bufnr: number, row: number, col: number): blink.pairs.Match?, blink.pairs.Error?
+--- @field context_at fun(bufnr: number, row: number, col: number): blink.pairs.LexicalContext?, blink.pairs.Error? Lexical context at the cursor, between the characters at `col - 1` and `col`
+--- @field get_match_pair fun(bufnr: number, row: number, col: number): blink.pairs.MatchWithLine[]?, blink.pairs.Error?
+--- @field get_surrounding_match_pair fun(bufnr: number, row: number, col: number): blink.pairs.MatchWithLine[]?, blink.pairs.Error?
+--- @field get_unmatched_opening_before fun(bufnr: number, opening: string, closing: string, row: number, col: number): blink.pairs.MatchWithLine?, blink.pairs.Error?
+--- @field get_unmatched_closing_after fun(bufnr: number, opening: string, closing: string, row: number, col: number): blink.pairs.MatchWithLine?, blink.pairs.Error?
+--- @field get_indent_levels fun(bufnr: number, start_line: number, end_line: number): number[]?, blink.pairs.Error?
+--- @field detect_indent fun(bufnr: number): blink.pairs.IndentStyle?, blink.pairs.Error?
+--- @field get_diagnostics fun(bufnr: number, start_line: number, end_line: number): blink.pairs.Diagnostic[]?, blink.pairs.Error?
+--- @field get_next_opening fun(bufnr: number, row: number, col: number, filter: string?): blink.pairs.MatchWithLine?, blink.pairs.Error?
+--- @field get_prev_opening fun(bufnr: number, row: number, col: number, filter: string?): blink.pairs.MatchWithLine?, blink.pairs.Error?
+--- @field get_next_closing fun(bufnr: number, row: number, col: number, filter: string?): blink.pairs.MatchWithLine?, blink.pairs.Error?
+--- @field get_prev_closing fun(bufnr: number, row: number, col: number, filter: string?): blink.pairs.MatchWithLine?, blink.pairs.Error?
+--- @field get_parent_pair fun(bufnr: number, row: number, col: number, filter: string?): blink.pairs.MatchWithLine[]?, blink.pairs.Error?
+--- @field get_first_child_pair fun(bufnr: number, row: number, col: number, filter: string?): blink.pairs.MatchWithLine[]?, blink.pairs.Error?
+--- @field get_last_child_pair fun(bufnr: number, row: number, col: number, filter: string?): blink.pairs.MatchWithLine[]?, blink.pairs.Error?
+--- @field get_next_sibling_pair fun(bufnr: number, row: number, col: number, filter: string?): blink.pairs.MatchWithLine[]?, blink.pairs.Error?
+--- @field get_prev_sibling_pair fun(bufnr: number, row: number, col: number, filter: string?): blink.pairs.MatchWithLine[]?, blink.pairs.Error?
+--- @field get_tab_out_closing fun(bufnr: number, row: number, col: number, filter: string?): blink.pairs.MatchWithLine?, blink.pairs.Error?
+--- @field get_text_object fun(bufnr: number, row: number, col: number, kind: string, mode: 'inner' | 'around', count: number?): blink.pairs.TextObject?, blink.pairs.Error?
+--- @field get_pair_tree fun(bufnr: number, start_line: number, end_line: number): blink.pairs.PairTreeNode[]?, blink.pairs.Error?
+--- @field get_selection_ranges fun(bufnr: number, row: number, col: number): blink.pairs.TextObject[]?, blink.pairs.Error?
+--- @field get_autopair_action fun(bufnr: number, row: number, col: number, key: string, rules: { opening: string, closing: string }[], is_active: fun(idx: number, mode: blink.pairs.Mode?): boolean): blink.pairs.AutopairAction?, blink.pairs.Error?
+--- @field get_folds fun(bufnr: number, start_line: number, end_line: number, opts: blink.pairs.FoldOptions?): blink.pairs.Folds?, blink.pairs.Error?
+--- @field get_scope_at fun(bufnr: number, row: number, col: number): blink.pairs.Scope?, blink.pairs.Error?
+--- @field get_scope_guides fun(bufnr: number, start_line: number, end_line: number): number[][]?, blink.pairs.Error?
+--- @field suggest_indent fun(bufnr: number, line: number, shiftwidth: number): number?, blink.pairs.Error? Indentation for the line, or -1 to keep the current indentation like `indentexpr`
+--- @field reindent_range fun(bufnr: number, start_line: number, end_line: number, shiftwidth: number): number[]?, blink.pairs.Error? Indentation for each line, or -1 for blank lines and lines inside block strings or comments
+--- @field simulate_edit fun(bufnr: number, row: number, col: number, text: string): blink.pairs.EditSimulation?, blink.pairs.Error? Parses the buffer as if the text had been inserted, without modifying it
+--- @field expression_end_after fun(bufnr: number, row: number, col: number): blink.pairs.Position?, blink.pairs.Error? End (exclusive) of the word with calls and indexing, or string, at or after the position
+--- @field expression_start_before fun(bufnr: number, row: number, col: number): blink.pairs.Position?, blink.pairs.Error? Start of the word with calls and indexing, or string, ending at or before the position
+--- @field structural_edit fun(bufnr: number, row: number, col: number, operation: blink.pairs.StructuralOp): blink.pairs.TextEdit[]?, blink.pairs.Error? Edits for paredit style operations on the list containing the cursor, from the end of the buffer
+--- @field parinfer fun(bufnr: number, mode: 'indent' | 'paren', cursor_row: number?, cursor_col: number?): blink.pairs.Parinfer?, blink.pairs.Error? Edits inferring closing delimiters from indentation, or indentation from delimiters
+--- @field batch_query fun(query: 'match_at' | 'match_pair' | 'surrounding_match_pair' | 'span_at' | 'stack_height', positions: { [1]: number, [2]: number, [3]: number }[]): blink.pairs.BatchResults?, blink.pairs.Error? Runs the query for each `{ bufnr, row, col }` in a single call
+--- @field surround_delete fun(bufnr: number, row: number, col: number, target: string?, opts: blink.pairs.SurroundOptions?): blink.pairs.TextEdit[]?, blink.pairs.Error? Edits removing the surrounding pair, from the end of the buffer
+--- @field surround_change fun(bufnr: number, row: number, col: number, target: string?, new_open: string, new_close: string, opts: blink.pairs.SurroundOptions?): blink.pairs.TextEdit[]?, blink.pairs.Error? Edits replacing the surrounding pair, from the end of the buffer
+
+--- @alias blink.pairs.ErrorCode 'unknown_buffer' | 'unsupported_filetype' | 'line_out_of_range' | 'col_out_of_range' | 'invalid_token_type' | 'stale_version' | 'invalid_argument' | 'stale_text' | 'panicked'
+
+--- @class blink.pairs.Error
+--- @field code blink.pairs.ErrorCode
+--- @field message string
+--- @field bufnr number?
+--- @field filetype string?
+--- @field line number?
+--- @field line_count number?
+--- @field col number?
+--- @field line_len number?
+--- @field token_type number?
+--- @field version number?
+--- @field current_version number?
+--- @field name string?
+--- @field value string?
+--- @field panic string?
+
+--- @class blink.pairs.IndentStyle
+--- @field expand_tab boolean? Whether the buffer is indented with spaces, or nil when no lines are indented
+--- @field shiftwidth number? Most common indentation step, or nil when indented with tabs
+--- @field mixed_lines number[] Lines mixing tabs and spaces, or indented differently from the rest of the buffer
+--- @field confidence number Between 0 and 1
+
+--- @class blink.pairs.Diagnostic : vim.Diagnostic
+--- @field code 'unmatched_opening' | 'unmatched_closing' | 'mismatched_closing' | 'unterminated_string' | 'unterminated_block_comment' | 'unterminated_block_string'
+--- @field user_data { fix: blink.pairs.Fix? }
+
+--- @class blink.pairs.Fix
+--- @field kind 'insert' | 'insert_line_after' | 'delete' | 'replace'
+--- @field line number
+--- @field col number?
+--- @field end_col number?
+--- @field indent number?
+--- @field text string?
+
+--- Range with an exclusive end. `kind` may be `delimiter`, `string`, `block_comment`, `span:<name>`
+--- or the opening/closing text of a pair such as `(` or `"`
+--- @class blink.pairs.TextObject
+--- @field start_line number
+--- @field start_col number
+--- @field end_line number
+--- @field end_col number
+
+--- @class blink.pairs.Position
+--- @field line number
+--- @field col number
+
+--- @alias blink.pairs.StructuralOp 'slurp_forward' | 'slurp_backward' | 'barf_forward' | 'barf_backward' | 'raise' | 'splice' | 'split' | 'join' | 'transpose'
+
+--- @class blink.pairs.Parinfer
+--- @field edits blink.pairs.TextEdit[] Ordered from the end of the buffer
+--- @field cursor blink.pairs.Position? Cursor after applying the edits, when a cursor was given
+
+--- @class blink.pairs.BatchResults
+--- @field results any[] Result for each position, in order, or `false` when there's no result
+--- @field errors table<number, blink.pairs.Error> Errors keyed by the index of the position
+
+--- 0: delimiter, 1: string, 2: block string, 3: line comment, 4: block comment, 5: inline span, 6: block span
+--- @alias blink.pairs.TokenType 0 | 1 | 2 | 3 | 4 | 5 | 6
+
+--- Parallel arrays with an entry per match
+--- @class blink.pairs.RangeMatches
+--- @field lines number[]
+--- @field cols number[]
+--- @field end_cols number[]
+--- @field depths number[] Stack height, or -1 when the match has no pair
+--- @field kinds blink.pairs.TokenType[]
+
+--- @class blink.pairs.SurroundOptions
+--- @field trim_padding boolean? Remove the whitespace after the opening and before the closing
+--- @field remove_lone_closing boolean? When deleting, remove the line of the closing if nothing else is left on it
+
+--- Range to replace with the text, as in `vim.api.nvim_buf_set_text`
+--- @class blink.pairs.TextEdit : blink.pairs.TextObject
+--- @field text string
+
+--- @class blink.pairs.AutopairAction
+--- @field kind 'passthrough' | 'insert' | 'insert_pair' | 'skip_over' | 'delete_pair' | 'expand_spaces' | 'newline_split'
+--- @field text string? Text to insert instead of the key
+--- @field opening string? Text to insert before the cursor
+--- @field closing string? Text to insert after the cursor
+--- @field len number? Number of characters to move over
+--- @field before number? Number of characters to delete before the cursor
+--- @field after number? Number of characters to delete after the cursor
+--- @field trim_spaces boolean? Whether to delete the spaces around the cursor before splitting
+
+--- @class blink.pairs.State
+--- @field kind 'normal' | 'string' | 'block_string' | 'line_comment' | 'block_comment' | 'inline_span' | 'block_span'
+--- @field delimiter string? Opening of the string or block comment
+--- @field span string? Name of the innermost span
+--- @field spans string[]? Names of the spans, from the outermost to the innermost
+
+--- @class blink.pairs.LexicalContext
+--- @field state blink.pairs.State Whether the position is in code (`normal`), a string, a comment or a span
+--- @field pairs blink.pairs.MatchWithLine[][] Opening and closing of the delimiter pairs containing the position, from the outermost to the innermost
+
+--- Span with an exclusive end. Spans without a closing extend to the end of the line, or the end of the buffer for block spans
+--- @class blink.pairs.SpanRange
+--- @field span string
+--- @field block boolean
+--- @field start_line number
+--- @field start_col number
+--- @field end_line number
+--- @field end_col number
+
+--- @class blink.pairs.EditSimulation
+--- @field unmatched_delta number Change in the number of unmatched openings and closings
+--- @field matched boolean? Whether the last opening or closing in the inserted text has a pair, or nil when there are none
+--- @field state blink.pairs.State State at the end of the inserted text
+
+--- @class blink.pairs.FoldOptions
+--- @field min_lines number? Minimum lines in a fold, defaults to 2
+--- @field kinds string[]? Same as the `kind` of text objects, defaults to `{ 'delimiter', 'string', 'block_comment' }`
+--- @field closing_visible boolean? Leave the line with the closing outside of the fold
+
+--- @class blink.pairs.Folds
+--- @field levels number[]
+--- @field exprs string[] Values for `foldexpr`
+
+--- @class blink.pairs.Scope
+--- @field start_line number Line opening the scope
+--- @field end_line number Line closing the scope (inclusive), or the last line of an indentation based scope
+--- @field indent number Column of the guide
+
+--- Matched pair, or an opening/closing without a match when `error` is set
+--- @class blink.pairs.PairTreeNode
+--- @field open { line: number, col: number }?
+--- @field close { line: number, col: number }?
+--- @field token { [1]: string, [2]: string? }
+--- @field span string?
+--- @field error boolean?
+--- @field children blink.pairs.PairTreeNode[]
 
 --- @class blink.pairs.Match
 --- @field [1] string
@@ -34,6 +201,7 @@ package.cpath = package.cpath
 --- @field span string?
 --- @field col number
 --- @field stack_height number?
+--- @field depth number? Nesting depth among the pairs with the same token, when parsed with the `per_type` depth mode
 
 --- @class blink.pairs.MatchWithLine : blink.pairs.Match
 --- @field line number
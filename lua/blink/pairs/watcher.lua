@@ -40,10 +40,24 @@ local function parse_buffer(bufnr, start_line, old_end_line, new_end_line)
     end
   end
 
-  local ok, filetype_supported, full_reparse_needed =
-    pcall(rust.parse_buffer, bufnr, utils.get_tab_width(bufnr), ft, lines, start_line, old_end_line, new_end_line)
-  local did_parse = ok and filetype_supported
-  local state_changed = ok and full_reparse_needed
+  local ok, full_reparse_needed, err = pcall(
+    rust.parse_buffer,
+    bufnr,
+    utils.get_tab_width(bufnr),
+    ft,
+    lines,
+    start_line,
+    old_end_line,
+    new_end_line,
+    vim.api.nvim_buf_get_changedtick(bufnr),
+    require('blink.pairs.config').highlights.depth_mode
+  )
+
+  -- the edit didn't line up with the parsed buffer, start from scratch
+  if ok and err and err.code == 'line_out_of_range' and new_end_line then return parse_buffer(bufnr) end
+
+  local did_parse = ok and full_reparse_needed ~= nil
+  local state_changed = did_parse and full_reparse_needed
 
   if did_parse and require('blink.pairs.config').debug then
     vim.print('parsing time: ' .. (vim.uv.hrtime() - start_time) / 1e6 .. ' ms')
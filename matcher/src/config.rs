@@ -1,7 +1,7 @@
 use syn::parse::{Parse, ParseStream};
 use syn::token::{Colon, Comma, FatArrow};
 use syn::{braced, bracketed, Result};
-use syn::{Ident, LitStr};
+use syn::{Ident, LitBool, LitStr};
 
 use std::collections::{HashMap, HashSet};
 
@@ -15,6 +15,11 @@ pub struct MatcherDef {
     pub block_strings: Vec<(String, String)>,
     pub inline_spans: HashMap<String, (String, String)>,
     pub block_spans: HashMap<String, (String, String)>,
+    /// Spans which may contain other spans, the rest are raw (i.e. code blocks)
+    pub nesting_spans: Vec<String>,
+    /// Treats delimiters and string openings after a backslash as literal characters,
+    /// i.e. Clojure's `\(` and Scheme's `#\(`
+    pub literal_escapes: bool,
 }
 
 // Parse the incoming macro definition into a MatcherDef struct
@@ -32,6 +37,8 @@ impl Parse for MatcherDef {
         let mut block_strings = Vec::new();
         let mut inline_spans = HashMap::new();
         let mut block_spans = HashMap::new();
+        let mut nesting_spans = Vec::new();
+        let mut literal_escapes = false;
 
         fn get_single_char(token: LitStr) -> Result<String> {
             let value = token.value();
@@ -50,10 +57,18 @@ impl Parse for MatcherDef {
             let section_name = content.parse::<Ident>()?;
             content.parse::<Colon>()?;
 
+            if section_name == "literal_escapes" {
+                literal_escapes = content.parse::<LitBool>()?.value;
+                if !content.is_empty() {
+                    content.parse::<Comma>()?;
+                }
+                continue;
+            }
+
             let section_content;
             match section_name.to_string().as_str() {
                 "delimiters" | "line_comment" | "block_comment" | "string" | "char"
-                | "block_string" => {
+                | "block_string" | "nesting_spans" => {
                     bracketed!(section_content in content);
                 }
                 "inline_span" | "block_span" => {
@@ -152,6 +167,14 @@ impl Parse for MatcherDef {
                         }
                     }
                 }
+                "nesting_spans" => {
+                    while !section_content.is_empty() {
+                        nesting_spans.push(section_content.parse::<Ident>()?.to_string());
+                        if !section_content.is_empty() {
+                            section_content.parse::<Comma>()?;
+                        }
+                    }
+                }
                 _ => return Err(syn::Error::new(section_name.span(), "Unknown section name")),
             }
 
@@ -170,6 +193,8 @@ impl Parse for MatcherDef {
             block_strings,
             inline_spans,
             block_spans,
+            nesting_spans,
+            literal_escapes,
         })
     }
 }
@@ -94,28 +94,36 @@ pub fn define_matcher(input: TokenStream) -> TokenStream {
     }
 
     // 3. Block span patterns
+    let nesting_spans = &def.nesting_spans;
+    let can_open_span = quote! { state.can_open_span(&[#(#nesting_spans),*]) };
+    let span_states = quote! { State::Normal | State::InInlineSpan(_) | State::InBlockSpan(_) };
     for (name, (open, close)) in &def.block_spans {
-        let arm = MatchArm::builder(open.to_string(), max_lookahead).body(quote! {
-            matches.push(Match::new(Kind::Opening, Token::BlockSpan(#name, #open, #close), token.col));
-            // Skip tokens based on length of pattern
-            for _ in 1..#open.len() {
-                tokens.next();
-            }
-            State::InBlockSpan(#name)
-        });
-        match_arms.push(arm.build());
-
+        // Closing first, so that spans with the same opening and closing can't nest in themselves
         let close_arm = MatchArm::builder(close.to_string(), max_lookahead)
-            .input_state(quote! { State::InBlockSpan(#name) })
+            .input_state(quote! { State::InBlockSpan(spans) })
+            .if_condition(quote! { spans.top().is_some_and(|span| span.name == #name) })
             .body(quote! {
                 matches.push(Match::new(Kind::Closing, Token::BlockSpan(#name, #open, #close), token.col));
                 // Skip tokens based on length of pattern
                 for _ in 1..#close.len() {
                     tokens.next();
                 }
-                State::Normal
+                state.close_span()
             });
         match_arms.push(close_arm.build());
+
+        let arm = MatchArm::builder(open.to_string(), max_lookahead)
+            .input_state(span_states.clone())
+            .if_condition(can_open_span.clone())
+            .body(quote! {
+                matches.push(Match::new(Kind::Opening, Token::BlockSpan(#name, #open, #close), token.col));
+                // Skip tokens based on length of pattern
+                for _ in 1..#open.len() {
+                    tokens.next();
+                }
+                state.open_span(OpenSpan::block(#name, #open))
+            });
+        match_arms.push(arm.build());
     }
 
     // 4. Line comment patterns
@@ -137,7 +145,7 @@ pub fn define_matcher(input: TokenStream) -> TokenStream {
     // 5. String patterns
     for delim in &def.strings {
         // Opening string
-        let open_arm = MatchArm::builder(delim.to_string(), max_lookahead).body(quote! {
+        let mut open_arm = MatchArm::builder(delim.to_string(), max_lookahead).body(quote! {
             matches.push(Match::new(Kind::Opening, Token::String(#delim), token.col));
             // Skip tokens based on length of pattern
             for _ in 1..#delim.len() {
@@ -145,6 +153,9 @@ pub fn define_matcher(input: TokenStream) -> TokenStream {
             }
             State::InString(#delim)
         });
+        if def.literal_escapes {
+            open_arm = open_arm.ignore_escaped();
+        }
         // TODO: skip tokens based on length of pattern
         match_arms.push(open_arm.build());
 
@@ -194,43 +205,52 @@ pub fn define_matcher(input: TokenStream) -> TokenStream {
 
     // 7. Inline span patterns
     for (name, (open, close)) in &def.inline_spans {
-        let arm = MatchArm::builder(open.to_string(), max_lookahead).body(quote! {
-            matches.push(Match::new(Kind::Opening, Token::InlineSpan(#name, #open, #close), token.col));
-            // Skip tokens based on length of pattern
-            for _ in 1..#open.len() {
-                tokens.next();
-            }
-            State::InInlineSpan(#name)
-        });
-        match_arms.push(arm.build());
-
         let close_arm = MatchArm::builder(close.to_string(), max_lookahead)
-            .input_state(quote! { State::InInlineSpan(#name) })
+            .input_state(quote! { State::InInlineSpan(spans) })
+            .if_condition(quote! { spans.top().is_some_and(|span| span.name == #name) })
             .body(quote! {
                 matches.push(Match::new(Kind::Closing, Token::InlineSpan(#name, #open, #close), token.col));
                 // Skip tokens based on length of pattern
                 for _ in 1..#close.len() {
                     tokens.next();
                 }
-                State::Normal
+                state.close_span()
             });
         match_arms.push(close_arm.build());
+
+        let arm = MatchArm::builder(open.to_string(), max_lookahead)
+            .input_state(span_states.clone())
+            .if_condition(can_open_span.clone())
+            .body(quote! {
+                matches.push(Match::new(Kind::Opening, Token::InlineSpan(#name, #open, #close), token.col));
+                // Skip tokens based on length of pattern
+                for _ in 1..#open.len() {
+                    tokens.next();
+                }
+                state.open_span(OpenSpan::inline(#name, #open))
+            });
+        match_arms.push(arm.build());
     }
 
     // 8. Delimiter patterns
     for (open, close) in &def.delimiters {
         // Opening delimiter
-        let open_arm = MatchArm::builder(open.to_string(), max_lookahead).body(quote! {
+        let mut open_arm = MatchArm::builder(open.to_string(), max_lookahead).body(quote! {
             matches.push(Match::new(Kind::Opening, Token::Delimiter(#open, #close), token.col));
             State::Normal
         });
-        match_arms.push(open_arm.build());
 
         // Closing delimiter
-        let close_arm = MatchArm::builder(close.to_string(), max_lookahead).body(quote! {
+        let mut close_arm = MatchArm::builder(close.to_string(), max_lookahead).body(quote! {
             matches.push(Match::new(Kind::Closing, Token::Delimiter(#open, #close), token.col));
             State::Normal
         });
+
+        if def.literal_escapes {
+            open_arm = open_arm.ignore_escaped();
+            close_arm = close_arm.ignore_escaped();
+        }
+        match_arms.push(open_arm.build());
         match_arms.push(close_arm.build());
     }
 
@@ -0,0 +1,643 @@
+//! Decides what to do when a key is typed in insert mode, i.e. whether to insert a pair,
+//! skip over a closing or delete a pair, from the text around the cursor and the parsed buffer.
+
+use std::str::FromStr;
+
+use mlua::{FromLua, IntoLua};
+
+use crate::buffer::ParsedBuffer;
+use crate::error::Error;
+use crate::parser::State;
+
+/// Pair which may be inserted. Rules must be sorted by priority, highest first
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub opening: String,
+    pub closing: String,
+}
+
+impl Rule {
+    pub fn new(opening: &str, closing: &str) -> Self {
+        Self {
+            opening: opening.to_string(),
+            closing: closing.to_string(),
+        }
+    }
+}
+
+impl FromLua for Rule {
+    fn from_lua(value: mlua::Value, lua: &mlua::Lua) -> mlua::Result<Self> {
+        let table = mlua::Table::from_lua(value, lua)?;
+        Ok(Self {
+            opening: table.get("opening")?,
+            closing: table.get("closing")?,
+        })
+    }
+}
+
+/// Condition of a rule, checked for the rule before it's applied
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RuleMode {
+    Open,
+    Close,
+    /// Used in-place of open and close when the opening and closing are the same
+    OpenOrClose,
+    Enter,
+    Backspace,
+    Space,
+}
+
+impl RuleMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleMode::Open => "open",
+            RuleMode::Close => "close",
+            RuleMode::OpenOrClose => "open_or_close",
+            RuleMode::Enter => "enter",
+            RuleMode::Backspace => "backspace",
+            RuleMode::Space => "space",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Key {
+    Char(String),
+    Backspace,
+    Enter,
+    Space,
+}
+
+/// Parses `<BS>`, `<CR>` and `<Space>`, or otherwise treats the text as the typed characters
+impl FromStr for Key {
+    type Err = Error;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        Ok(match key {
+            "" => {
+                return Err(Error::InvalidArgument {
+                    name: "key",
+                    value: key.to_string(),
+                })
+            }
+            "<BS>" => Key::Backspace,
+            "<CR>" => Key::Enter,
+            "<Space>" | " " => Key::Space,
+            _ => Key::Char(key.to_string()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Perform the default behavior of the key
+    Passthrough,
+    /// Insert the text before the cursor instead of the key
+    Insert { text: String },
+    /// Insert the opening before the cursor and the closing after the cursor
+    InsertPair { opening: String, closing: String },
+    /// Move the cursor forward over the closing
+    SkipOver { len: usize },
+    /// Delete the text on either side of the cursor
+    DeletePair { before: usize, after: usize },
+    /// Insert a space on either side of the cursor
+    ExpandSpaces,
+    /// Insert a new line and move the closing to the line after it, deleting the spaces around
+    /// the cursor when `trim_spaces` is set
+    NewlineSplit { trim_spaces: bool },
+}
+
+impl IntoLua for Action {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+
+        let kind = match self {
+            Action::Passthrough => "passthrough",
+            Action::Insert { text } => {
+                table.set("text", text)?;
+                "insert"
+            }
+            Action::InsertPair { opening, closing } => {
+                table.set("opening", opening)?;
+                table.set("closing", closing)?;
+                "insert_pair"
+            }
+            Action::SkipOver { len } => {
+                table.set("len", len)?;
+                "skip_over"
+            }
+            Action::DeletePair { before, after } => {
+                table.set("before", before)?;
+                table.set("after", after)?;
+                "delete_pair"
+            }
+            Action::ExpandSpaces => "expand_spaces",
+            Action::NewlineSplit { trim_spaces } => {
+                table.set("trim_spaces", trim_spaces)?;
+                "newline_split"
+            }
+        };
+        table.set("kind", kind)?;
+
+        (&table).into_lua(lua)
+    }
+}
+
+/// Length of the longest suffix of `a` which is a prefix of `b`
+fn find_overlap(a: &str, b: &str) -> usize {
+    (1..=a.len().min(b.len()))
+        .rev()
+        .find(|&overlap| b.is_char_boundary(overlap) && a.ends_with(&b[..overlap]))
+        .unwrap_or(0)
+}
+
+/// Whether typing the key could open or close a pair of the rule
+fn is_candidate(rule: &Rule, key: &str) -> bool {
+    rule.opening.contains(key) || rule.closing.starts_with(key)
+}
+
+/// Pairs left unbalanced around the cursor for a rule
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Unmatched {
+    opening_before: bool,
+    closing_after: bool,
+}
+
+impl Unmatched {
+    fn new(buffer: &ParsedBuffer, rule: &Rule, line_number: usize, col: usize) -> Self {
+        let (opening, closing) = (&rule.opening, &rule.closing);
+        Self {
+            opening_before: buffer
+                .unmatched_opening_before(opening, closing, line_number, col)
+                .is_some(),
+            closing_after: buffer
+                .unmatched_closing_after(opening, closing, line_number, col)
+                .is_some(),
+        }
+    }
+}
+
+/// Everything the action is decided from in the parsed buffer, taken up front so that the
+/// buffer doesn't need to stay locked while `is_active` is called, which may query it again
+#[derive(Debug, Clone, PartialEq)]
+pub struct BufferSnapshot {
+    state: State,
+    /// For each rule, only filled in for the rules that could apply to the key
+    unmatched: Vec<Unmatched>,
+}
+
+impl BufferSnapshot {
+    pub fn new(
+        buffer: &ParsedBuffer,
+        line_number: usize,
+        col: usize,
+        key: &Key,
+        rules: &[Rule],
+    ) -> Self {
+        let unmatched = rules
+            .iter()
+            .map(|rule| match key {
+                Key::Char(key) if is_candidate(rule, key) => {
+                    Unmatched::new(buffer, rule, line_number, col)
+                }
+                _ => Unmatched::default(),
+            })
+            .collect();
+
+        Self {
+            state: buffer.state_at(line_number, col),
+            unmatched,
+        }
+    }
+}
+
+struct Context<'a> {
+    snapshot: &'a BufferSnapshot,
+    before: &'a str,
+    after: &'a str,
+}
+
+impl Context<'_> {
+    /// Whether the cursor is preceded by an odd number of backslashes
+    fn is_escaped(&self) -> bool {
+        (self.before.len() - self.before.trim_end_matches('\\').len()) % 2 == 1
+    }
+
+    fn state(&self) -> State {
+        self.snapshot.state
+    }
+}
+
+/// Decides the action for the key typed at the column of the line. `is_active` is called with
+/// the index of a rule in `rules` and a mode, or `None` for the general condition of the rule,
+/// and must return whether the rule applies. It's only called for rules that could apply to
+/// the text around the cursor, and its first error is returned right away
+pub fn autopair_action<E>(
+    snapshot: &BufferSnapshot,
+    line: &str,
+    col: usize,
+    key: &Key,
+    rules: &[Rule],
+    mut is_active: impl FnMut(usize, Option<RuleMode>) -> Result<bool, E>,
+) -> Result<Action, E> {
+    let ctx = Context {
+        snapshot,
+        before: line.get(..col).unwrap_or(""),
+        after: line.get(col..).unwrap_or(""),
+    };
+
+    Ok(match key {
+        Key::Char(key) => on_char(&ctx, key, rules, &mut is_active)?,
+        Key::Backspace => match surrounding_rule(&ctx, rules, RuleMode::Backspace, is_active)? {
+            // ( | ) -> (|)
+            // but not in strings and comments, where the spaces are likely intentional
+            Some((_, true)) => match ctx.state() {
+                State::InString(_)
+                | State::InBlockString(_)
+                | State::InLineComment
+                | State::InBlockComment(_) => Action::Passthrough,
+                _ => Action::DeletePair {
+                    before: 1,
+                    after: 1,
+                },
+            },
+            // (|) -> |
+            Some((rule, false)) => Action::DeletePair {
+                before: rule.opening.len(),
+                after: rule.closing.len(),
+            },
+            None => Action::Passthrough,
+        },
+        Key::Enter => match surrounding_rule(&ctx, rules, RuleMode::Enter, is_active)? {
+            Some((_, trim_spaces)) => Action::NewlineSplit { trim_spaces },
+            None => Action::Passthrough,
+        },
+        // "(|)" -> "( | )"
+        Key::Space => match surrounding_rule(&ctx, rules, RuleMode::Space, is_active)? {
+            Some(_) => Action::ExpandSpaces,
+            None => Action::Passthrough,
+        },
+    })
+}
+
+fn on_char<E>(
+    ctx: &Context,
+    key: &str,
+    rules: &[Rule],
+    is_active: &mut impl FnMut(usize, Option<RuleMode>) -> Result<bool, E>,
+) -> Result<Action, E> {
+    for (idx, rule) in rules.iter().enumerate() {
+        if !is_candidate(rule, key) {
+            continue;
+        }
+        if !is_active(idx, None)? {
+            continue;
+        }
+        let unmatched = ctx.snapshot.unmatched[idx];
+
+        if rule.opening == rule.closing {
+            if !is_active(idx, Some(RuleMode::OpenOrClose))? {
+                return Ok(Action::Passthrough);
+            }
+            return Ok(open_or_close_pair(ctx, rule));
+        }
+
+        let Some(key_idx) = rule.opening.find(key) else {
+            return close_pair(ctx, key, rule, unmatched, || {
+                is_active(idx, Some(RuleMode::Close))
+            });
+        };
+
+        // I.e. user types '"' for line 'r#|', we expand to 'r#""#'
+        // or the pair is "'''", in which case the key_idx is 0 because there's no relevant prefix
+        if key_idx == 0 || ctx.before.ends_with(&rule.opening[..key_idx]) {
+            if !is_active(idx, Some(RuleMode::Open))? {
+                return Ok(Action::Passthrough);
+            }
+            return Ok(open_pair(ctx, key_idx, rule, unmatched));
+        }
+
+        // I.e. for line 'r#"', user types '"' to close the pair
+        if rule.opening.len() > 1 && ctx.before.ends_with(&rule.opening) {
+            return close_pair(ctx, key, rule, unmatched, || {
+                is_active(idx, Some(RuleMode::Close))
+            });
+        }
+    }
+
+    Ok(Action::Passthrough)
+}
+
+fn open_pair(ctx: &Context, key_idx: usize, rule: &Rule, unmatched: Unmatched) -> Action {
+    // \| -> \(|
+    if ctx.is_escaped() {
+        return Action::Passthrough;
+    }
+
+    // |) -> (|)
+    if unmatched.closing_after {
+        return Action::Passthrough;
+    }
+
+    // | -> (|)
+    Action::InsertPair {
+        opening: rule.opening[key_idx..].to_string(),
+        closing: rule.closing.clone(),
+    }
+}
+
+fn close_pair<E>(
+    ctx: &Context,
+    key: &str,
+    rule: &Rule,
+    unmatched: Unmatched,
+    is_active: impl FnOnce() -> Result<bool, E>,
+) -> Result<Action, E> {
+    if !is_active()? {
+        return Ok(Action::Passthrough);
+    }
+
+    let insert = if rule.closing == key {
+        Action::Passthrough
+    } else {
+        Action::Insert {
+            text: rule.closing.clone(),
+        }
+    };
+
+    // ( ( |) -> ( (  )|)
+    if unmatched.opening_before {
+        return Ok(insert);
+    }
+
+    // |) -> )|
+    if ctx.after.starts_with(&rule.closing) {
+        return Ok(Action::SkipOver {
+            len: rule.closing.len(),
+        });
+    }
+    // | ) ->  )|
+    if ctx
+        .after
+        .strip_prefix(' ')
+        .is_some_and(|after| after.starts_with(&rule.closing))
+    {
+        return Ok(Action::SkipOver {
+            len: 1 + rule.closing.len(),
+        });
+    }
+
+    Ok(insert)
+}
+
+fn open_or_close_pair(ctx: &Context, rule: &Rule) -> Action {
+    // \| -> \"|
+    if ctx.is_escaped() {
+        return Action::Passthrough;
+    }
+
+    let pair = &rule.opening;
+
+    // |' -> '|
+    if ctx.after.starts_with(pair.as_str()) {
+        return Action::SkipOver { len: pair.len() };
+    }
+
+    // "foo| -> "foo"|
+    // "it|" -> "it'|"
+    if matches!(ctx.state(), State::InString(_) | State::InBlockString(_)) {
+        return Action::Passthrough;
+    }
+
+    // Multiple character open
+    // '|' -> '''|'''
+    if pair.len() > 1 {
+        let start_overlap = find_overlap(ctx.before, pair);
+        let end_overlap = find_overlap(ctx.after, pair);
+        return Action::InsertPair {
+            opening: pair[start_overlap..].to_string(),
+            closing: pair[..pair.len() - end_overlap].to_string(),
+        };
+    }
+
+    // | -> '|'
+    Action::InsertPair {
+        opening: pair.clone(),
+        closing: pair.clone(),
+    }
+}
+
+/// Finds the first active rule with the opening directly before the cursor and the closing
+/// directly after it. For backspace and enter, a single space on either side of the cursor is
+/// allowed, in which case the second value is true
+fn surrounding_rule<'a, E>(
+    ctx: &Context,
+    rules: &'a [Rule],
+    mode: RuleMode,
+    mut is_active: impl FnMut(usize, Option<RuleMode>) -> Result<bool, E>,
+) -> Result<Option<(&'a Rule, bool)>, E> {
+    let spaced = match (ctx.before.strip_suffix(' '), ctx.after.strip_prefix(' ')) {
+        (Some(before), Some(after)) if matches!(mode, RuleMode::Backspace | RuleMode::Enter) => {
+            Some((before, after))
+        }
+        _ => None,
+    };
+
+    for (idx, rule) in rules.iter().enumerate() {
+        let is_around = |before: &str, after: &str| {
+            before.ends_with(&rule.opening) && after.starts_with(&rule.closing)
+        };
+        let surrounding_space = if spaced.is_some_and(|(before, after)| is_around(before, after)) {
+            true
+        } else if is_around(ctx.before, ctx.after) {
+            false
+        } else {
+            continue;
+        };
+
+        if is_active(idx, None)? && is_active(idx, Some(mode))? {
+            return Ok(Some((rule, surrounding_space)));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules() -> Vec<Rule> {
+        vec![
+            Rule::new("r#\"", "\"#"),
+            Rule::new("'''", "'''"),
+            Rule::new("(", ")"),
+            Rule::new("[", "]"),
+            Rule::new("\"", "\""),
+            Rule::new("'", "'"),
+        ]
+    }
+
+    /// Cursor is at the `|` in the line
+    fn action(filetype: &str, line: &str, key: &str) -> Action {
+        let col = line.find('|').unwrap();
+        let line = line.replacen('|', "", 1);
+        let buffer = ParsedBuffer::parse(filetype, 4, &[&line]).unwrap();
+        let key = key.parse().unwrap();
+        let snapshot = BufferSnapshot::new(&buffer, 0, col, &key, &rules());
+        autopair_action(&snapshot, &line, col, &key, &rules(), |_, _| {
+            Ok::<_, ()>(true)
+        })
+        .unwrap()
+    }
+
+    fn insert_pair(opening: &str, closing: &str) -> Action {
+        Action::InsertPair {
+            opening: opening.to_string(),
+            closing: closing.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_open() {
+        assert_eq!(action("rust", "|", "("), insert_pair("(", ")"));
+        assert_eq!(action("rust", "\\|", "("), Action::Passthrough);
+        assert_eq!(action("rust", "|)", "("), Action::Passthrough);
+        assert_eq!(action("rust", "r#|", "\""), insert_pair("\"", "\"#"));
+    }
+
+    #[test]
+    fn test_close() {
+        assert_eq!(action("rust", "(|)", ")"), Action::SkipOver { len: 1 });
+        assert_eq!(action("rust", "( |)", ")"), Action::SkipOver { len: 1 });
+        assert_eq!(action("rust", "(| )", ")"), Action::SkipOver { len: 2 });
+        assert_eq!(action("rust", "( ( |)", ")"), Action::Passthrough);
+        assert_eq!(
+            action("rust", "r#\"|", "\""),
+            Action::Insert {
+                text: "\"#".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_open_or_close() {
+        assert_eq!(action("rust", "|", "\""), insert_pair("\"", "\""));
+        assert_eq!(action("rust", "\"a|\"", "\""), Action::SkipOver { len: 1 });
+        // Closes the unterminated string
+        assert_eq!(action("rust", "\"a|", "\""), Action::Passthrough);
+        // Inside a string with a different quote
+        assert_eq!(action("rust", "\"it| is\"", "'"), Action::Passthrough);
+        assert_eq!(action("python", "''|", "'"), insert_pair("'", "'''"));
+    }
+
+    #[test]
+    fn test_backspace() {
+        assert_eq!(
+            action("rust", "(|)", "<BS>"),
+            Action::DeletePair {
+                before: 1,
+                after: 1
+            }
+        );
+        assert_eq!(
+            action("rust", "r#\"|\"#", "<BS>"),
+            Action::DeletePair {
+                before: 3,
+                after: 2
+            }
+        );
+        assert_eq!(
+            action("rust", "( | )", "<BS>"),
+            Action::DeletePair {
+                before: 1,
+                after: 1
+            }
+        );
+        // Spaces inside strings are left alone
+        assert_eq!(action("rust", "\"( | )\"", "<BS>"), Action::Passthrough);
+        assert_eq!(action("rust", "a|b", "<BS>"), Action::Passthrough);
+    }
+
+    #[test]
+    fn test_enter_and_space() {
+        assert_eq!(
+            action("rust", "[|]", "<CR>"),
+            Action::NewlineSplit { trim_spaces: false }
+        );
+        assert_eq!(
+            action("rust", "[ | ]", "<CR>"),
+            Action::NewlineSplit { trim_spaces: true }
+        );
+        assert_eq!(action("rust", "[|]", "<Space>"), Action::ExpandSpaces);
+        assert_eq!(action("rust", "[ | ]", "<Space>"), Action::Passthrough);
+    }
+
+    /// Outside of strings and comments, the actions must match the line based rules in
+    /// `mappings/ops.lua`, which remain the default engine
+    #[test]
+    fn test_lua_parity() {
+        let delete_pair = |before, after| Action::DeletePair { before, after };
+        let cases = [
+            ("|", "(", insert_pair("(", ")")),
+            ("a|", "(", insert_pair("(", ")")),
+            ("\\|", "(", Action::Passthrough),
+            ("|)", "(", Action::Passthrough),
+            ("(|)", ")", Action::SkipOver { len: 1 }),
+            ("(| )", ")", Action::SkipOver { len: 2 }),
+            ("( ( |)", ")", Action::Passthrough),
+            ("|a", ")", Action::Passthrough),
+            ("|", "\"", insert_pair("\"", "\"")),
+            ("|\"", "\"", Action::SkipOver { len: 1 }),
+            ("\\|", "\"", Action::Passthrough),
+            ("r#|", "\"", insert_pair("\"", "\"#")),
+            ("(|)", "<BS>", delete_pair(1, 1)),
+            ("( | )", "<BS>", delete_pair(1, 1)),
+            ("r#\"|\"#", "<BS>", delete_pair(3, 2)),
+            ("(|a)", "<BS>", Action::Passthrough),
+            ("[|]", "<CR>", Action::NewlineSplit { trim_spaces: false }),
+            ("[ | ]", "<CR>", Action::NewlineSplit { trim_spaces: true }),
+            ("a|", "<CR>", Action::Passthrough),
+            ("[|]", "<Space>", Action::ExpandSpaces),
+            ("[ | ]", "<Space>", Action::Passthrough),
+        ];
+        for (line, key, expected) in cases {
+            assert_eq!(action("rust", line, key), expected, "{key} at {line}");
+        }
+    }
+
+    #[test]
+    fn test_inactive_rules() {
+        let buffer = ParsedBuffer::parse("rust", 4, &[""]).unwrap();
+        let key = "(".parse().unwrap();
+        let snapshot = BufferSnapshot::new(&buffer, 0, 0, &key, &rules());
+        assert_eq!(
+            autopair_action(&snapshot, "", 0, &key, &rules(), |_, mode| {
+                Ok::<_, ()>(mode.is_none())
+            }),
+            Ok(Action::Passthrough)
+        );
+        assert_eq!(
+            autopair_action(&snapshot, "", 0, &key, &rules(), |idx, _| {
+                Ok::<_, ()>(idx != 2)
+            }),
+            Ok(Action::Passthrough)
+        );
+    }
+
+    #[test]
+    fn test_callback_error() {
+        let buffer = ParsedBuffer::parse("rust", 4, &[""]).unwrap();
+        let key = "\"".parse().unwrap();
+        let snapshot = BufferSnapshot::new(&buffer, 0, 0, &key, &rules());
+        // The error is returned without checking the remaining rules
+        let mut calls = 0;
+        assert_eq!(
+            autopair_action(&snapshot, "", 0, &key, &rules(), |_, _| {
+                calls += 1;
+                Err("callback failed")
+            }),
+            Err("callback failed")
+        );
+        assert_eq!(calls, 1);
+    }
+}
@@ -1,9 +1,132 @@
-use crate::parser::{parse_filetype, supports_filetype, Kind, Match, MatchWithLine, State, Token};
+use std::collections::BTreeMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::OnceLock;
+
+use crate::error::Error;
+use crate::parser::indent::{detect_indent, IndentStyle};
+use crate::parser::{
+    parse_filetype, supports_filetype, Kind, Match, MatchWithLine, OpenSpan, State, Token,
+};
+
+mod context;
+mod depth;
+mod diagnostics;
+mod expression;
+mod folds;
+mod navigation;
+mod parinfer;
+mod range;
+mod reindent;
+mod scope;
+mod simulate;
+mod span;
+mod structural;
+mod surround;
+mod text_object;
+mod tree;
+
+pub use context::*;
+pub use depth::*;
+pub use diagnostics::*;
+pub use expression::*;
+pub use folds::*;
+pub use parinfer::*;
+pub use range::*;
+pub use scope::*;
+pub use simulate::*;
+pub use span::*;
+pub use structural::*;
+pub use surround::*;
+pub use text_object::*;
+pub use tree::*;
+
+/// Applies the matches ending at or before the column to the state at the start of the line
+pub(crate) fn state_after_matches(mut state: State, line_matches: &[Match], col: usize) -> State {
+    for match_ in line_matches
+        .iter()
+        .filter(|match_| match_.col + match_.len() <= col)
+    {
+        state = match (&match_.token, match_.kind) {
+            (Token::Delimiter(open, _), kind) => match (match_.span, kind) {
+                (None, _) => state,
+                (Some(_), Kind::Closing) => state.close_span(),
+                (Some(span), _) => state.open_span(OpenSpan::block(span, open)),
+            },
+            (Token::LineComment(_), _) => State::InLineComment,
+            (Token::InlineSpan(_, _, _) | Token::BlockSpan(_, _, _), Kind::Closing) => {
+                state.close_span()
+            }
+            (_, Kind::Closing) => State::Normal,
+            (Token::String(open), _) => State::InString(open),
+            (Token::BlockString(open, _), _) => State::InBlockString(open),
+            (Token::BlockComment(open, _), _) => State::InBlockComment(open),
+            (Token::InlineSpan(span, open, _), _) => {
+                state.open_span(OpenSpan::inline(span, open))
+            }
+            (Token::BlockSpan(span, open, _), _) => {
+                state.open_span(OpenSpan::block(span, open))
+            }
+        };
+    }
+    state
+}
+
+/// Byte offsets of a line, calculated while parsing so that queries don't need the text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineLayout {
+    pub len: usize,
+    /// Start of the first non-whitespace character, or the length for blank lines
+    pub content_start: usize,
+    /// End of the last non-whitespace character, or 0 for blank lines
+    pub content_end: usize,
+    /// Hash of the text, for checking that text passed to the queries is the parsed text
+    pub hash: u64,
+}
+
+impl LineLayout {
+    pub fn new(line: &str) -> Self {
+        let mut hasher = DefaultHasher::new();
+        line.hash(&mut hasher);
+        Self {
+            len: line.len(),
+            content_start: line.len() - line.trim_start().len(),
+            content_end: line.trim_end().len(),
+            hash: hasher.finish(),
+        }
+    }
+}
+
+/// Text of the lines of the buffer, which may only hold the lines read by the query
+pub trait BufferText {
+    fn line(&self, line_number: usize) -> &str;
+}
+
+impl BufferText for [&str] {
+    fn line(&self, line_number: usize) -> &str {
+        self[line_number]
+    }
+}
+
+impl BufferText for BTreeMap<usize, String> {
+    fn line(&self, line_number: usize) -> &str {
+        &self[&line_number]
+    }
+}
 
 pub struct ParsedBuffer {
     pub matches_by_line: Vec<Vec<Match>>,
     pub state_by_line: Vec<State>,
-    pub indent_levels: Vec<u8>,
+    pub indent_levels: Vec<u16>,
+    pub line_layouts: Vec<LineLayout>,
+    /// Filetype and tab width of the last parse, used for reparsing parts of the buffer
+    pub filetype: String,
+    pub tab_width: u8,
+    /// Version of the buffer (i.e. `b:changedtick`) the parse was last updated for, if provided
+    pub version: Option<u64>,
+    /// Whether to calculate the per token depth next to the stack height
+    pub depth_mode: DepthMode,
+    /// Diagnostics for the whole buffer, calculated on the first query after each parse
+    pub(crate) diagnostics: OnceLock<Vec<Diagnostic>>,
 }
 
 impl ParsedBuffer {
@@ -11,10 +134,25 @@ impl ParsedBuffer {
         supports_filetype(filetype)
     }
 
-    pub fn parse(filetype: &str, tab_width: u8, lines: &[&str]) -> Option<Self> {
-        let mut parsed = parse_filetype(filetype, tab_width, lines, State::Normal)?;
+    pub fn parse(filetype: &str, tab_width: u8, lines: &[&str]) -> Result<Self, Error> {
+        let mut parsed = parse_filetype(filetype, tab_width, lines, State::Normal)
+            .ok_or_else(|| Error::UnsupportedFiletype(filetype.to_string()))?;
+        parsed.filetype = filetype.to_string();
         parsed.calculate_stack_heights(tab_width);
-        Some(parsed)
+        Ok(parsed)
+    }
+
+    /// Returns an error when the version is older than the version of the current parse
+    pub fn check_version(&self, version: Option<u64>) -> Result<(), Error> {
+        match (version, self.version) {
+            (Some(version), Some(current_version)) if version < current_version => {
+                Err(Error::StaleVersion {
+                    version,
+                    current_version,
+                })
+            }
+            _ => Ok(()),
+        }
     }
 
     pub fn reparse_range(
@@ -25,10 +163,20 @@ impl ParsedBuffer {
         start_line: Option<usize>,
         old_end_line: Option<usize>,
         _new_end_line: Option<usize>,
-    ) -> (bool, bool) {
+    ) -> Result<bool, Error> {
         let max_line = self.matches_by_line.len();
-        let start_line = start_line.unwrap_or(0).min(max_line);
-        let old_end_line = old_end_line.unwrap_or(max_line).min(max_line);
+        let start_line = start_line.unwrap_or(0);
+        let old_end_line = old_end_line.unwrap_or(max_line);
+        // The edit doesn't line up with the parsed buffer, so the caller should do a full parse
+        for line in [start_line, old_end_line] {
+            if line > max_line {
+                return Err(Error::LineOutOfRange {
+                    line,
+                    line_count: max_line,
+                });
+            }
+        }
+        let old_end_line = old_end_line.max(start_line);
 
         let initial_state = if start_line > 0 {
             self.state_by_line
@@ -46,12 +194,10 @@ impl ParsedBuffer {
             .cloned()
             .unwrap_or(State::Normal);
 
-        let Some(new) = parse_filetype(filetype, tab_width, lines, initial_state) else {
-            return (false, false);
-        };
+        let new = parse_filetype(filetype, tab_width, lines, initial_state)
+            .ok_or_else(|| Error::UnsupportedFiletype(filetype.to_string()))?;
 
         // Use lines.len() as authoritative length to avoid index mismatch
-        // when start_line is clamped by max_line
         let length = lines.len();
 
         let new_end_state = new
@@ -72,10 +218,16 @@ impl ParsedBuffer {
             start_line..old_end_line.min(self.indent_levels.len()),
             new.indent_levels.into_iter().take(length),
         );
+        self.line_layouts.splice(
+            start_line..old_end_line.min(self.line_layouts.len()),
+            new.line_layouts.into_iter().take(length),
+        );
 
+        self.filetype = filetype.to_string();
+        self.tab_width = tab_width;
         self.calculate_stack_heights(tab_width);
 
-        (true, old_end_state != new_end_state)
+        Ok(old_end_state != new_end_state)
     }
 
     fn calculate_stack_heights(&mut self, tab_width: u8) {
@@ -96,7 +248,7 @@ impl ParsedBuffer {
                 // Closing delimiter
                 else {
                     for (i, (_, opening)) in stack.iter().enumerate().rev() {
-                        if opening.token == match_.token {
+                        if opening.pairs_with(match_) {
                             // Mark all skipped matches as unmatched
                             for (unmatched_line, unmatched_opening) in
                                 stack.splice((i + 1).., vec![])
@@ -140,10 +292,14 @@ impl ParsedBuffer {
         for (line, col) in unmatched_openings.into_iter().rev() {
             self.rematch_by_indent_recursive(line, col, tab_width);
         }
+
+        self.calculate_depths();
+        self.diagnostics.take();
     }
 
     /// Gets the indent level of the line, rounded down to the nearest tab width
-    pub fn rounded_indent_level(&self, line: usize, tab_width: u8) -> u8 {
+    pub fn rounded_indent_level(&self, line: usize, tab_width: u8) -> u16 {
+        let tab_width = tab_width as u16;
         self.indent_levels[line].div_floor(tab_width) * tab_width
     }
 
@@ -151,7 +307,7 @@ impl ParsedBuffer {
     /// where the closing ident level matches the unmatched opening.
     /// Performed recursively until the match cannot be moved further down the stack.
     ///
-    /// ```rust
+    /// ```rust,ignore
     /// if some_example {
     ///     //          ^ unmatched
     ///     if no_closing_on_this {
@@ -159,7 +315,7 @@ impl ParsedBuffer {
     /// }
     /// ```
     /// becomes
-    /// ```rust
+    /// ```rust,ignore
     /// if some_example {
     ///     //  matched ^
     ///     if no_closing_on_this {
@@ -169,10 +325,10 @@ impl ParsedBuffer {
     /// ```
     pub fn rematch_by_indent_recursive(&mut self, line: usize, col: usize, tab_width: u8) {
         let indent_level = self.rounded_indent_level(line, tab_width);
-        let token = self.match_at(line, col).unwrap().token;
+        let unmatched = self.match_at(line, col).unwrap();
         let stack_height = self.stack_height_at(line, col);
 
-        // Find the first matched opening that has the same stack height and token
+        // Find the first matched opening that has the same stack height, token and name
         let matched_pair = self
             .iter_from(line, col + 1)
             .take_while(|match_| {
@@ -181,7 +337,7 @@ impl ParsedBuffer {
                     .map(|sh| sh >= stack_height.saturating_add(1))
                     .unwrap_or(true)
             })
-            .filter(|match_| match_.token == token.clone())
+            .filter(|match_| match_.token == unmatched.token && match_.name == unmatched.name)
             .flat_map(|match_| self.match_pair(match_.line, match_.col))
             .find(|(open, close)| {
                 self.rounded_indent_level(close.line, tab_width) == indent_level
@@ -256,11 +412,102 @@ impl ParsedBuffer {
         }
     }
 
+    pub fn line_count(&self) -> usize {
+        self.matches_by_line.len()
+    }
+
+    /// Length of the line in bytes, or 0 if the line doesn't exist
+    pub fn line_len(&self, line_number: usize) -> usize {
+        self.line_layout(line_number).len
+    }
+
+    /// Layout of the line, or an empty layout if the line doesn't exist
+    pub fn line_layout(&self, line_number: usize) -> LineLayout {
+        self.line_layouts
+            .get(line_number)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Whether the line is empty or only contains whitespace
+    pub fn is_blank(&self, line_number: usize) -> bool {
+        self.line_layout(line_number).content_end == 0
+    }
+
+    pub fn validate_line(&self, line_number: usize) -> Result<(), Error> {
+        if line_number >= self.line_count() {
+            return Err(Error::LineOutOfRange {
+                line: line_number,
+                line_count: self.line_count(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that the position is inside the buffer, allowing the column just past the end
+    /// of the line (i.e. the cursor in insert mode)
+    pub fn validate_position(&self, line_number: usize, col: usize) -> Result<(), Error> {
+        self.validate_line(line_number)?;
+        let line_len = self.line_len(line_number);
+        if col > line_len {
+            return Err(Error::ColOutOfRange {
+                line: line_number,
+                col,
+                line_len,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that the text of the buffer, passed to the queries that need more than the layout
+    /// of the lines, is the text of the last parse
+    pub fn validate_text(&self, lines: &[&str]) -> Result<(), Error> {
+        if lines.len() != self.line_count() {
+            return Err(Error::StaleText);
+        }
+        (lines.iter().enumerate()).try_for_each(|(line_number, line)| {
+            self.validate_line_text(line_number, line)
+        })
+    }
+
+    /// Checks that the text of the line is the text of the last parse
+    pub fn validate_line_text(&self, line_number: usize, line: &str) -> Result<(), Error> {
+        match self.line_layouts.get(line_number) {
+            Some(layout) if *layout == LineLayout::new(line) => Ok(()),
+            _ => Err(Error::StaleText),
+        }
+    }
+
+    /// Checks that the exclusive line range fits inside the buffer
+    pub fn validate_line_range(&self, start_line: usize, end_line: usize) -> Result<(), Error> {
+        for line in [start_line, end_line] {
+            if line > self.line_count() {
+                return Err(Error::LineOutOfRange {
+                    line,
+                    line_count: self.line_count(),
+                });
+            }
+        }
+        Ok(())
+    }
+
     pub fn line_matches(&self, line_number: usize) -> Option<Vec<Match>> {
         self.matches_by_line.get(line_number).cloned()
     }
 
-    pub fn get_indent_levels(&self, start_line: usize, end_line: usize) -> Vec<u8> {
+    /// Infers the indentation style of the buffer, ignoring lines that continue a block
+    /// comment or block string
+    pub fn detect_indent(&self, lines: &[&str]) -> IndentStyle {
+        detect_indent(lines, |line| {
+            line > 0
+                && matches!(
+                    self.state_by_line[line - 1],
+                    State::InBlockComment(_) | State::InBlockString(_)
+                )
+        })
+    }
+
+    pub fn get_indent_levels(&self, start_line: usize, end_line: usize) -> Vec<u16> {
         let start_idx = start_line.min(self.indent_levels.len());
         let end_idx = end_line.min(self.indent_levels.len());
 
@@ -276,7 +523,7 @@ impl ParsedBuffer {
         line_number: usize,
         col: usize,
     ) -> impl Iterator<Item = MatchWithLine> + '_ {
-        self.matches_by_line[line_number.max(0)..]
+        self.matches_by_line[line_number..]
             .iter()
             .enumerate()
             .flat_map(move |(offset, matches)| {
@@ -306,48 +553,18 @@ impl ParsedBuffer {
             })
     }
 
-    pub fn span_at(&self, line_number: usize, col: usize) -> Option<String> {
-        let line_matches = self.matches_by_line.get(line_number)?;
-        let line_state = self.state_by_line.get(line_number)?;
-
-        // Look for spans starting in the current line before the desired column
-
-        let matching_span = line_matches
-            .iter()
-            .rev()
-            // Get all opening matches before the cursor on the current line
-            .filter(|match_| match_.kind == Kind::Opening && match_.col <= col)
-            // Find closing match on the same line or no match (overflows to next line)
-            .find_map(|opening| {
-                match opening.token {
-                    Token::InlineSpan(span, _, _) | Token::BlockSpan(span, _, _) => {
-                        let closing = line_matches.iter().find(|closing| {
-                            closing.kind == Kind::Closing
-                                && closing.col > opening.col
-                                && closing.token == opening.token
-                                && closing.stack_height == opening.stack_height
-                        });
-
-                        match closing {
-                            // Ends before desired column
-                            Some(closing) if closing.col < col => None,
-                            // Extends to end of line or found closing after desired column
-                            _ => Some(span),
-                        }
-                    }
-                    _ => None,
-                }
-            });
-
-        if let Some(span) = matching_span {
-            return Some(span.to_string());
-        }
+    /// Gets the parser state at the position from the state at the end of the previous line
+    /// and the matches on the line ending at or before the position
+    pub fn state_at(&self, line_number: usize, col: usize) -> State {
+        let state = line_number
+            .checked_sub(1)
+            .and_then(|line| self.state_by_line.get(line))
+            .copied()
+            .unwrap_or(State::Normal);
 
-        // Look for spans that started before the current line
-        match line_state {
-            // TODO: check that the span doesn't end before the cursor
-            State::InInlineSpan(span) | State::InBlockSpan(span) => Some(span.to_string()),
-            _ => None,
+        match self.matches_by_line.get(line_number) {
+            Some(line_matches) => state_after_matches(state, line_matches, col),
+            None => state,
         }
     }
 
@@ -392,6 +609,7 @@ impl ParsedBuffer {
                         .find(|match_| {
                             (line_number != matches_line_number || match_.col > match_at_pos.col)
                                 && match_at_pos.token == match_.token
+                                && match_at_pos.name == match_.name
                                 && match_at_pos.stack_height == match_.stack_height
                         })
                         .map(|match_| match_.with_line(matches_line_number))
@@ -412,6 +630,7 @@ impl ParsedBuffer {
                         .find(|match_| {
                             (line_number != matches_line_number || match_.col < match_at_pos.col)
                                 && match_at_pos.token == match_.token
+                                && match_at_pos.name == match_.name
                                 && match_at_pos.stack_height == match_.stack_height
                         })
                         .map(|match_| match_.with_line(matches_line_number))
@@ -606,7 +825,7 @@ impl ParsedBuffer {
             if match_.kind == Kind::Closing
                 && match_.token.opening() == opening
                 && match_.token.closing() == Some(closing)
-                && match_.stack_height == None
+                && match_.stack_height.is_none()
                 && current_stack_height == lowest_stack_height
             {
                 return Some(match_);
@@ -672,6 +891,71 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_reparse_range_errors() {
+        let mut buffer = parse("rust", &["{", "}"]);
+        assert_eq!(
+            buffer.reparse_range("rust", 4, &["{"], Some(3), Some(4), Some(4)),
+            Err(Error::LineOutOfRange {
+                line: 3,
+                line_count: 2
+            })
+        );
+        assert_eq!(
+            buffer.reparse_range("foo", 4, &["{"], Some(0), Some(1), Some(1)),
+            Err(Error::UnsupportedFiletype("foo".to_string()))
+        );
+        assert_eq!(
+            buffer.reparse_range("rust", 4, &["/*"], Some(0), Some(1), Some(1)),
+            Ok(true)
+        );
+        assert_eq!(
+            buffer.line_layouts,
+            vec![LineLayout::new("/*"), LineLayout::new("}")]
+        );
+        assert_eq!(buffer.validate_text(&["/*", "}"]), Ok(()));
+        assert_eq!(buffer.validate_text(&["{", "}"]), Err(Error::StaleText));
+        assert_eq!(buffer.validate_text(&["/*"]), Err(Error::StaleText));
+        // Edits which keep the length of the line
+        assert_eq!(buffer.validate_text(&["//", "}"]), Err(Error::StaleText));
+        assert_eq!(buffer.validate_line_text(1, "}"), Ok(()));
+        assert_eq!(buffer.validate_line_text(1, ")"), Err(Error::StaleText));
+        assert_eq!(buffer.validate_line_text(2, ""), Err(Error::StaleText));
+    }
+
+    #[test]
+    fn test_reparse_inside_typst_block() {
+        // The brackets nested in the content block are counted in the state, so reparsing
+        // from the middle of the block pairs like the full parse
+        let lines = ["#f[", "[a", "b]", "]", "*c*"];
+        let mut buffer = parse("typst", &lines);
+        assert_eq!(
+            buffer.reparse_range("typst", 4, &["b ]"], Some(2), Some(3), Some(3)),
+            Ok(false)
+        );
+
+        let full = parse("typst", &["#f[", "[a", "b ]", "]", "*c*"]);
+        assert_eq!(buffer.matches_by_line, full.matches_by_line);
+        assert_eq!(buffer.state_by_line, full.state_by_line);
+    }
+
+    #[test]
+    fn test_check_version() {
+        let mut buffer = parse("rust", &["{", "}"]);
+        assert_eq!(buffer.check_version(Some(1)), Ok(()));
+
+        buffer.version = Some(5);
+        assert_eq!(buffer.check_version(None), Ok(()));
+        assert_eq!(buffer.check_version(Some(5)), Ok(()));
+        assert_eq!(
+            buffer.check_version(Some(4)),
+            Err(Error::StaleVersion {
+                version: 4,
+                current_version: 5
+            })
+        );
+    }
+
     #[test]
     fn test_rebalanced_matching() {
         let buffer = parse("rust", &["{", "\t{", "\t", "}"]);
@@ -0,0 +1,87 @@
+use mlua::IntoLua;
+
+use super::ParsedBuffer;
+use crate::parser::{MatchWithLine, State, Token};
+
+/// Lexical context at a position, for deciding on behavior without treesitter
+#[derive(Debug, Clone, PartialEq)]
+pub struct Context {
+    /// Whether the position is in code, a string, a comment or a span
+    pub state: State,
+    /// Delimiter pairs containing the position, from the outermost to the innermost
+    pub pairs: Vec<(MatchWithLine, MatchWithLine)>,
+}
+
+impl IntoLua for Context {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+
+        table.set("state", self.state)?;
+        let pairs = self
+            .pairs
+            .into_iter()
+            .map(|(opening, closing)| vec![opening, closing]);
+        table.set("pairs", lua.create_sequence_from(pairs)?)?;
+
+        (&table).into_lua(lua)
+    }
+}
+
+impl ParsedBuffer {
+    /// Gets the lexical context between the characters at `col - 1` and `col`, i.e. at the
+    /// cursor in insert mode. Only pairs which the position is strictly inside of are included,
+    /// so `|()` and `()|` aren't inside of the pair, while `(|)` is
+    pub fn context_at(&self, line_number: usize, col: usize) -> Context {
+        let mut pairs = self
+            .enclosing_pairs(line_number, col)
+            .filter(|(opening, closing)| {
+                matches!(opening.token, Token::Delimiter(_, _))
+                    && (opening.line, opening.col + opening.len()) <= (line_number, col)
+                    && (line_number, col) <= (closing.line, closing.col)
+            })
+            .collect::<Vec<_>>();
+        pairs.reverse();
+
+        Context {
+            state: self.state_at(line_number, col),
+            pairs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn openings(context: &Context) -> Vec<(usize, usize)> {
+        context
+            .pairs
+            .iter()
+            .map(|(opening, _)| (opening.line, opening.col))
+            .collect()
+    }
+
+    #[test]
+    fn test_context_at() {
+        let lines = ["fn a() {", "  b(\"(c\", [d]) // e", "}"];
+        let buffer = ParsedBuffer::parse("rust", 4, &lines).unwrap();
+
+        let context = buffer.context_at(1, 12);
+        assert_eq!(context.state, State::Normal);
+        assert_eq!(openings(&context), vec![(0, 7), (1, 3), (1, 10)]);
+
+        // Strings don't count as delimiter pairs
+        let context = buffer.context_at(1, 6);
+        assert_eq!(context.state, State::InBlockString("\""));
+        assert_eq!(openings(&context), vec![(0, 7), (1, 3)]);
+
+        let context = buffer.context_at(1, 17);
+        assert_eq!(context.state, State::InLineComment);
+        assert_eq!(openings(&context), vec![(0, 7)]);
+
+        // Positions before the opening and after the closing are outside of the pair
+        assert_eq!(openings(&buffer.context_at(1, 3)), vec![(0, 7)]);
+        assert_eq!(openings(&buffer.context_at(1, 14)), vec![(0, 7)]);
+        assert_eq!(openings(&buffer.context_at(1, 13)), vec![(0, 7), (1, 3)]);
+    }
+}
@@ -0,0 +1,119 @@
+use std::str::FromStr;
+
+use super::ParsedBuffer;
+use crate::error::Error;
+use crate::parser::{Kind, Token};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DepthMode {
+    /// Only the stack height is calculated, shared between all pairs
+    #[default]
+    Shared,
+    /// The depth of each match is also calculated, counting only the pairs with the same
+    /// token, so that e.g. `{}` nested in `()` starts again from 0
+    PerType,
+}
+
+impl FromStr for DepthMode {
+    type Err = Error;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "shared" => Ok(DepthMode::Shared),
+            "per_type" => Ok(DepthMode::PerType),
+            _ => Err(Error::InvalidArgument {
+                name: "depth_mode",
+                value: mode.to_string(),
+            }),
+        }
+    }
+}
+
+impl ParsedBuffer {
+    /// Sets the depth mode, recalculating the depths when it changed
+    pub fn set_depth_mode(&mut self, depth_mode: DepthMode) {
+        if self.depth_mode != depth_mode {
+            self.depth_mode = depth_mode;
+            self.calculate_depths();
+        }
+    }
+
+    /// Calculates the per token depth of the matched pairs, following the pairs found by
+    /// `calculate_stack_heights`. Clears the depths when using `DepthMode::Shared`
+    pub(super) fn calculate_depths(&mut self) {
+        let per_type = self.depth_mode == DepthMode::PerType;
+        // Matched openings which haven't been closed yet, with their stack height
+        let mut stack: Vec<(Token, usize)> = vec![];
+
+        for match_ in self.matches_by_line.iter_mut().flatten() {
+            match_.depth = None;
+            let Some(stack_height) = match_.stack_height.filter(|_| per_type) else {
+                continue;
+            };
+
+            match match_.kind {
+                Kind::Opening => {
+                    match_.depth = Some(depth_of(&stack, &match_.token));
+                    stack.push((match_.token.clone(), stack_height));
+                }
+                Kind::Closing => {
+                    if let Some(i) = stack.iter().rposition(|(token, height)| {
+                        *token == match_.token && *height == stack_height
+                    }) {
+                        stack.truncate(i);
+                        match_.depth = Some(depth_of(&stack, &match_.token));
+                    }
+                }
+                Kind::NonPair => {}
+            }
+        }
+    }
+}
+
+/// Number of openings on the stack with the same token
+fn depth_of(stack: &[(Token, usize)], token: &Token) -> usize {
+    stack.iter().filter(|(opening, _)| opening == token).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn depths(buffer: &ParsedBuffer) -> Vec<Option<usize>> {
+        buffer
+            .matches_by_line
+            .iter()
+            .flatten()
+            .map(|match_| match_.depth)
+            .collect()
+    }
+
+    #[test]
+    fn test_per_type_depth() {
+        let mut buffer = ParsedBuffer::parse("rust", 4, &["{ ( { [] ( ) } ) } ]"]).unwrap();
+        assert_eq!(depths(&buffer), vec![None; 11]);
+
+        buffer.set_depth_mode(DepthMode::PerType);
+        assert_eq!(
+            depths(&buffer),
+            vec![
+                Some(0),
+                Some(0),
+                Some(1),
+                Some(0),
+                Some(0),
+                Some(1),
+                Some(1),
+                Some(1),
+                Some(0),
+                Some(0),
+                None
+            ]
+        );
+        // Stack heights are left untouched
+        assert_eq!(buffer.matches_by_line[0][2].stack_height, Some(2));
+
+        buffer.set_depth_mode(DepthMode::Shared);
+        assert_eq!(depths(&buffer), vec![None; 11]);
+    }
+}
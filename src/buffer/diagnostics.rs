@@ -0,0 +1,589 @@
+use std::collections::HashSet;
+
+use mlua::IntoLua;
+
+use super::ParsedBuffer;
+use crate::parser::{Kind, MatchWithLine, State, Token};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiagnosticKind {
+    /// Opening delimiter without a closing delimiter
+    UnmatchedOpening,
+    /// Closing delimiter without an opening delimiter
+    UnmatchedClosing,
+    /// Closing delimiter that closes a different type of opening delimiter, i.e. `( ]`
+    MismatchedClosing,
+    /// String that isn't closed before the end of the line
+    UnterminatedString,
+    /// Block comment that isn't closed before the end of the buffer
+    UnterminatedBlockComment,
+    /// Block string that isn't closed before the end of the buffer
+    UnterminatedBlockString,
+}
+
+impl DiagnosticKind {
+    pub fn code(&self) -> &'static str {
+        match self {
+            DiagnosticKind::UnmatchedOpening => "unmatched_opening",
+            DiagnosticKind::UnmatchedClosing => "unmatched_closing",
+            DiagnosticKind::MismatchedClosing => "mismatched_closing",
+            DiagnosticKind::UnterminatedString => "unterminated_string",
+            DiagnosticKind::UnterminatedBlockComment => "unterminated_block_comment",
+            DiagnosticKind::UnterminatedBlockString => "unterminated_block_string",
+        }
+    }
+}
+
+/// Suggested edit for resolving a diagnostic. Lines and columns are 0-indexed
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fix {
+    /// Insert the text at the position
+    Insert {
+        line: usize,
+        col: usize,
+        text: &'static str,
+    },
+    /// Insert a new line after the line, containing the text indented by `indent` columns
+    InsertLineAfter {
+        line: usize,
+        indent: usize,
+        text: &'static str,
+    },
+    /// Delete the text in the range
+    Delete {
+        line: usize,
+        col: usize,
+        end_col: usize,
+    },
+    /// Replace the text in the range
+    Replace {
+        line: usize,
+        col: usize,
+        end_col: usize,
+        text: &'static str,
+    },
+}
+
+impl Fix {
+    fn describe(&self) -> String {
+        match self {
+            Fix::Insert { line, col, text } => {
+                format!("insert `{text}` at line {}, col {}", line + 1, col + 1)
+            }
+            Fix::InsertLineAfter { line, text, .. } => {
+                format!("insert `{text}` after line {}", line + 1)
+            }
+            Fix::Delete { .. } => "remove it".to_string(),
+            Fix::Replace { text, .. } => format!("replace with `{text}`"),
+        }
+    }
+}
+
+impl IntoLua for Fix {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+
+        match self {
+            Fix::Insert { line, col, text } => {
+                table.set("kind", "insert")?;
+                table.set("line", line)?;
+                table.set("col", col)?;
+                table.set("text", text)?;
+            }
+            Fix::InsertLineAfter { line, indent, text } => {
+                table.set("kind", "insert_line_after")?;
+                table.set("line", line)?;
+                table.set("indent", indent)?;
+                table.set("text", text)?;
+            }
+            Fix::Delete { line, col, end_col } => {
+                table.set("kind", "delete")?;
+                table.set("line", line)?;
+                table.set("col", col)?;
+                table.set("end_col", end_col)?;
+            }
+            Fix::Replace {
+                line,
+                col,
+                end_col,
+                text,
+            } => {
+                table.set("kind", "replace")?;
+                table.set("line", line)?;
+                table.set("col", col)?;
+                table.set("end_col", end_col)?;
+                table.set("text", text)?;
+            }
+        }
+
+        (&table).into_lua(lua)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub line: usize,
+    pub col: usize,
+    pub end_col: usize,
+    pub message: String,
+    /// Suggested edit, or `None` when the right edit can't be known, e.g. for LaTeX
+    /// environments with different names
+    pub fix: Option<Fix>,
+}
+
+impl Diagnostic {
+    fn new(kind: DiagnosticKind, match_: &MatchWithLine, problem: String, fix: Fix) -> Self {
+        Self {
+            message: format!("{problem}, {}", fix.describe()),
+            fix: Some(fix),
+            ..Self::without_fix(kind, match_, problem)
+        }
+    }
+
+    fn without_fix(kind: DiagnosticKind, match_: &MatchWithLine, problem: String) -> Self {
+        Self {
+            kind,
+            line: match_.line,
+            col: match_.col,
+            end_col: match_.col + match_.len(),
+            message: problem,
+            fix: None,
+        }
+    }
+}
+
+/// Converts to the shape expected by `vim.diagnostic.set`
+impl IntoLua for Diagnostic {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+
+        table.set("lnum", self.line)?;
+        table.set("end_lnum", self.line)?;
+        table.set("col", self.col)?;
+        table.set("end_col", self.end_col)?;
+        // vim.diagnostic.severity.ERROR
+        table.set("severity", 1)?;
+        table.set("message", self.message)?;
+        table.set("source", "blink.pairs")?;
+        table.set("code", self.kind.code())?;
+
+        let user_data = lua.create_table()?;
+        user_data.set("fix", self.fix)?;
+        table.set("user_data", user_data)?;
+
+        (&table).into_lua(lua)
+    }
+}
+
+impl ParsedBuffer {
+    /// Gets the problems with unbalanced delimiters, strings and comments that start in the
+    /// line range (exclusive), sorted by position. The diagnostics for the whole buffer are
+    /// calculated once per parse, as unbalanced delimiters depend on the lines before the range
+    pub fn diagnostics(&self, start_line: usize, end_line: usize) -> Vec<Diagnostic> {
+        let diagnostics = self.diagnostics.get_or_init(|| {
+            let mut diagnostics = self.delimiter_diagnostics();
+            diagnostics.extend(self.string_diagnostics());
+            diagnostics.extend(self.block_diagnostics());
+            diagnostics.sort_by_key(|diagnostic| (diagnostic.line, diagnostic.col));
+            diagnostics
+        });
+
+        let start = diagnostics.partition_point(|diagnostic| diagnostic.line < start_line);
+        let end = diagnostics.partition_point(|diagnostic| diagnostic.line < end_line);
+        diagnostics[start..end.max(start)].to_vec()
+    }
+
+    fn delimiter_diagnostics(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        // Unmatched openings which have been reported as part of a type mismatch
+        let mut mismatched_openings = HashSet::new();
+        let mut stack: Vec<MatchWithLine> = vec![];
+
+        for match_ in self
+            .iter_from(0, 0)
+            .filter(|match_| matches!(match_.token, Token::Delimiter(_, _)))
+        {
+            match match_.kind {
+                Kind::Opening => stack.push(match_),
+                Kind::Closing if match_.stack_height.is_some() => {
+                    // Pop until we reach the matching opening, any unmatched openings in
+                    // between are reported separately
+                    while let Some(opening) = stack.pop() {
+                        if opening.pairs_with(&match_)
+                            && opening.stack_height == match_.stack_height
+                        {
+                            break;
+                        }
+                    }
+                }
+                Kind::Closing => {
+                    let closing = match_.token.closing().unwrap_or_default();
+                    match stack.last() {
+                        // \begin{a} \end{b}, where only the names differ
+                        Some(opening)
+                            if opening.stack_height.is_none()
+                                && opening.token == match_.token
+                                && !opening.pairs_with(&match_) =>
+                        {
+                            let problem = format!(
+                                "mismatched `{closing}`, its name differs from the `{}` on line {}",
+                                opening.token.opening(),
+                                opening.line + 1
+                            );
+                            mismatched_openings.insert((opening.line, opening.col));
+                            stack.pop();
+                            diagnostics.push(Diagnostic::without_fix(
+                                DiagnosticKind::MismatchedClosing,
+                                &match_,
+                                problem,
+                            ));
+                        }
+                        // ( ]
+                        Some(opening)
+                            if opening.stack_height.is_none() && opening.token != match_.token =>
+                        {
+                            let expected = opening.token.closing().unwrap_or_default();
+                            mismatched_openings.insert((opening.line, opening.col));
+                            stack.pop();
+
+                            diagnostics.push(Diagnostic::new(
+                                DiagnosticKind::MismatchedClosing,
+                                &match_,
+                                format!("mismatched `{closing}`, expected `{expected}`"),
+                                Fix::Replace {
+                                    line: match_.line,
+                                    col: match_.col,
+                                    end_col: match_.col + match_.len(),
+                                    text: expected,
+                                },
+                            ));
+                        }
+                        _ => diagnostics.push(Diagnostic::new(
+                            DiagnosticKind::UnmatchedClosing,
+                            &match_,
+                            format!("unexpected `{closing}`"),
+                            Fix::Delete {
+                                line: match_.line,
+                                col: match_.col,
+                                end_col: match_.col + match_.len(),
+                            },
+                        )),
+                    }
+                }
+                Kind::NonPair => {}
+            }
+        }
+
+        for opening in self.iter_from(0, 0).filter(|match_| {
+            matches!(match_.token, Token::Delimiter(_, _))
+                && match_.kind == Kind::Opening
+                && match_.stack_height.is_none()
+                && !mismatched_openings.contains(&(match_.line, match_.col))
+        }) {
+            let closing = opening.token.closing().unwrap_or_default();
+            diagnostics.push(Diagnostic::new(
+                DiagnosticKind::UnmatchedOpening,
+                &opening,
+                format!("unclosed `{}`", opening.token.opening()),
+                self.closing_fix(&opening, closing),
+            ));
+        }
+
+        diagnostics
+    }
+
+    /// Uses the indentation to guess where the closing delimiter for the unmatched opening
+    /// belongs. When the following lines are indented further than the opening line, the
+    /// closing goes on a new line after the last of them. Otherwise, it goes at the end of the
+    /// opening line
+    fn closing_fix(&self, opening: &MatchWithLine, closing: &'static str) -> Fix {
+        let indent = self.indent_levels[opening.line] as usize;
+
+        let last_nested_line = (opening.line + 1..self.line_count())
+            .take_while(|&line| self.is_blank(line) || self.indent_levels[line] as usize > indent)
+            .filter(|&line| !self.is_blank(line))
+            .last();
+
+        match last_nested_line {
+            Some(line) => Fix::InsertLineAfter {
+                line,
+                indent,
+                text: closing,
+            },
+            None => Fix::Insert {
+                line: opening.line,
+                col: self.end_of_code(opening.line),
+                text: closing,
+            },
+        }
+    }
+
+    /// Column at which the code on the line ends, excluding any trailing line comment
+    pub(super) fn end_of_code(&self, line: usize) -> usize {
+        self.matches_by_line[line]
+            .iter()
+            .find(|match_| matches!(match_.token, Token::LineComment(_)))
+            .map(|comment| comment.col)
+            .unwrap_or(self.line_len(line))
+    }
+
+    fn string_diagnostics(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+
+        for (line, matches) in self.matches_by_line.iter().enumerate() {
+            let mut open_string = None;
+            for match_ in matches {
+                match (&match_.token, match_.kind) {
+                    (Token::String(_), Kind::Opening) => open_string = Some(match_),
+                    (Token::String(_), Kind::Closing) => open_string = None,
+                    _ => {}
+                }
+            }
+
+            if let Some(opening) = open_string {
+                let delim = opening.token.opening();
+                diagnostics.push(Diagnostic::new(
+                    DiagnosticKind::UnterminatedString,
+                    &opening.with_line(line),
+                    "unterminated string".to_string(),
+                    Fix::Insert {
+                        line,
+                        col: self.line_len(line),
+                        text: delim,
+                    },
+                ));
+            }
+        }
+
+        diagnostics
+    }
+
+    fn block_diagnostics(&self) -> Vec<Diagnostic> {
+        let last_line = self.line_count().saturating_sub(1);
+        let (kind, problem) = match self.state_by_line.last() {
+            Some(State::InBlockComment(_)) => (
+                DiagnosticKind::UnterminatedBlockComment,
+                "block comment is not closed",
+            ),
+            Some(State::InBlockString(_)) => (
+                DiagnosticKind::UnterminatedBlockString,
+                "block string is not closed",
+            ),
+            _ => return vec![],
+        };
+
+        // The last opening is the one that was never closed
+        let Some(opening) = self.iter_to(last_line, usize::MAX).find(|match_| {
+            match_.kind == Kind::Opening
+                && matches!(
+                    (kind, &match_.token),
+                    (
+                        DiagnosticKind::UnterminatedBlockComment,
+                        Token::BlockComment(_, _)
+                    ) | (
+                        DiagnosticKind::UnterminatedBlockString,
+                        Token::BlockString(_, _)
+                    )
+                )
+        }) else {
+            return vec![];
+        };
+
+        let closing = opening.token.closing().unwrap_or_default();
+        vec![Diagnostic::new(
+            kind,
+            &opening,
+            problem.to_string(),
+            Fix::Insert {
+                line: last_line,
+                col: self.line_len(last_line),
+                text: closing,
+            },
+        )]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn diagnostics(filetype: &str, lines: &[&str]) -> Vec<(DiagnosticKind, usize, usize, Fix)> {
+        ParsedBuffer::parse(filetype, 4, lines)
+            .unwrap()
+            .diagnostics(0, lines.len())
+            .into_iter()
+            .map(|d| (d.kind, d.line, d.col, d.fix.unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_balanced() {
+        assert_eq!(
+            diagnostics("rust", &["fn main() {", "    foo(\"(\");", "}"]),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_unmatched_opening_uses_indent() {
+        assert_eq!(
+            diagnostics(
+                "rust",
+                &[
+                    "fn main() {",
+                    "    if x {",
+                    "        a();",
+                    "",
+                    "    b();",
+                    "}"
+                ]
+            ),
+            vec![(
+                DiagnosticKind::UnmatchedOpening,
+                1,
+                9,
+                Fix::InsertLineAfter {
+                    line: 2,
+                    indent: 4,
+                    text: "}"
+                }
+            )]
+        );
+
+        assert_eq!(
+            diagnostics("rust", &["foo(a, b // comment", "bar();"]),
+            vec![(
+                DiagnosticKind::UnmatchedOpening,
+                0,
+                3,
+                Fix::Insert {
+                    line: 0,
+                    col: 9,
+                    text: ")"
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_unmatched_closing() {
+        assert_eq!(
+            diagnostics("rust", &["foo());"]),
+            vec![(
+                DiagnosticKind::UnmatchedClosing,
+                0,
+                5,
+                Fix::Delete {
+                    line: 0,
+                    col: 5,
+                    end_col: 6
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_mismatched_closing() {
+        assert_eq!(
+            diagnostics("rust", &["foo(a];"]),
+            vec![(
+                DiagnosticKind::MismatchedClosing,
+                0,
+                5,
+                Fix::Replace {
+                    line: 0,
+                    col: 5,
+                    end_col: 6,
+                    text: ")"
+                }
+            )]
+        );
+
+        // Environments with different names don't pair, but the name to use isn't known
+        let lines = &["\\begin{a}", "\\end{b}"];
+        let diagnostics = ParsedBuffer::parse("tex", 4, lines)
+            .unwrap()
+            .diagnostics(0, lines.len());
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic {
+                kind: DiagnosticKind::MismatchedClosing,
+                line: 1,
+                col: 0,
+                end_col: 4,
+                message: "mismatched `\\end`, its name differs from the `\\begin` on line 1"
+                    .to_string(),
+                fix: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_string() {
+        assert_eq!(
+            diagnostics("python", &["x = 'abc", "y = 1"]),
+            vec![(
+                DiagnosticKind::UnterminatedString,
+                0,
+                4,
+                Fix::Insert {
+                    line: 0,
+                    col: 8,
+                    text: "'"
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_block() {
+        assert_eq!(
+            diagnostics("c", &["/* a */", "/* b", "c"]),
+            vec![(
+                DiagnosticKind::UnterminatedBlockComment,
+                1,
+                0,
+                Fix::Insert {
+                    line: 2,
+                    col: 1,
+                    text: "*/"
+                }
+            )]
+        );
+
+        assert_eq!(
+            diagnostics("python", &["x = \"\"\"", "abc"]),
+            vec![(
+                DiagnosticKind::UnterminatedBlockString,
+                0,
+                4,
+                Fix::Insert {
+                    line: 1,
+                    col: 3,
+                    text: "\"\"\""
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_range() {
+        let buffer = ParsedBuffer::parse("rust", 4, &["(", "]", ")"]).unwrap();
+        assert_eq!(buffer.diagnostics(0, 1), vec![]);
+        assert_eq!(buffer.diagnostics(1, 2).len(), 1);
+        assert_eq!(buffer.diagnostics(3, 1), vec![]);
+    }
+
+    #[test]
+    fn test_cache_cleared_on_reparse() {
+        let mut buffer = ParsedBuffer::parse("rust", 4, &["(", "]", ")"]).unwrap();
+        assert_eq!(buffer.diagnostics(0, 3).len(), 1);
+
+        buffer
+            .reparse_range("rust", 4, &[""], Some(1), Some(2), Some(2))
+            .unwrap();
+        assert_eq!(buffer.diagnostics(0, 3), vec![]);
+    }
+}
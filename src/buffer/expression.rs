@@ -0,0 +1,270 @@
+use mlua::IntoLua;
+
+use super::ParsedBuffer;
+use crate::parser::{Kind, Token};
+
+/// Position in the buffer, 0-indexed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Position {
+    pub fn new(line: usize, col: usize) -> Self {
+        Self { line, col }
+    }
+}
+
+impl IntoLua for Position {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+
+        table.set("line", self.line)?;
+        table.set("col", self.col)?;
+
+        (&table).into_lua(lua)
+    }
+}
+
+/// Separators between the parts of an expression, i.e. `foo.bar` or `Foo::bar`
+const ACCESSORS: [&str; 4] = [".", "::", "->", "?."];
+
+fn is_word_char(char: char) -> bool {
+    char.is_alphanumeric() || char == '_'
+}
+
+/// Whether the token may directly follow a part of an expression, i.e. calls `foo(bar)`,
+/// indexing `foo[0]` and string prefixes or calls `f"bar"`
+fn is_trailing_token(token: &Token) -> bool {
+    match token {
+        Token::Delimiter(opening, _) => matches!(*opening, "(" | "["),
+        Token::String(_) | Token::BlockString(_, _) => true,
+        _ => false,
+    }
+}
+
+/// Whether the token may start an expression, i.e. `(a + b)`, `[1, 2]` or `"foo"`
+fn is_expression_token(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Delimiter(_, _) | Token::String(_) | Token::BlockString(_, _)
+    )
+}
+
+impl ParsedBuffer {
+    /// Gets the end (exclusive) of the expression starting at or after the position, skipping
+    /// whitespace. An expression is a word or pair, followed by any calls, indexing and member
+    /// accesses, such as `foo.bar(baz)[0]`, or a whole string such as `'foo'`
+    pub fn expression_end_after(
+        &self,
+        lines: &[&str],
+        line_number: usize,
+        col: usize,
+    ) -> Option<Position> {
+        let start = self.next_non_whitespace(lines, line_number, col)?;
+
+        let mut end = self
+            .pair_end_at(start, is_expression_token)
+            .or_else(|| self.word_end_at(lines, start))?;
+        loop {
+            if let Some(pair_end) = self.pair_end_at(end, is_trailing_token) {
+                end = pair_end;
+                continue;
+            }
+
+            let rest = &lines[end.line][end.col..];
+            let Some(accessor) = ACCESSORS
+                .iter()
+                .find(|accessor| rest.starts_with(**accessor))
+            else {
+                break;
+            };
+            match self.word_end_at(lines, Position::new(end.line, end.col + accessor.len())) {
+                Some(word_end) => end = word_end,
+                None => break,
+            }
+        }
+
+        Some(end)
+    }
+
+    /// Gets the start of the expression ending at or before the position, skipping whitespace,
+    /// with the same rules as `expression_end_after`
+    pub fn expression_start_before(
+        &self,
+        lines: &[&str],
+        line_number: usize,
+        col: usize,
+    ) -> Option<Position> {
+        let end = self.prev_non_whitespace(lines, line_number, col)?;
+
+        let (mut start, mut is_pair) = match self.pair_start_before(end, is_expression_token) {
+            Some(start) => (start, true),
+            None => (self.word_start_before(lines, end)?, false),
+        };
+        loop {
+            // Calls and indexing may follow a word or another pair
+            if is_pair {
+                if let Some(pair_start) = self.pair_start_before(start, is_trailing_token) {
+                    start = pair_start;
+                    continue;
+                }
+                if let Some(word_start) = self.word_start_before(lines, start) {
+                    start = word_start;
+                    is_pair = false;
+                    continue;
+                }
+            }
+
+            let before = &lines[start.line][..start.col];
+            let Some(accessor) = ACCESSORS
+                .iter()
+                .find(|accessor| before.ends_with(**accessor))
+            else {
+                break;
+            };
+            let accessor_start = Position::new(start.line, start.col - accessor.len());
+            if let Some(pair_start) = self.pair_start_before(accessor_start, is_trailing_token) {
+                (start, is_pair) = (pair_start, true);
+            } else if let Some(word_start) = self.word_start_before(lines, accessor_start) {
+                (start, is_pair) = (word_start, false);
+            } else {
+                break;
+            }
+        }
+
+        Some(start)
+    }
+
+    /// Gets the end of the pair whose opening starts exactly at the position
+    fn pair_end_at(&self, pos: Position, predicate: fn(&Token) -> bool) -> Option<Position> {
+        let opening = self.match_at(pos.line, pos.col)?;
+        if opening.col != pos.col || opening.kind != Kind::Opening || !predicate(&opening.token) {
+            return None;
+        }
+        let (_, closing) = self.match_pair(pos.line, pos.col)?;
+        Some(Position::new(closing.line, closing.col + closing.len()))
+    }
+
+    /// Gets the start of the pair whose closing ends exactly at the position
+    fn pair_start_before(&self, pos: Position, predicate: fn(&Token) -> bool) -> Option<Position> {
+        let closing = self.matches_by_line[pos.line]
+            .iter()
+            .find(|match_| match_.kind == Kind::Closing && match_.col + match_.len() == pos.col)?;
+        if !predicate(&closing.token) {
+            return None;
+        }
+        let (opening, _) = self.match_pair(pos.line, closing.col)?;
+        Some(Position::new(opening.line, opening.col))
+    }
+
+    fn word_end_at(&self, lines: &[&str], pos: Position) -> Option<Position> {
+        let rest = &lines[pos.line][pos.col..];
+        let len = rest
+            .find(|char: char| !is_word_char(char))
+            .unwrap_or(rest.len());
+        (len > 0).then_some(Position::new(pos.line, pos.col + len))
+    }
+
+    fn word_start_before(&self, lines: &[&str], pos: Position) -> Option<Position> {
+        let before = &lines[pos.line][..pos.col];
+        let start = before
+            .rfind(|char: char| !is_word_char(char))
+            .map(|idx| idx + before[idx..].chars().next().unwrap().len_utf8())
+            .unwrap_or(0);
+        (start < pos.col).then_some(Position::new(pos.line, start))
+    }
+
+    /// Gets the first non-whitespace character at or after the position, across lines
+    fn next_non_whitespace(
+        &self,
+        lines: &[&str],
+        line_number: usize,
+        col: usize,
+    ) -> Option<Position> {
+        (line_number..self.line_count()).find_map(|line| {
+            let start = if line == line_number { col } else { 0 };
+            let text = lines[line].get(start..)?;
+            text.find(|char: char| !char.is_whitespace())
+                .map(|idx| Position::new(line, start + idx))
+        })
+    }
+
+    /// Gets the end (exclusive) of the last non-whitespace character before the position,
+    /// across lines
+    fn prev_non_whitespace(
+        &self,
+        lines: &[&str],
+        line_number: usize,
+        col: usize,
+    ) -> Option<Position> {
+        (0..=line_number).rev().find_map(|line| {
+            let text = lines[line];
+            let text = if line == line_number {
+                text.get(..col)?
+            } else {
+                text
+            };
+            let trimmed = text.trim_end();
+            (!trimmed.is_empty()).then_some(Position::new(line, trimmed.len()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(filetype: &str, lines: &[&str]) -> ParsedBuffer {
+        ParsedBuffer::parse(filetype, 4, lines).unwrap()
+    }
+
+    #[test]
+    fn test_expression_end_after() {
+        let lines = &["(x, foo.bar(a)[0] + 1)"];
+        let buffer = parse("rust", lines);
+        assert_eq!(
+            buffer.expression_end_after(lines, 0, 3),
+            Some(Position::new(0, 17))
+        );
+        assert_eq!(
+            buffer.expression_end_after(lines, 0, 8),
+            Some(Position::new(0, 17))
+        );
+        assert_eq!(buffer.expression_end_after(lines, 0, 17), None);
+        assert_eq!(
+            buffer.expression_end_after(lines, 0, 0),
+            Some(Position::new(0, 22))
+        );
+
+        let lines = &["(", "  'a(b' .. c"];
+        let buffer = parse("lua", lines);
+        assert_eq!(
+            buffer.expression_end_after(lines, 0, 1),
+            Some(Position::new(1, 7))
+        );
+    }
+
+    #[test]
+    fn test_expression_start_before() {
+        let lines = &["(x, foo.bar(a)[0] + 1)"];
+        let buffer = parse("rust", lines);
+        assert_eq!(
+            buffer.expression_start_before(lines, 0, 17),
+            Some(Position::new(0, 4))
+        );
+        assert_eq!(
+            buffer.expression_start_before(lines, 0, 11),
+            Some(Position::new(0, 4))
+        );
+        assert_eq!(buffer.expression_start_before(lines, 0, 19), None);
+
+        let lines = &["x = 'a)b' ", ")"];
+        let buffer = parse("lua", lines);
+        assert_eq!(
+            buffer.expression_start_before(lines, 1, 0),
+            Some(Position::new(0, 4))
+        );
+    }
+}
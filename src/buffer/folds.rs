@@ -0,0 +1,225 @@
+use std::collections::{BTreeMap, HashSet};
+
+use mlua::{FromLua, IntoLua};
+
+use super::{PairTreeNode, ParsedBuffer, TextObjectKind};
+use crate::error::Error;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoldOptions {
+    /// Minimum number of lines in a fold, after excluding the closing line when it stays visible
+    pub min_lines: usize,
+    /// Pairs which create folds, i.e. delimiters, strings and block comments
+    pub kinds: Vec<TextObjectKind>,
+    /// Leaves the line with the closing outside of the fold
+    pub closing_visible: bool,
+}
+
+impl Default for FoldOptions {
+    fn default() -> Self {
+        Self {
+            min_lines: 2,
+            kinds: vec![
+                TextObjectKind::Delimiter,
+                TextObjectKind::String,
+                TextObjectKind::BlockComment,
+            ],
+            closing_visible: false,
+        }
+    }
+}
+
+impl FromLua for FoldOptions {
+    fn from_lua(value: mlua::Value, lua: &mlua::Lua) -> mlua::Result<Self> {
+        let table = mlua::Table::from_lua(value, lua)?;
+        let default = FoldOptions::default();
+
+        let kinds = match table.get::<Option<Vec<String>>>("kinds")? {
+            Some(kinds) => kinds
+                .iter()
+                .map(|kind| kind.parse())
+                .collect::<Result<_, Error>>()
+                .map_err(|err| mlua::Error::FromLuaConversionError {
+                    from: "table",
+                    to: "FoldOptions".to_string(),
+                    message: Some(err.to_string()),
+                })?,
+            None => default.kinds,
+        };
+
+        Ok(Self {
+            min_lines: table
+                .get::<Option<usize>>("min_lines")?
+                .unwrap_or(default.min_lines),
+            kinds,
+            closing_visible: table
+                .get::<Option<bool>>("closing_visible")?
+                .unwrap_or(default.closing_visible),
+        })
+    }
+}
+
+/// Fold information for each line in a range
+#[derive(Debug, Clone, PartialEq)]
+pub struct Folds {
+    /// Number of folds containing the line
+    pub levels: Vec<usize>,
+    /// Values for `foldexpr`, i.e. `>1` when a fold starts on the line, `<1` when it ends on the
+    /// line or `1` otherwise
+    pub exprs: Vec<String>,
+}
+
+impl IntoLua for Folds {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+
+        table.set("levels", self.levels)?;
+        table.set("exprs", self.exprs)?;
+
+        (&table).into_lua(lua)
+    }
+}
+
+impl ParsedBuffer {
+    /// Computes the folds for the line range (exclusive) from the pairs spanning multiple lines.
+    /// Only one fold may start on a line, so pairs starting on the same line are merged, and
+    /// a fold ending on the line where another starts (i.e. `} else {`) ends on the line before
+    pub fn folds(&self, start_line: usize, end_line: usize, options: &FoldOptions) -> Folds {
+        let mut ends_by_start = BTreeMap::<usize, usize>::new();
+        let mut nodes = self.pair_tree(start_line, end_line);
+        while let Some(node) = nodes.pop() {
+            let PairTreeNode::Pair {
+                opening,
+                closing,
+                children,
+            } = node
+            else {
+                continue;
+            };
+            nodes.extend(children);
+
+            if opening.line == closing.line
+                || !options
+                    .kinds
+                    .iter()
+                    .any(|kind| kind.matches(&opening.token))
+            {
+                continue;
+            }
+
+            let end = if options.closing_visible {
+                closing.line - 1
+            } else {
+                closing.line
+            };
+            let fold_end = ends_by_start.entry(opening.line).or_insert(end);
+            *fold_end = (*fold_end).max(end);
+        }
+
+        let starts = ends_by_start.keys().copied().collect::<HashSet<_>>();
+        let folds = ends_by_start
+            .iter()
+            .map(|(&start, &end)| {
+                if end > start && starts.contains(&end) {
+                    (start, end - 1)
+                } else {
+                    (start, end)
+                }
+            })
+            .filter(|(start, end)| end + 1 - start >= options.min_lines);
+
+        let line_count = end_line.saturating_sub(start_line);
+        let mut levels = vec![0; line_count];
+        let mut starting = vec![false; line_count];
+        let mut ending = vec![0; line_count];
+        for (start, end) in folds {
+            for line in start.max(start_line)..(end + 1).min(end_line) {
+                levels[line - start_line] += 1;
+            }
+            if (start_line..end_line).contains(&start) {
+                starting[start - start_line] = true;
+            }
+            if (start_line..end_line).contains(&end) {
+                ending[end - start_line] += 1;
+            }
+        }
+
+        let exprs = (0..line_count)
+            .map(|idx| {
+                let level = levels[idx];
+                if starting[idx] {
+                    format!(">{level}")
+                } else if ending[idx] > 0 {
+                    // Ends the outermost fold on the line, which ends the inner folds as well
+                    format!("<{}", level + 1 - ending[idx])
+                } else {
+                    level.to_string()
+                }
+            })
+            .collect();
+
+        Folds { levels, exprs }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folds(lines: &[&str], options: &FoldOptions) -> Vec<String> {
+        ParsedBuffer::parse("rust", 4, lines)
+            .unwrap()
+            .folds(0, lines.len(), options)
+            .exprs
+    }
+
+    #[test]
+    fn test_nested_folds() {
+        let lines = &["fn a() {", "    b(", "        c,", "    );", "}", "d"];
+        assert_eq!(
+            folds(lines, &FoldOptions::default()),
+            vec![">1", ">2", "2", "<2", "<1", "0"]
+        );
+    }
+
+    #[test]
+    fn test_else_and_shared_lines() {
+        let lines = &["if a {", "    b", "} else {", "    c({", "    })", "}"];
+        assert_eq!(
+            folds(lines, &FoldOptions::default()),
+            vec![">1", "<1", ">1", ">2", "<2", "<1"]
+        );
+    }
+
+    #[test]
+    fn test_options() {
+        let lines = &["/*", "a", "*/", "{", "}", "{", "b", "}"];
+        let options = FoldOptions {
+            closing_visible: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            folds(lines, &options),
+            vec![">1", "<1", "0", "0", "0", ">1", "<1", "0"]
+        );
+
+        let options = FoldOptions {
+            kinds: vec![TextObjectKind::Delimiter],
+            min_lines: 3,
+            ..Default::default()
+        };
+        assert_eq!(
+            folds(lines, &options),
+            vec!["0", "0", "0", "0", "0", ">1", "1", "<1"]
+        );
+    }
+
+    #[test]
+    fn test_partial_range() {
+        let lines = &["{", "    (", "    )", "    x", "}"];
+        let buffer = ParsedBuffer::parse("rust", 4, lines).unwrap();
+        let folds = buffer.folds(2, 4, &FoldOptions::default());
+        assert_eq!(folds.levels, vec![2, 1]);
+        assert_eq!(folds.exprs, vec!["<2", "1"]);
+    }
+}
@@ -0,0 +1,345 @@
+use super::ParsedBuffer;
+use crate::parser::{Kind, MatchWithLine, Token};
+
+type Pair = (MatchWithLine, MatchWithLine);
+
+/// Whether the match is a matched delimiter, optionally with the given opening
+fn is_delimiter(match_: &MatchWithLine, filter: Option<&str>) -> bool {
+    matches!(match_.token, Token::Delimiter(_, _))
+        && match_.stack_height.is_some()
+        && filter.is_none_or(|opening| match_.token.opening() == opening)
+}
+
+impl ParsedBuffer {
+    /// Walks backwards from the position, skipping over pairs that end before the position,
+    /// and yields the opening of every pair that encloses the position, innermost first.
+    /// Only considers the matches accepted by the predicate, which must accept both
+    /// sides of a pair
+    pub(super) fn enclosing_openings<'a>(
+        &'a self,
+        line_number: usize,
+        col: usize,
+        predicate: impl Fn(&MatchWithLine) -> bool + 'a,
+    ) -> impl Iterator<Item = MatchWithLine> + 'a {
+        let mut closed_pairs: usize = 0;
+        self.iter_to(line_number, col)
+            .filter(move |match_| match_.stack_height.is_some() && predicate(match_))
+            .filter(move |match_| match match_.kind {
+                Kind::Closing => {
+                    closed_pairs += 1;
+                    false
+                }
+                Kind::Opening if closed_pairs > 0 => {
+                    closed_pairs -= 1;
+                    false
+                }
+                Kind::Opening => true,
+                Kind::NonPair => false,
+            })
+    }
+
+    /// Gets the innermost delimiter pair strictly enclosing the position, so a position on a
+    /// delimiter returns the pair around that delimiter's pair
+    pub fn parent_pair(
+        &self,
+        line_number: usize,
+        col: usize,
+        filter: Option<&str>,
+    ) -> Option<Pair> {
+        self.enclosing_openings(line_number, col, |match_| is_delimiter(match_, None))
+            .filter(|opening| is_delimiter(opening, filter))
+            .filter_map(|opening| self.match_pair(opening.line, opening.col))
+            // Position on a closing delimiter belongs to the pair outside of it
+            .find(|(_, closing)| (closing.line, closing.col) > (line_number, col))
+    }
+
+    /// Gets the delimiter pair at the position, or the pair enclosing the position
+    fn current_pair(&self, line_number: usize, col: usize) -> Option<Pair> {
+        self.match_at(line_number, col)
+            .map(|match_| match_.with_line(line_number))
+            .filter(|match_| is_delimiter(match_, None))
+            .and_then(|match_| self.match_pair(match_.line, match_.col))
+            .or_else(|| self.parent_pair(line_number, col, None))
+    }
+
+    /// Gets the nesting depth of delimiters at the position, where a position on a delimiter
+    /// has the depth of that delimiter
+    fn delimiter_depth_at(&self, line_number: usize, col: usize) -> usize {
+        if let Some(stack_height) = self
+            .match_at(line_number, col)
+            .map(|match_| match_.with_line(line_number))
+            .filter(|match_| is_delimiter(match_, None))
+            .and_then(|match_| match_.stack_height)
+        {
+            return stack_height;
+        }
+
+        self.parent_pair(line_number, col, None)
+            .and_then(|(opening, _)| opening.stack_height)
+            .map(|stack_height| stack_height + 1)
+            .unwrap_or(0)
+    }
+
+    /// Gets the next matched delimiter of the given kind at the same depth as the position,
+    /// without leaving the enclosing pair
+    pub fn next_at_depth(
+        &self,
+        line_number: usize,
+        col: usize,
+        kind: Kind,
+        filter: Option<&str>,
+    ) -> Option<MatchWithLine> {
+        let depth = self.delimiter_depth_at(line_number, col);
+        self.iter_from(line_number, col + 1)
+            .filter(|match_| is_delimiter(match_, None))
+            .take_while(|match_| match_.stack_height.is_some_and(|sh| sh >= depth))
+            .find(|match_| {
+                match_.kind == kind
+                    && match_.stack_height == Some(depth)
+                    && is_delimiter(match_, filter)
+            })
+    }
+
+    /// Gets the previous matched delimiter of the given kind at the same depth as the position,
+    /// without leaving the enclosing pair
+    pub fn prev_at_depth(
+        &self,
+        line_number: usize,
+        col: usize,
+        kind: Kind,
+        filter: Option<&str>,
+    ) -> Option<MatchWithLine> {
+        let depth = self.delimiter_depth_at(line_number, col);
+        self.iter_to(line_number, col)
+            .filter(|match_| is_delimiter(match_, None))
+            .take_while(|match_| match_.stack_height.is_some_and(|sh| sh >= depth))
+            .find(|match_| {
+                match_.kind == kind
+                    && match_.stack_height == Some(depth)
+                    && is_delimiter(match_, filter)
+            })
+    }
+
+    /// Gets the pair after the pair at the position (or after the position) with the same parent
+    pub fn next_sibling_pair(
+        &self,
+        line_number: usize,
+        col: usize,
+        filter: Option<&str>,
+    ) -> Option<Pair> {
+        let (line_number, col) = self
+            .match_at(line_number, col)
+            .map(|match_| match_.with_line(line_number))
+            .filter(|match_| match_.kind == Kind::Opening && is_delimiter(match_, None))
+            .and_then(|match_| self.match_pair(match_.line, match_.col))
+            .map(|(_, closing)| (closing.line, closing.col))
+            .unwrap_or((line_number, col));
+
+        let opening = self.next_at_depth(line_number, col, Kind::Opening, filter)?;
+        self.match_pair(opening.line, opening.col)
+    }
+
+    /// Gets the pair before the pair at the position (or before the position) with the same
+    /// parent
+    pub fn prev_sibling_pair(
+        &self,
+        line_number: usize,
+        col: usize,
+        filter: Option<&str>,
+    ) -> Option<Pair> {
+        let (line_number, col) = self
+            .match_at(line_number, col)
+            .map(|match_| match_.with_line(line_number))
+            .filter(|match_| match_.kind == Kind::Closing && is_delimiter(match_, None))
+            .and_then(|match_| self.match_pair(match_.line, match_.col))
+            .map(|(opening, _)| (opening.line, opening.col))
+            .unwrap_or((line_number, col));
+
+        let closing = self.prev_at_depth(line_number, col, Kind::Closing, filter)?;
+        self.match_pair(closing.line, closing.col)
+    }
+
+    /// Gets the child pairs directly inside the pair at the position (or enclosing the position),
+    /// or the top level pairs when there's no such pair
+    fn child_pairs<'a>(
+        &'a self,
+        line_number: usize,
+        col: usize,
+        filter: Option<&'a str>,
+    ) -> impl Iterator<Item = Pair> + 'a {
+        let current_pair = self.current_pair(line_number, col);
+        let depth = current_pair
+            .as_ref()
+            .and_then(|(opening, _)| opening.stack_height)
+            .map(|stack_height| stack_height + 1)
+            .unwrap_or(0);
+        let (start, end) = match current_pair {
+            Some((opening, closing)) => (
+                (opening.line, opening.col + opening.len()),
+                (closing.line, closing.col),
+            ),
+            None => ((0, 0), (usize::MAX, usize::MAX)),
+        };
+
+        self.iter_from(start.0, start.1)
+            .take_while(move |match_| (match_.line, match_.col) < end)
+            .filter(move |match_| {
+                match_.kind == Kind::Opening
+                    && match_.stack_height == Some(depth)
+                    && is_delimiter(match_, filter)
+            })
+            .filter_map(|opening| self.match_pair(opening.line, opening.col))
+    }
+
+    pub fn first_child_pair(
+        &self,
+        line_number: usize,
+        col: usize,
+        filter: Option<&str>,
+    ) -> Option<Pair> {
+        self.child_pairs(line_number, col, filter).next()
+    }
+
+    pub fn last_child_pair(
+        &self,
+        line_number: usize,
+        col: usize,
+        filter: Option<&str>,
+    ) -> Option<Pair> {
+        self.child_pairs(line_number, col, filter).last()
+    }
+
+    /// Gets the closing of the innermost delimiter pair, string or block string enclosing
+    /// the position, for moving the cursor past it (i.e. "tab out")
+    pub fn tab_out_closing(
+        &self,
+        line_number: usize,
+        col: usize,
+        filter: Option<&str>,
+    ) -> Option<MatchWithLine> {
+        self.enclosing_openings(line_number, col, |match_| {
+            matches!(
+                match_.token,
+                Token::Delimiter(_, _) | Token::String(_) | Token::BlockString(_, _)
+            )
+        })
+        .filter(|opening| filter.is_none_or(|filter| opening.token.opening() == filter))
+        .find_map(|opening| self.match_pair(opening.line, opening.col))
+        .map(|(_, closing)| closing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(lines: &[&str]) -> ParsedBuffer {
+        ParsedBuffer::parse("rust", 4, lines).unwrap()
+    }
+
+    fn pos(pair: Option<Pair>) -> Option<((usize, usize), (usize, usize))> {
+        pair.map(|(open, close)| ((open.line, open.col), (close.line, close.col)))
+    }
+
+    #[test]
+    fn test_parent_pair() {
+        let buffer = parse(&["a(b[c], \"(\" d)"]);
+        assert_eq!(pos(buffer.parent_pair(0, 4, None)), Some(((0, 3), (0, 5))));
+        assert_eq!(pos(buffer.parent_pair(0, 3, None)), Some(((0, 1), (0, 13))));
+        assert_eq!(pos(buffer.parent_pair(0, 5, None)), Some(((0, 1), (0, 13))));
+        assert_eq!(
+            pos(buffer.parent_pair(0, 4, Some("("))),
+            Some(((0, 1), (0, 13)))
+        );
+        assert_eq!(
+            pos(buffer.parent_pair(0, 10, None)),
+            Some(((0, 1), (0, 13)))
+        );
+        assert_eq!(pos(buffer.parent_pair(0, 0, None)), None);
+        assert_eq!(pos(buffer.parent_pair(0, 14, None)), None);
+    }
+
+    #[test]
+    fn test_children() {
+        let buffer = parse(&["f(a(1), [2], b(3))"]);
+        assert_eq!(
+            pos(buffer.first_child_pair(0, 7, None)),
+            Some(((0, 3), (0, 5)))
+        );
+        assert_eq!(
+            pos(buffer.first_child_pair(0, 1, None)),
+            Some(((0, 3), (0, 5)))
+        );
+        assert_eq!(
+            pos(buffer.last_child_pair(0, 1, None)),
+            Some(((0, 14), (0, 16)))
+        );
+        assert_eq!(
+            pos(buffer.first_child_pair(0, 1, Some("["))),
+            Some(((0, 8), (0, 10)))
+        );
+        assert_eq!(
+            pos(buffer.first_child_pair(0, 0, None)),
+            Some(((0, 1), (0, 17)))
+        );
+        assert_eq!(pos(buffer.first_child_pair(0, 4, None)), None);
+    }
+
+    #[test]
+    fn test_siblings() {
+        let buffer = parse(&["f(a(1), [2], b(3))"]);
+        assert_eq!(
+            pos(buffer.next_sibling_pair(0, 3, None)),
+            Some(((0, 8), (0, 10)))
+        );
+        assert_eq!(
+            pos(buffer.next_sibling_pair(0, 6, None)),
+            Some(((0, 8), (0, 10)))
+        );
+        assert_eq!(
+            pos(buffer.next_sibling_pair(0, 3, Some("("))),
+            Some(((0, 14), (0, 16)))
+        );
+        assert_eq!(pos(buffer.next_sibling_pair(0, 14, None)), None);
+        assert_eq!(
+            pos(buffer.prev_sibling_pair(0, 16, None)),
+            Some(((0, 8), (0, 10)))
+        );
+        assert_eq!(
+            pos(buffer.prev_sibling_pair(0, 13, None)),
+            Some(((0, 8), (0, 10)))
+        );
+        assert_eq!(pos(buffer.prev_sibling_pair(0, 3, None)), None);
+    }
+
+    #[test]
+    fn test_at_depth() {
+        let buffer = parse(&["{", "    a(1);", "    b[2];", "}"]);
+        let next = buffer.next_at_depth(1, 0, Kind::Opening, None).unwrap();
+        assert_eq!((next.line, next.col), (1, 5));
+        let next = buffer.next_at_depth(1, 5, Kind::Opening, None).unwrap();
+        assert_eq!((next.line, next.col), (2, 5));
+        let next = buffer
+            .next_at_depth(1, 5, Kind::Closing, Some("["))
+            .unwrap();
+        assert_eq!((next.line, next.col), (2, 7));
+        assert_eq!(buffer.next_at_depth(2, 5, Kind::Opening, None), None);
+        let prev = buffer.prev_at_depth(2, 5, Kind::Closing, None).unwrap();
+        assert_eq!((prev.line, prev.col), (1, 7));
+        assert_eq!(buffer.prev_at_depth(1, 5, Kind::Closing, None), None);
+    }
+
+    #[test]
+    fn test_tab_out_closing() {
+        let buffer = parse(&["f(g(1), \"ab\")"]);
+        let closing = buffer.tab_out_closing(0, 10, None).unwrap();
+        assert_eq!((closing.line, closing.col), (0, 11));
+        let closing = buffer.tab_out_closing(0, 2, None).unwrap();
+        assert_eq!((closing.line, closing.col), (0, 12));
+        let closing = buffer.tab_out_closing(0, 4, None).unwrap();
+        assert_eq!((closing.line, closing.col), (0, 5));
+        let closing = buffer.tab_out_closing(0, 10, Some("(")).unwrap();
+        assert_eq!((closing.line, closing.col), (0, 12));
+        assert_eq!(buffer.tab_out_closing(0, 13, None), None);
+    }
+}
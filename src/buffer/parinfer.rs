@@ -0,0 +1,340 @@
+use std::str::FromStr;
+
+use mlua::IntoLua;
+
+use super::{ParsedBuffer, Position, TextEdit};
+use crate::error::Error;
+use crate::parser::{Kind, State, Token};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParinferMode {
+    /// Infers the closing delimiters at the end of each line from the indentation
+    Indent,
+    /// Corrects the indentation of each line from the delimiters
+    Paren,
+}
+
+impl FromStr for ParinferMode {
+    type Err = Error;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "indent" => Ok(ParinferMode::Indent),
+            "paren" => Ok(ParinferMode::Paren),
+            _ => Err(Error::InvalidArgument {
+                name: "mode",
+                value: mode.to_string(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parinfer {
+    /// Edits ordered from the end of the buffer, so they may be applied one by one
+    pub edits: Vec<TextEdit>,
+    /// Position of the cursor after applying the edits
+    pub cursor: Option<Position>,
+}
+
+impl IntoLua for Parinfer {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+
+        table.set("edits", self.edits)?;
+        table.set("cursor", self.cursor)?;
+
+        (&table).into_lua(lua)
+    }
+}
+
+/// Opening delimiter which hasn't been closed yet, with its column in characters
+struct Opener {
+    token: Token,
+    x: usize,
+}
+
+/// Closing delimiters and whitespace at the end of the code on a line, i.e. `))` in
+/// `(foo (bar)) ; comment`
+struct Trail {
+    line: usize,
+    start_col: usize,
+    end_col: usize,
+}
+
+/// Number of characters in the leading whitespace of the text
+fn indent_of(text: &str) -> usize {
+    text.chars().take_while(|char| char.is_whitespace()).count()
+}
+
+impl ParsedBuffer {
+    /// Runs Parinfer over the buffer, returning the edits to keep the delimiters and the
+    /// indentation in agreement, and the cursor position after the edits. Only delimiters are
+    /// considered, so strings, comments and character literals such as `\(` are respected
+    pub fn parinfer(
+        &self,
+        lines: &[&str],
+        mode: ParinferMode,
+        cursor: Option<Position>,
+    ) -> Parinfer {
+        let mut edits = match mode {
+            ParinferMode::Indent => self.parinfer_indent(lines),
+            ParinferMode::Paren => self.parinfer_paren(lines),
+        };
+        edits.sort_by_key(|edit| std::cmp::Reverse((edit.start_line, edit.start_col)));
+
+        Parinfer {
+            cursor: cursor.map(|cursor| adjust_cursor(cursor, &edits)),
+            edits,
+        }
+    }
+
+    fn parinfer_indent(&self, lines: &[&str]) -> Vec<TextEdit> {
+        let mut edits = vec![];
+        let mut stack: Vec<Opener> = vec![];
+        let mut prev_trail: Option<(Trail, String)> = None;
+
+        let mut flush = |trail: Trail, closers: String| {
+            let old = &lines[trail.line][trail.start_col..trail.end_col];
+            if old != closers {
+                edits.push(TextEdit {
+                    start_line: trail.line,
+                    start_col: trail.start_col,
+                    end_line: trail.line,
+                    end_col: trail.end_col,
+                    text: closers,
+                });
+            }
+        };
+
+        for line in 0..self.line_count() {
+            let Some(trail) = self.paren_trail(lines, line) else {
+                continue;
+            };
+
+            // Openers at or after the indentation are closed at the end of the previous line
+            if trail.start_col > self.line_layout(line).content_start {
+                let x = indent_of(lines[line]);
+                if let Some((_, closers)) = prev_trail.as_mut() {
+                    while let Some(opener) = stack.pop_if(|opener| opener.x >= x) {
+                        closers.push_str(opener.token.closing().unwrap_or_default());
+                    }
+                }
+            }
+            if let Some((trail, closers)) = prev_trail.take() {
+                flush(trail, closers);
+            }
+
+            for match_ in self.matches_by_line[line]
+                .iter()
+                .take_while(|match_| match_.col < trail.start_col)
+                .filter(|match_| matches!(match_.token, Token::Delimiter(_, _)))
+            {
+                match match_.kind {
+                    Kind::Opening => stack.push(Opener {
+                        token: match_.token.clone(),
+                        x: lines[line][..match_.col].chars().count(),
+                    }),
+                    Kind::Closing => {
+                        stack.pop_if(|opener| opener.token == match_.token);
+                    }
+                    Kind::NonPair => {}
+                }
+            }
+
+            prev_trail = Some((trail, String::new()));
+        }
+
+        if let Some((trail, mut closers)) = prev_trail {
+            while let Some(opener) = stack.pop() {
+                closers.push_str(opener.token.closing().unwrap_or_default());
+            }
+            flush(trail, closers);
+        }
+
+        edits
+    }
+
+    fn parinfer_paren(&self, lines: &[&str]) -> Vec<TextEdit> {
+        let mut edits = vec![];
+        let mut stack: Vec<Opener> = vec![];
+        // Column of the outermost opener closed by the trail of the previous line, which the
+        // line mustn't be indented past, or it would be inside of the pair in indent mode
+        let mut max_indent: Option<usize> = None;
+
+        for line in 0..self.line_count() {
+            let Some(trail) = self.paren_trail(lines, line) else {
+                continue;
+            };
+            let text = lines[line];
+            let leading_whitespace = text.len() - text.trim_start().len();
+
+            let mut shift: isize = 0;
+            let starts_with_closing = trail.start_col == leading_whitespace;
+            if !starts_with_closing {
+                let x = indent_of(text);
+                let min_indent = stack.last().map(|opener| opener.x + 1).unwrap_or(0);
+                let new_x = x.min(max_indent.unwrap_or(usize::MAX)).max(min_indent);
+                if new_x != x {
+                    shift = new_x as isize - x as isize;
+                    edits.push(TextEdit {
+                        start_line: line,
+                        start_col: 0,
+                        end_line: line,
+                        end_col: leading_whitespace,
+                        text: " ".repeat(new_x),
+                    });
+                }
+            }
+
+            max_indent = None;
+            for match_ in self.matches_by_line[line]
+                .iter()
+                .filter(|match_| matches!(match_.token, Token::Delimiter(_, _)))
+            {
+                match match_.kind {
+                    Kind::Opening => stack.push(Opener {
+                        token: match_.token.clone(),
+                        x: (text[..match_.col].chars().count() as isize + shift) as usize,
+                    }),
+                    Kind::Closing => {
+                        let opener = stack.pop_if(|opener| opener.token == match_.token);
+                        if let Some(opener) = opener.filter(|_| match_.col >= trail.start_col) {
+                            max_indent = Some(opener.x);
+                        }
+                    }
+                    Kind::NonPair => {}
+                }
+            }
+        }
+
+        edits
+    }
+
+    /// Gets the trail of closing delimiters on the line, or `None` when the line has no code,
+    /// or starts inside of a block string or block comment
+    fn paren_trail(&self, lines: &[&str], line: usize) -> Option<Trail> {
+        if line > 0
+            && matches!(
+                self.state_by_line[line - 1],
+                State::InBlockString(_) | State::InBlockComment(_)
+            )
+        {
+            return None;
+        }
+
+        let text = lines[line];
+        let end_col = text[..self.end_of_code(line)].trim_end().len();
+        if end_col == 0 {
+            return None;
+        }
+
+        // Walk backwards over the closing delimiters and whitespace
+        let matches = &self.matches_by_line[line];
+        let mut start_col = end_col;
+        loop {
+            let before = text[..start_col].trim_end();
+            let closing = matches.iter().find(|match_| {
+                match_.kind == Kind::Closing
+                    && matches!(match_.token, Token::Delimiter(_, _))
+                    && match_.col + match_.len() == before.len()
+            });
+            match closing {
+                Some(closing) => start_col = closing.col,
+                None => break,
+            }
+        }
+
+        Some(Trail {
+            line,
+            start_col,
+            end_col,
+        })
+    }
+}
+
+/// Moves the cursor to account for the edits, which must be ordered from the end of the buffer
+fn adjust_cursor(mut cursor: Position, edits: &[TextEdit]) -> Position {
+    for edit in edits.iter().filter(|edit| edit.start_line == cursor.line) {
+        if cursor.col >= edit.end_col {
+            cursor.col = cursor.col + edit.text.len() - (edit.end_col - edit.start_col);
+        } else if cursor.col > edit.start_col {
+            cursor.col = edit.start_col + (cursor.col - edit.start_col).min(edit.text.len());
+        }
+    }
+    cursor
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::apply_edits;
+
+    fn parinfer(text: &str, mode: ParinferMode) -> String {
+        let lines = text.split('\n').collect::<Vec<_>>();
+        let result = ParsedBuffer::parse("clojure", 4, &lines)
+            .unwrap()
+            .parinfer(&lines, mode, None);
+        apply_edits(&lines, &result.edits)
+    }
+
+    #[test]
+    fn test_indent_mode() {
+        assert_eq!(
+            parinfer("(defn foo\n  [a]\n  (bar a", ParinferMode::Indent),
+            "(defn foo\n  [a]\n  (bar a))"
+        );
+        assert_eq!(
+            parinfer("(foo (bar)\n      baz)", ParinferMode::Indent),
+            "(foo (bar\n      baz))"
+        );
+        // Dedenting closes the pairs on the previous line
+        assert_eq!(
+            parinfer("(let [a 1\n  b 2]\n  a)", ParinferMode::Indent),
+            "(let [a 1]\n  b 2\n  a)"
+        );
+        // Closing delimiters stay before comments
+        assert_eq!(
+            parinfer("(foo ; (\nbar)", ParinferMode::Indent),
+            "(foo) ; (\nbar"
+        );
+    }
+
+    #[test]
+    fn test_indent_mode_unchanged() {
+        let text = "(defn foo [a]\n  ;; \"(\" \\(\n  (str \"(\" \\( a))";
+        let lines = text.split('\n').collect::<Vec<_>>();
+        let result = ParsedBuffer::parse("clojure", 4, &lines).unwrap().parinfer(
+            &lines,
+            ParinferMode::Indent,
+            Some(Position::new(2, 3)),
+        );
+        assert_eq!(result.edits, vec![]);
+        assert_eq!(result.cursor, Some(Position::new(2, 3)));
+    }
+
+    #[test]
+    fn test_paren_mode() {
+        assert_eq!(parinfer("(foo\nbar)", ParinferMode::Paren), "(foo\n bar)");
+        assert_eq!(
+            parinfer("(foo (bar)\n        baz)", ParinferMode::Paren),
+            "(foo (bar)\n     baz)"
+        );
+        assert_eq!(
+            parinfer("(let [a 1\n b 2])", ParinferMode::Paren),
+            "(let [a 1\n      b 2])"
+        );
+    }
+
+    #[test]
+    fn test_cursor() {
+        let lines = ["(foo", "  (bar", "baz"];
+        let result = ParsedBuffer::parse("clojure", 4, &lines).unwrap().parinfer(
+            &lines,
+            ParinferMode::Paren,
+            Some(Position::new(2, 1)),
+        );
+        assert_eq!(apply_edits(&lines, &result.edits), "(foo\n  (bar\n   baz");
+        assert_eq!(result.cursor, Some(Position::new(2, 4)));
+    }
+}
@@ -0,0 +1,89 @@
+use mlua::IntoLua;
+
+use super::ParsedBuffer;
+use crate::parser::matcher::{MatchFilter, TokenType};
+
+/// Matches in a range of lines as parallel arrays, so that they can be passed to
+/// `nvim_buf_set_extmark` without building a table per match
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RangeMatches {
+    pub lines: Vec<usize>,
+    pub cols: Vec<usize>,
+    pub end_cols: Vec<usize>,
+    /// Depth of the match when using `DepthMode::PerType`, otherwise the stack height, or -1
+    /// when it has no pair
+    pub depths: Vec<i64>,
+    /// Value of the `TokenType` of the match
+    pub kinds: Vec<u8>,
+}
+
+impl IntoLua for RangeMatches {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+
+        table.set("lines", self.lines)?;
+        table.set("cols", self.cols)?;
+        table.set("end_cols", self.end_cols)?;
+        table.set("depths", self.depths)?;
+        table.set("kinds", self.kinds)?;
+
+        (&table).into_lua(lua)
+    }
+}
+
+impl ParsedBuffer {
+    /// Gets the matches accepted by the filter in the line range (exclusive)
+    pub fn range_matches(
+        &self,
+        start_line: usize,
+        end_line: usize,
+        filter: MatchFilter,
+    ) -> RangeMatches {
+        let mut range = RangeMatches::default();
+        let end_line = end_line.min(self.line_count());
+
+        for (line, matches) in self.matches_by_line[start_line.min(end_line)..end_line]
+            .iter()
+            .enumerate()
+        {
+            for match_ in matches.iter().filter(|match_| filter.matches(match_)) {
+                range.lines.push(start_line + line);
+                range.cols.push(match_.col);
+                range.end_cols.push(match_.col + match_.len());
+                range.depths.push(
+                    match_
+                        .depth
+                        .or(match_.stack_height)
+                        .map(|height| height as i64)
+                        .unwrap_or(-1),
+                );
+                range.kinds.push(TokenType::of(&match_.token) as u8);
+            }
+        }
+
+        range
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_range_matches() {
+        let buffer = ParsedBuffer::parse("rust", 4, &["fn a() {", "  \"b\" )", "}"]).unwrap();
+
+        let range = buffer.range_matches(1, 3, MatchFilter::default());
+        assert_eq!(range.lines, vec![1, 2]);
+        assert_eq!(range.cols, vec![6, 0]);
+        assert_eq!(range.depths, vec![-1, 0]);
+
+        let filter = (1 << TokenType::Delimiter as u16)
+            | (1 << TokenType::BlockString as u16)
+            | MatchFilter::MATCHED;
+        let range = buffer.range_matches(0, 3, MatchFilter::try_from(filter).unwrap());
+        assert_eq!(range.lines, vec![0, 0, 0, 1, 1, 2]);
+        assert_eq!(range.end_cols, vec![5, 6, 8, 3, 5, 1]);
+        assert_eq!(range.kinds, vec![0, 0, 0, 2, 2, 0]);
+    }
+}
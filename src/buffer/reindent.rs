@@ -0,0 +1,249 @@
+use std::collections::BTreeSet;
+
+use super::{BufferText, ParsedBuffer};
+use crate::parser::indent::text_width;
+use crate::parser::{Kind, MatchWithLine, State, Token};
+
+impl ParsedBuffer {
+    /// Gets the expected indentation of the line from the pairs enclosing it, or `None` when
+    /// the line continues a block string or block comment and should be left untouched
+    pub fn suggest_indent(
+        &self,
+        lines: &(impl BufferText + ?Sized),
+        line_number: usize,
+        shiftwidth: usize,
+    ) -> Option<usize> {
+        self.suggest_indent_with(lines, line_number, shiftwidth, &|line| {
+            self.indent_levels[line] as usize
+        })
+    }
+
+    /// Gets the expected indentation of every non-blank line in the range (exclusive), taking
+    /// into account the new indentation of the lines before it in the range. Blank lines and
+    /// lines inside block strings and block comments are `None`
+    pub fn reindent_range(
+        &self,
+        lines: &(impl BufferText + ?Sized),
+        start_line: usize,
+        end_line: usize,
+        shiftwidth: usize,
+    ) -> Vec<Option<usize>> {
+        let end_line = end_line.min(self.line_count());
+        let mut indents: Vec<Option<usize>> =
+            Vec::with_capacity(end_line.saturating_sub(start_line));
+
+        for line_number in start_line..end_line {
+            let indent = if self.is_blank(line_number) {
+                None
+            } else {
+                self.suggest_indent_with(lines, line_number, shiftwidth, &|line| {
+                    line.checked_sub(start_line)
+                        .and_then(|idx| indents.get(idx).copied().flatten())
+                        .unwrap_or(self.indent_levels[line] as usize)
+                })
+            };
+            indents.push(indent);
+        }
+
+        indents
+    }
+
+    /// Gets the lines whose text is read when indenting the lines in the range (exclusive),
+    /// which are the lines of the openings that the lines may be aligned to
+    pub fn indent_text_lines(&self, start_line: usize, end_line: usize) -> BTreeSet<usize> {
+        (start_line..end_line.min(self.line_count()))
+            .filter_map(|line_number| self.unclosed_opening_before(line_number))
+            .map(|opening| opening.line)
+            .collect()
+    }
+
+    fn suggest_indent_with(
+        &self,
+        lines: &(impl BufferText + ?Sized),
+        line_number: usize,
+        shiftwidth: usize,
+        indent_of: &dyn Fn(usize) -> usize,
+    ) -> Option<usize> {
+        if line_number > 0
+            && matches!(
+                self.state_by_line.get(line_number - 1),
+                Some(State::InBlockString(_) | State::InBlockComment(_))
+            )
+        {
+            return None;
+        }
+
+        let Some(opening) = self.unclosed_opening_before(line_number) else {
+            // Keep the indentation of the previous statement
+            return Some(
+                self.prev_statement_line(line_number)
+                    .map(indent_of)
+                    .unwrap_or(0),
+            );
+        };
+
+        // {
+        // }
+        if self.starts_with_closing(line_number) {
+            return Some(indent_of(opening.line));
+        }
+
+        // foo(a,
+        //     b)
+        let opening_text = lines.line(opening.line);
+        let content_col = opening.col + opening.len();
+        let content_col = content_col
+            + opening_text[content_col..]
+                .find(|c: char| !c.is_whitespace())
+                .unwrap_or(self.line_len(opening.line) - content_col);
+        if content_col < self.end_of_code(opening.line) {
+            let leading_whitespace = self.line_layout(opening.line).content_start;
+            let alignment = &opening_text[leading_whitespace..content_col];
+            return Some(indent_of(opening.line) + text_width(alignment, self.tab_width));
+        }
+
+        Some(indent_of(opening.line) + shiftwidth)
+    }
+
+    /// Walks backwards from the start of the line to find the innermost delimiter that hasn't
+    /// been closed before the line, including delimiters without a closing at all
+    fn unclosed_opening_before(&self, line_number: usize) -> Option<MatchWithLine> {
+        let mut closed_pairs: usize = 0;
+        self.iter_to(line_number, 0)
+            .filter(|match_| matches!(match_.token, Token::Delimiter(_, _)))
+            .find(|match_| match (match_.kind, match_.stack_height) {
+                // Unmatched closings don't close anything
+                (Kind::Closing, Some(_)) => {
+                    closed_pairs += 1;
+                    false
+                }
+                (Kind::Opening, Some(_)) if closed_pairs > 0 => {
+                    closed_pairs -= 1;
+                    false
+                }
+                (Kind::Opening, _) => true,
+                _ => false,
+            })
+    }
+
+    /// Gets the line starting the statement on the closest non-blank line before the line,
+    /// which is the line of the outermost pair containing the start of that line
+    fn prev_statement_line(&self, line_number: usize) -> Option<usize> {
+        let prev_line = (0..line_number).rev().find(|&line| !self.is_blank(line))?;
+        Some(
+            self.enclosing_openings(prev_line, 0, |match_| {
+                matches!(match_.token, Token::Delimiter(_, _))
+            })
+            .last()
+            .map(|opening| opening.line)
+            .unwrap_or(prev_line),
+        )
+    }
+
+    fn starts_with_closing(&self, line_number: usize) -> bool {
+        let first_col = self.line_layout(line_number).content_start;
+        self.matches_by_line[line_number]
+            .iter()
+            .find(|match_| match_.col == first_col)
+            .is_some_and(|match_| {
+                match_.kind == Kind::Closing && matches!(match_.token, Token::Delimiter(_, _))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn reindent(filetype: &str, lines: &[&str]) -> Vec<Option<usize>> {
+        ParsedBuffer::parse(filetype, 4, lines)
+            .unwrap()
+            .reindent_range(lines, 0, lines.len(), 4)
+    }
+
+    #[test]
+    fn test_blocks() {
+        let lines = &["fn a() {", "if b {", "c();", "", "}", "}", "d();"];
+        assert_eq!(
+            reindent("rust", lines),
+            vec![Some(0), Some(4), Some(8), None, Some(4), Some(0), Some(0)]
+        );
+    }
+
+    #[test]
+    fn test_hanging_alignment() {
+        let lines = &["let x = foo(a,", "b,", "c);", "y();"];
+        assert_eq!(
+            reindent("rust", lines),
+            vec![Some(0), Some(12), Some(12), Some(0)]
+        );
+
+        // Tabs count as the tab width, like in the indentation
+        let lines = &["\tx = f(\ta,", "b)"];
+        assert_eq!(reindent("rust", lines), vec![Some(0), Some(10)]);
+
+        // Comments after the opening don't count as content
+        let lines = &["foo( // a", "b", ")"];
+        assert_eq!(reindent("rust", lines), vec![Some(0), Some(4), Some(0)]);
+    }
+
+    #[test]
+    fn test_unclosed_opening() {
+        let lines = &["    fn a() {", ""];
+        let buffer = ParsedBuffer::parse("rust", 4, lines).unwrap();
+        assert_eq!(buffer.suggest_indent(&lines[..], 1, 2), Some(6));
+    }
+
+    #[test]
+    fn test_indent_text_lines() {
+        let lines = &["let x = foo(a,", "b,", "c);", "{", "y();", "}"];
+        let buffer = ParsedBuffer::parse("rust", 4, lines).unwrap();
+        assert_eq!(
+            buffer.indent_text_lines(0, lines.len()),
+            BTreeSet::from([0, 3])
+        );
+        assert_eq!(buffer.indent_text_lines(4, 5), BTreeSet::from([3]));
+        assert_eq!(buffer.indent_text_lines(0, 1), BTreeSet::new());
+
+        // Only the lines of the openings are needed to indent the range
+        let text = buffer
+            .indent_text_lines(0, lines.len())
+            .into_iter()
+            .map(|line| (line, lines[line].to_string()))
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(
+            buffer.reindent_range(&text, 0, lines.len(), 4),
+            buffer.reindent_range(&lines[..], 0, lines.len(), 4)
+        );
+    }
+
+    #[test]
+    fn test_block_comment_untouched() {
+        let lines = &["{", "/*", "  a", "*/", "}"];
+        assert_eq!(
+            reindent("rust", lines),
+            vec![Some(0), Some(4), None, None, Some(0)]
+        );
+    }
+
+    #[test]
+    fn test_apostrophes() {
+        // Apostrophes in plain scalars don't open strings hiding the pairs after them
+        let lines = &["msg: don't [x]", "{"];
+        assert_eq!(reindent("yaml", lines), vec![Some(0), Some(0)]);
+        let lines = &["msg: don't [", "x", "]"];
+        assert_eq!(reindent("yaml", lines), vec![Some(0), Some(4), Some(0)]);
+
+        let lines = &["$(info it's) (a", "b)"];
+        assert_eq!(reindent("make", lines), vec![Some(0), Some(14)]);
+    }
+
+    #[test]
+    fn test_keeps_statement_indent() {
+        let lines = &["def a():", "    b = (1,", "         2)", "    c = 3"];
+        let buffer = ParsedBuffer::parse("python", 4, lines).unwrap();
+        assert_eq!(buffer.suggest_indent(&lines[..], 3, 4), Some(4));
+    }
+}
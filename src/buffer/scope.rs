@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+
+use mlua::IntoLua;
+
+use super::ParsedBuffer;
+use crate::parser::{MatchWithLine, Token};
+
+/// Block of lines for drawing an indent guide
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Scope {
+    /// Line opening the scope, i.e. the line with the `{` or the `def foo():`
+    pub start_line: usize,
+    /// Line closing the scope (inclusive). Indentation based scopes have no closing line,
+    /// so they end on the last line of the block
+    pub end_line: usize,
+    /// Column of the guide, which is the indentation of the start line
+    pub indent: usize,
+}
+
+impl IntoLua for Scope {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+
+        table.set("start_line", self.start_line)?;
+        table.set("end_line", self.end_line)?;
+        table.set("indent", self.indent)?;
+
+        (&table).into_lua(lua)
+    }
+}
+
+impl ParsedBuffer {
+    /// Indentation of the line, where blank lines take the max of the indentation of
+    /// the closest non-blank lines above and below them
+    pub fn effective_indent(&self, line_number: usize) -> usize {
+        self.effective_indents(line_number, line_number + 1)
+            .pop()
+            .unwrap_or(0)
+    }
+
+    /// Indentation of the lines in the range (exclusive), as in `effective_indent`, looking
+    /// for the closest non-blank lines once for each run of blank lines
+    pub fn effective_indents(&self, start_line: usize, end_line: usize) -> Vec<usize> {
+        let end_line = end_line.min(self.line_count());
+        let indent_of = |line: usize| self.indent_levels[line] as usize;
+
+        let mut indents = Vec::with_capacity(end_line.saturating_sub(start_line));
+        let mut above = (0..start_line.min(self.line_count()))
+            .rev()
+            .find(|&line| !self.is_blank(line))
+            .map(indent_of);
+        let mut blank_lines = 0;
+        for line in start_line..end_line {
+            if self.is_blank(line) {
+                blank_lines += 1;
+                continue;
+            }
+
+            let indent = indent_of(line);
+            let blank_indent = above.unwrap_or(0).max(indent);
+            indents.extend(std::iter::repeat_n(blank_indent, blank_lines));
+            indents.push(indent);
+            above = Some(indent);
+            blank_lines = 0;
+        }
+
+        if blank_lines > 0 {
+            let below = (end_line..self.line_count())
+                .find(|&line| !self.is_blank(line))
+                .map(indent_of);
+            let blank_indent = above.into_iter().chain(below).max().unwrap_or(0);
+            indents.extend(std::iter::repeat_n(blank_indent, blank_lines));
+        }
+
+        indents
+    }
+
+    /// Gets the innermost block containing the position, from either the enclosing multi-line
+    /// delimiter pair or the lines around the position with at least the same indentation
+    pub fn scope_at(&self, line_number: usize, col: usize) -> Option<Scope> {
+        self.scope_with_indent(line_number, col, self.effective_indent(line_number))
+    }
+
+    /// Gets the scope as in `scope_at`, with the effective indentation of the line
+    fn scope_with_indent(&self, line_number: usize, col: usize, indent: usize) -> Option<Scope> {
+        let pair_scope =
+            self.enclosing_multi_line_pair(line_number, col)
+                .map(|(opening, closing)| Scope {
+                    start_line: opening.line,
+                    end_line: closing.line,
+                    indent: self.effective_indent(opening.line),
+                });
+        let indent_scope = self.indent_scope(line_number, indent);
+
+        match (pair_scope, indent_scope) {
+            // Indentation within the pair, i.e. a python block or a hanging indent
+            (Some(pair_scope), Some(indent_scope))
+                if indent_scope.start_line > pair_scope.start_line =>
+            {
+                Some(indent_scope)
+            }
+            (Some(pair_scope), _) => Some(pair_scope),
+            (None, indent_scope) => indent_scope,
+        }
+    }
+
+    /// Gets the scope containing the start of the line opening the scope
+    fn parent_scope(&self, scope: &Scope) -> Option<Scope> {
+        let line = scope.start_line;
+        self.scope_at(line, self.line_layout(line).content_start)
+    }
+
+    fn enclosing_multi_line_pair(
+        &self,
+        line_number: usize,
+        col: usize,
+    ) -> Option<(MatchWithLine, MatchWithLine)> {
+        self.enclosing_openings(line_number, col, |match_| {
+            matches!(match_.token, Token::Delimiter(_, _))
+        })
+        .filter_map(|opening| self.match_pair(opening.line, opening.col))
+        .filter(|(opening, closing)| opening.line != closing.line)
+        // Position on a closing delimiter belongs to the pair outside of it
+        .find(|(_, closing)| (closing.line, closing.col) > (line_number, col))
+    }
+
+    /// Gets the lines around the line with at least the given indentation, along with the
+    /// less indented line above them
+    fn indent_scope(&self, line_number: usize, indent: usize) -> Option<Scope> {
+        if indent == 0 {
+            return None;
+        }
+
+        let start_line = (0..line_number)
+            .rev()
+            .find(|&line| !self.is_blank(line) && (self.indent_levels[line] as usize) < indent)?;
+        // Blank lines take the indentation of the non-blank line above when it's larger, so
+        // only a less indented non-blank line ends the scope
+        let end_line = (line_number + 1..self.line_count())
+            .take_while(|&line| self.is_blank(line) || self.indent_levels[line] as usize >= indent)
+            .last()
+            .unwrap_or(line_number);
+
+        Some(Scope {
+            start_line,
+            end_line,
+            indent: self.effective_indent(start_line),
+        })
+    }
+
+    /// Gets the guide columns for each line in the range (exclusive), which are the indentation
+    /// of every scope containing the start of the line, as in `scope_at`, outermost first
+    pub fn scope_guides(&self, start_line: usize, end_line: usize) -> Vec<Vec<usize>> {
+        let indents = self.effective_indents(start_line, end_line);
+        // Guides of each scope, including its own, shared by the lines in the scope
+        let mut guides_by_scope: HashMap<Scope, Vec<usize>> = HashMap::new();
+
+        (start_line..)
+            .zip(indents)
+            .map(|(line, indent)| {
+                let col = self.line_layout(line).content_start;
+                let mut scope = self.scope_with_indent(line, col, indent);
+
+                let mut unknown_scopes = vec![];
+                let mut guides = loop {
+                    let Some(current) = scope else {
+                        break vec![];
+                    };
+                    if let Some(guides) = guides_by_scope.get(&current) {
+                        break guides.clone();
+                    }
+                    unknown_scopes.push(current);
+                    scope = self.parent_scope(&current);
+                };
+                for scope in unknown_scopes.into_iter().rev() {
+                    // Nested pairs on lines with the same indentation share a guide
+                    if guides.last() != Some(&scope.indent) {
+                        guides.push(scope.indent);
+                    }
+                    guides_by_scope.insert(scope, guides.clone());
+                }
+                guides
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scope_at(filetype: &str, lines: &[&str], line: usize, col: usize) -> Option<Scope> {
+        ParsedBuffer::parse(filetype, 4, lines)
+            .unwrap()
+            .scope_at(line, col)
+    }
+
+    #[test]
+    fn test_pair_scope() {
+        let lines = &["fn a() {", "    b();", "", "    c();", "}"];
+        let scope = Some(Scope {
+            start_line: 0,
+            end_line: 4,
+            indent: 0,
+        });
+        assert_eq!(scope_at("rust", lines, 1, 4), scope);
+        assert_eq!(scope_at("rust", lines, 2, 0), scope);
+        assert_eq!(scope_at("rust", lines, 4, 0), None);
+    }
+
+    #[test]
+    fn test_indent_scope() {
+        let lines = &["def a():", "    if b:", "        c()", "", "    d()", "e()"];
+        assert_eq!(
+            scope_at("python", lines, 3, 0),
+            Some(Scope {
+                start_line: 1,
+                end_line: 3,
+                indent: 4,
+            })
+        );
+        assert_eq!(
+            scope_at("python", lines, 4, 4),
+            Some(Scope {
+                start_line: 0,
+                end_line: 4,
+                indent: 0,
+            })
+        );
+        assert_eq!(scope_at("python", lines, 5, 0), None);
+    }
+
+    #[test]
+    fn test_scope_guides() {
+        let lines = &["fn a() {", "    if b {", "        c();", "", "    }", "}"];
+        let buffer = ParsedBuffer::parse("rust", 4, lines).unwrap();
+        assert_eq!(
+            buffer.scope_guides(0, 6),
+            vec![vec![], vec![0], vec![0, 4], vec![0, 4], vec![0], vec![]]
+        );
+        assert_eq!(buffer.scope_guides(3, 5), vec![vec![0, 4], vec![0]]);
+
+        // Lines inside of a pair get its guide, even when they aren't indented
+        let lines = &["fn a() {", "b();", "}"];
+        let buffer = ParsedBuffer::parse("rust", 4, lines).unwrap();
+        assert_eq!(buffer.scope_guides(0, 3), vec![vec![], vec![0], vec![]]);
+    }
+
+    #[test]
+    fn test_effective_indents() {
+        let lines = &["def a():", "    b()", "", "", "c()", ""];
+        let buffer = ParsedBuffer::parse("python", 4, lines).unwrap();
+        assert_eq!(buffer.effective_indents(0, 6), vec![0, 4, 4, 4, 0, 0]);
+        assert_eq!(buffer.effective_indents(2, 4), vec![4, 4]);
+        assert_eq!(buffer.effective_indent(3), 4);
+    }
+}
@@ -0,0 +1,436 @@
+use mlua::IntoLua;
+
+use super::{state_after_matches, ParsedBuffer};
+use crate::error::Error;
+use crate::parser::{parse_filetype, Kind, Match, State, Token};
+
+/// Result of parsing the buffer as if text had been inserted
+#[derive(Debug, Clone, PartialEq)]
+pub struct EditSimulation {
+    /// Change in the number of unmatched openings and closings
+    pub unmatched_delta: isize,
+    /// Whether the last opening or closing overlapping the inserted text has a pair after the edit,
+    /// or `None` when the inserted text contains no openings or closings
+    pub matched: Option<bool>,
+    /// Parser state at the end of the inserted text
+    pub state: State,
+}
+
+impl IntoLua for EditSimulation {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+
+        table.set("unmatched_delta", self.unmatched_delta)?;
+        table.set("matched", self.matched)?;
+        table.set("state", self.state)?;
+
+        (&table).into_lua(lua)
+    }
+}
+
+/// Entry on the stack when matching the openings and closings of a range of lines
+enum Open<'a> {
+    /// Opening before the range, which the stored parse has paired with a closing when
+    /// `matched` is set
+    Before { token: &'a Token, matched: bool },
+    /// Opening in the range, with its line relative to the start of the range
+    Inside { line: usize, match_: &'a Match },
+}
+
+impl Open<'_> {
+    fn token(&self) -> &Token {
+        match self {
+            Open::Before { token, .. } => token,
+            Open::Inside { match_, .. } => &match_.token,
+        }
+    }
+}
+
+/// Result of matching the openings and closings in a range of lines against the openings left
+/// open before the range
+#[derive(Debug, Default)]
+struct RangeBalance {
+    /// Unmatched openings and closings in the range, minus the unmatched openings before the
+    /// range which the range closes
+    unmatched: isize,
+    /// For each token, the openings left open at the end of the range which have a closing after
+    /// it, minus the paired openings before the range which the range closes
+    balance: Vec<(Token, isize)>,
+    /// Whether the tracked opening or closing was matched in the range
+    tracked_matched: Option<bool>,
+    /// Token of the tracked opening when it's left open at the end of the range, and the number
+    /// of openings of the same token left open after it, including itself
+    tracked_open: Option<(Token, isize)>,
+}
+
+impl RangeBalance {
+    fn balance_of(&self, token: &Token) -> isize {
+        self.balance
+            .iter()
+            .find(|(balance_token, _)| balance_token == token)
+            .map(|(_, balance)| *balance)
+            .unwrap_or(0)
+    }
+
+    fn add_balance(&mut self, token: &Token, delta: isize) {
+        match self
+            .balance
+            .iter_mut()
+            .find(|(balance_token, _)| balance_token == token)
+        {
+            Some((_, balance)) => *balance += delta,
+            None => self.balance.push((token.clone(), delta)),
+        }
+    }
+}
+
+/// Matches the openings and closings in the lines with a stack starting from the openings
+/// left open before the lines, like `calculate_stack_heights` but without rematching by
+/// indentation. `has_closing_after` tells whether an opening left open at the end of the lines
+/// is closed after them
+fn match_range<'a>(
+    before: &[(&'a Token, bool)],
+    lines: &'a [Vec<Match>],
+    tracked: Option<(usize, usize)>,
+    has_closing_after: impl Fn(&Match) -> bool,
+) -> RangeBalance {
+    let mut stack = before
+        .iter()
+        .map(|&(token, matched)| Open::Before { token, matched })
+        .collect::<Vec<_>>();
+    let mut result = RangeBalance::default();
+    let is_tracked = |line: usize, match_: &Match| tracked == Some((line, match_.col));
+
+    for (line, matches) in lines.iter().enumerate() {
+        for match_ in matches {
+            match match_.kind {
+                Kind::Opening => stack.push(Open::Inside { line, match_ }),
+                Kind::Closing => {
+                    let Some(idx) = stack.iter().rposition(|open| *open.token() == match_.token)
+                    else {
+                        result.unmatched += 1;
+                        if is_tracked(line, match_) {
+                            result.tracked_matched = Some(false);
+                        }
+                        continue;
+                    };
+
+                    for skipped in stack.split_off(idx + 1) {
+                        match skipped {
+                            // Its closing after the range is left without an opening
+                            Open::Before {
+                                token,
+                                matched: true,
+                            } => {
+                                result.unmatched += 1;
+                                result.add_balance(token, -1);
+                            }
+                            Open::Before { matched: false, .. } => {}
+                            Open::Inside { line, match_ } => {
+                                result.unmatched += 1;
+                                if is_tracked(line, match_) {
+                                    result.tracked_matched = Some(false);
+                                }
+                            }
+                        }
+                    }
+                    match stack.pop().unwrap() {
+                        Open::Before {
+                            token,
+                            matched: true,
+                        } => result.add_balance(token, -1),
+                        Open::Before { matched: false, .. } => result.unmatched -= 1,
+                        Open::Inside {
+                            line: opening_line,
+                            match_: opening,
+                        } => {
+                            if is_tracked(opening_line, opening) {
+                                result.tracked_matched = Some(true);
+                            }
+                        }
+                    }
+                    if is_tracked(line, match_) {
+                        result.tracked_matched = Some(true);
+                    }
+                }
+                Kind::NonPair => {}
+            }
+        }
+    }
+
+    // Innermost first
+    let mut left_open: Vec<&Token> = vec![];
+    for open in stack.iter().rev() {
+        let Open::Inside { line, match_ } = *open else {
+            continue;
+        };
+        if has_closing_after(match_) {
+            left_open.push(&match_.token);
+            result.add_balance(&match_.token, 1);
+            if is_tracked(line, match_) {
+                let depth = left_open.iter().filter(|token| ***token == match_.token);
+                result.tracked_open = Some((match_.token.clone(), depth.count() as isize));
+            }
+        } else {
+            result.unmatched += 1;
+            if is_tracked(line, match_) {
+                result.tracked_matched = Some(false);
+            }
+        }
+    }
+
+    result
+}
+
+impl ParsedBuffer {
+    /// Gets the openings before the line which aren't closed before it, outermost first, and
+    /// whether the stored parse pairs them with a closing
+    fn open_before(&self, line_number: usize) -> Vec<(&Token, bool)> {
+        let mut closed_pairs: usize = 0;
+        let mut open = self.matches_by_line[..line_number]
+            .iter()
+            .rev()
+            .flat_map(|matches| matches.iter().rev())
+            .filter(|match_| match (match_.kind, match_.stack_height) {
+                (Kind::Closing, Some(_)) => {
+                    closed_pairs += 1;
+                    false
+                }
+                (Kind::Opening, Some(_)) if closed_pairs > 0 => {
+                    closed_pairs -= 1;
+                    false
+                }
+                (Kind::Opening, _) => true,
+                _ => false,
+            })
+            .map(|match_| (&match_.token, match_.stack_height.is_some()))
+            .collect::<Vec<_>>();
+        open.reverse();
+        open
+    }
+
+    /// Parses the buffer as if the text had been inserted at the position, without modifying
+    /// the stored parse. Only the edited lines, and the lines after them until the state
+    /// lines up with the stored parse again, are parsed
+    pub fn simulate_edit(
+        &self,
+        lines: &[&str],
+        line_number: usize,
+        col: usize,
+        text: &str,
+    ) -> Result<EditSimulation, Error> {
+        let line = lines[line_number];
+        if !line.is_char_boundary(col) {
+            return Err(Error::ColOutOfRange {
+                line: line_number,
+                col,
+                line_len: line.len(),
+            });
+        }
+
+        let new_text = format!("{}{}{}", &line[..col], text, &line[col..]);
+        let new_lines = new_text.split('\n').collect::<Vec<_>>();
+        let initial_state = line_number
+            .checked_sub(1)
+            .map(|line| self.state_by_line[line])
+            .unwrap_or(State::Normal);
+
+        let parse = |lines: &[&str], state: State| {
+            parse_filetype(&self.filetype, self.tab_width, lines, state)
+                .ok_or_else(|| Error::UnsupportedFiletype(self.filetype.clone()))
+        };
+        let mut parsed = parse(&new_lines, initial_state)?;
+
+        // Continue until the state at the end of a line lines up with the stored parse,
+        // after which the matches are the same, i.e. when inserting `/*`
+        let mut old_end_line = line_number + 1;
+        while old_end_line < self.line_count()
+            && parsed.state_by_line.last() != Some(&self.state_by_line[old_end_line - 1])
+        {
+            let state = *parsed.state_by_line.last().unwrap();
+            let next = parse(&[lines[old_end_line]], state)?;
+            parsed.matches_by_line.extend(next.matches_by_line);
+            parsed.state_by_line.extend(next.state_by_line);
+            old_end_line += 1;
+        }
+
+        // Position of the end of the inserted text, relative to the reparsed lines
+        let end_line = new_lines.len() - 1;
+        let end_col = new_lines[end_line].len() - (line.len() - col);
+
+        let tracked = parsed.matches_by_line[..=end_line]
+            .iter()
+            .enumerate()
+            .flat_map(|(line, matches)| matches.iter().map(move |match_| (line, match_)))
+            .rfind(|(line, match_)| {
+                // Overlaps the inserted text, i.e. `/*` when inserting `*` after `/`
+                match_.kind != Kind::NonPair
+                    && (*line, match_.col + match_.len()) > (0, col)
+                    && (*line, match_.col) < (end_line, end_col)
+            })
+            .map(|(line, match_)| (line, match_.col));
+
+        let line_state = match end_line {
+            0 => initial_state,
+            _ => parsed.state_by_line[end_line - 1],
+        };
+        let state = state_after_matches(line_state, &parsed.matches_by_line[end_line], end_col);
+
+        // Only the replaced lines are matched, against the openings left open before them.
+        // The closings after them are assumed to stay paired, so a change in the balance of
+        // openings and closings leaves as many pairs unmatched
+        let before = self.open_before(line_number);
+        let old = match_range(
+            &before,
+            &self.matches_by_line[line_number..old_end_line],
+            None,
+            |match_| match_.stack_height.is_some(),
+        );
+        let new = match_range(&before, &parsed.matches_by_line, tracked, |_| true);
+
+        let mut tokens: Vec<&Token> = vec![];
+        for (token, _) in old.balance.iter().chain(&new.balance) {
+            if !tokens.contains(&token) {
+                tokens.push(token);
+            }
+        }
+        let unbalanced = tokens
+            .into_iter()
+            .map(|token| (new.balance_of(token) - old.balance_of(token)).abs())
+            .sum::<isize>();
+        let matched = new.tracked_matched.or_else(|| {
+            // Closings after the edit pair with the innermost openings left open
+            let (token, depth) = new.tracked_open.as_ref()?;
+            let closings_after = before
+                .iter()
+                .filter(|(open_token, matched)| *matched && *open_token == token)
+                .count() as isize
+                + old.balance_of(token);
+            Some(*depth <= closings_after)
+        });
+
+        Ok(EditSimulation {
+            unmatched_delta: new.unmatched - old.unmatched + unbalanced,
+            matched,
+            state,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simulate(lines: &[&str], (line, col): (usize, usize), text: &str) -> EditSimulation {
+        ParsedBuffer::parse("rust", 4, lines)
+            .unwrap()
+            .simulate_edit(lines, line, col, text)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_closing() {
+        assert_eq!(
+            simulate(&["fn a(", "b"], (0, 5), ")"),
+            EditSimulation {
+                unmatched_delta: -1,
+                matched: Some(true),
+                state: State::Normal,
+            }
+        );
+        assert_eq!(
+            simulate(&["a()"], (0, 3), ")"),
+            EditSimulation {
+                unmatched_delta: 1,
+                matched: Some(false),
+                state: State::Normal,
+            }
+        );
+    }
+
+    #[test]
+    fn test_string() {
+        assert_eq!(
+            simulate(&["x = \"abc"], (0, 8), "\""),
+            EditSimulation {
+                unmatched_delta: -1,
+                matched: Some(true),
+                state: State::Normal,
+            }
+        );
+        assert_eq!(
+            simulate(&["x = abc"], (0, 4), "\""),
+            EditSimulation {
+                unmatched_delta: 1,
+                matched: Some(false),
+                state: State::InBlockString("\""),
+            }
+        );
+    }
+
+    #[test]
+    fn test_state_carries_to_following_lines() {
+        let lines = &["a(", "/", ")", "b"];
+        assert_eq!(
+            simulate(lines, (1, 1), "*"),
+            EditSimulation {
+                unmatched_delta: 2,
+                matched: Some(false),
+                state: State::InBlockComment("/*"),
+            }
+        );
+    }
+
+    #[test]
+    fn test_pairs_around_edited_line() {
+        // Closes the opening before the line, leaving its closing after the line unmatched
+        assert_eq!(
+            simulate(&["a(", "b", ")"], (1, 1), ")"),
+            EditSimulation {
+                unmatched_delta: 1,
+                matched: Some(true),
+                state: State::Normal,
+            }
+        );
+        // Takes the closing after the line from the opening before it
+        assert_eq!(
+            simulate(&["{", "", "}"], (1, 0), "{"),
+            EditSimulation {
+                unmatched_delta: 1,
+                matched: Some(true),
+                state: State::Normal,
+            }
+        );
+        assert_eq!(
+            simulate(&["a(", "b"], (1, 1), ")"),
+            EditSimulation {
+                unmatched_delta: -1,
+                matched: Some(true),
+                state: State::Normal,
+            }
+        );
+    }
+
+    #[test]
+    fn test_multi_line_insert() {
+        assert_eq!(
+            simulate(&["a(b)"], (0, 2), "{\n}"),
+            EditSimulation {
+                unmatched_delta: 0,
+                matched: Some(true),
+                state: State::Normal,
+            }
+        );
+    }
+
+    #[test]
+    fn test_stored_parse_untouched() {
+        let lines = &["a(", "b"];
+        let buffer = ParsedBuffer::parse("rust", 4, lines).unwrap();
+        let matches_by_line = buffer.matches_by_line.clone();
+        let line_layouts = buffer.line_layouts.clone();
+        buffer.simulate_edit(lines, 0, 2, ")").unwrap();
+        assert_eq!(buffer.matches_by_line, matches_by_line);
+        assert_eq!(buffer.line_layouts, line_layouts);
+    }
+}
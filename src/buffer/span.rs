@@ -0,0 +1,194 @@
+use mlua::IntoLua;
+
+use super::{ParsedBuffer, Position};
+use crate::parser::{Kind, Token};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpanRange {
+    pub span: &'static str,
+    pub block: bool,
+    /// Position of the opening
+    pub start: Position,
+    /// Position after the closing. Spans without a closing extend to the end of the line where
+    /// the parser left them, or to the end of the buffer
+    pub end: Position,
+}
+
+impl IntoLua for SpanRange {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+
+        table.set("span", self.span)?;
+        table.set("block", self.block)?;
+        table.set("start_line", self.start.line)?;
+        table.set("start_col", self.start.col)?;
+        table.set("end_line", self.end.line)?;
+        table.set("end_col", self.end.col)?;
+
+        (&table).into_lua(lua)
+    }
+}
+
+impl ParsedBuffer {
+    /// Gets the spans containing the position, from the outermost to the innermost. A position
+    /// on the opening or closing of a span counts as inside it
+    pub fn span_at(&self, line_number: usize, col: usize) -> Vec<SpanRange> {
+        let position = Position::new(line_number, col);
+        self.spans_in_range(line_number, line_number + 1)
+            .into_iter()
+            .filter(|span| span.start <= position && position < span.end)
+            .collect()
+    }
+
+    /// Gets the spans overlapping the line range (exclusive), ordered by their start so that
+    /// enclosing spans come before the spans nested in them
+    pub fn spans_in_range(&self, start_line: usize, end_line: usize) -> Vec<SpanRange> {
+        let end_line = end_line.min(self.line_count());
+        let start_line = start_line.min(end_line);
+
+        // Start from a line outside of any span, to find the openings of the spans continuing
+        // into the range
+        let mut first_line = start_line;
+        while first_line > 0 && self.state_by_line[first_line - 1].spans().top().is_some() {
+            first_line -= 1;
+        }
+
+        let mut spans = vec![];
+        // Spans which haven't been closed yet, mirroring the span stack of the parser
+        let mut stack: Vec<SpanRange> = vec![];
+        let mut finish = |span: SpanRange| {
+            if span.start.line < end_line && span.end.line >= start_line {
+                spans.push(span);
+            }
+        };
+
+        for (line, matches) in self.matches_by_line.iter().enumerate().skip(first_line) {
+            if line >= end_line && stack.is_empty() {
+                break;
+            }
+
+            for match_ in matches {
+                let (span, block) = match (&match_.token, match_.span) {
+                    (Token::InlineSpan(span, _, _), _) => (*span, false),
+                    (Token::BlockSpan(span, _, _), _) => (*span, true),
+                    (Token::Delimiter(_, _), Some(span)) => (span, true),
+                    _ => continue,
+                };
+                let position = Position::new(line, match_.col);
+                match match_.kind {
+                    Kind::Opening => stack.push(SpanRange {
+                        span,
+                        block,
+                        start: position,
+                        end: position,
+                    }),
+                    Kind::Closing => {
+                        if let Some(mut span) = stack.pop() {
+                            span.end = Position::new(line, match_.col + match_.len());
+                            finish(span);
+                        }
+                    }
+                    Kind::NonPair => {}
+                }
+            }
+
+            // Spans which the parser left at the end of the line, e.g. inline spans or
+            // emphasis at the end of a paragraph, end with the line
+            let line_end = Position::new(line, self.line_len(line));
+            let state_spans = self.state_by_line[line].spans().to_vec();
+            let mut state_spans = state_spans.iter().peekable();
+            for mut span in std::mem::take(&mut stack) {
+                if state_spans.next_if(|open| open.name == span.span).is_some() {
+                    stack.push(span);
+                } else {
+                    span.end = line_end;
+                    finish(span);
+                }
+            }
+        }
+
+        // Block spans without a closing extend to the end of the buffer
+        let buffer_end = self
+            .line_count()
+            .checked_sub(1)
+            .map(|line| Position::new(line, self.line_len(line)));
+        for mut span in stack {
+            span.end = buffer_end.unwrap_or(span.start);
+            finish(span);
+        }
+
+        spans.sort_by_key(|span| span.start);
+        spans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(spans: &[SpanRange]) -> Vec<&'static str> {
+        spans.iter().map(|span| span.span).collect()
+    }
+
+    #[test]
+    fn test_nested_spans() {
+        let buffer = ParsedBuffer::parse("markdown", 4, &["_a ~~b~~ c_ `d", "e"]).unwrap();
+
+        assert_eq!(
+            names(&buffer.span_at(0, 5)),
+            vec!["italic", "strikethrough"]
+        );
+        assert_eq!(names(&buffer.span_at(0, 9)), vec!["italic"]);
+        assert_eq!(names(&buffer.span_at(0, 11)), Vec::<&str>::new());
+        // Unclosed inline spans extend to the end of the line
+        assert_eq!(
+            buffer.span_at(0, 13),
+            vec![SpanRange {
+                span: "code",
+                block: false,
+                start: Position::new(0, 12),
+                end: Position::new(0, 14),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_raw_spans() {
+        // Code and math spans can't contain other spans
+        let buffer = ParsedBuffer::parse("markdown", 4, &["$a_1$ _b_"]).unwrap();
+        assert_eq!(names(&buffer.span_at(0, 2)), vec!["math"]);
+        assert_eq!(names(&buffer.span_at(0, 7)), vec!["italic"]);
+    }
+
+    #[test]
+    fn test_spans_in_range() {
+        let lines = ["```", "_a_", "```", "_b_", "~~c", "", "d"];
+        let buffer = ParsedBuffer::parse("markdown", 4, &lines).unwrap();
+
+        let spans = buffer.spans_in_range(1, 2);
+        assert_eq!(names(&spans), vec!["code"]);
+        assert_eq!(spans[0].start, Position::new(0, 0));
+        assert_eq!(spans[0].end, Position::new(2, 3));
+
+        assert_eq!(
+            names(&buffer.spans_in_range(2, 5)),
+            vec!["code", "italic", "strikethrough"]
+        );
+        // Emphasis ends with the paragraph
+        assert_eq!(buffer.spans_in_range(6, 7), vec![]);
+    }
+
+    #[test]
+    fn test_typst_modes() {
+        let buffer = ParsedBuffer::parse("typst", 4, &["#f[*a* $x$]", "#{", "  x", "}"]).unwrap();
+
+        assert_eq!(names(&buffer.span_at(0, 4)), vec!["content", "strong"]);
+        assert_eq!(names(&buffer.span_at(0, 8)), vec!["content", "math"]);
+        assert_eq!(names(&buffer.span_at(2, 2)), vec!["code"]);
+        assert_eq!(buffer.span_at(0, 11), vec![]);
+
+        let buffer = ParsedBuffer::parse("typst", 4, &["#f(((((((((x))))))))) $y$"]).unwrap();
+        assert_eq!(names(&buffer.span_at(0, 12)), vec!["code"]);
+        assert_eq!(names(&buffer.span_at(0, 23)), vec!["math"]);
+    }
+}
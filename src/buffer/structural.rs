@@ -0,0 +1,565 @@
+use std::str::FromStr;
+
+use super::{ParsedBuffer, Position, TextEdit};
+use crate::error::Error;
+use crate::parser::{Kind, MatchWithLine, Token};
+
+/// Structural editing operations on s-expressions, as in paredit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StructuralOp {
+    /// Moves the closing of the list after the next form, i.e. `(a|) b` -> `(a| b)`
+    SlurpForward,
+    /// Moves the opening of the list before the previous form, i.e. `a (|b)` -> `(a |b)`
+    SlurpBackward,
+    /// Moves the closing of the list before its last form, i.e. `(a| b)` -> `(a|) b`
+    BarfForward,
+    /// Moves the opening of the list after its first form, i.e. `(a |b)` -> `a (|b)`
+    BarfBackward,
+    /// Replaces the list with the form at the position, i.e. `(a |b c)` -> `b`
+    Raise,
+    /// Removes the opening and closing of the list, i.e. `(a (|b c))` -> `(a b c)`
+    Splice,
+    /// Splits the list or string at the position, i.e. `(a| b)` -> `(a) (b)`
+    Split,
+    /// Joins the lists or strings before and after the position, i.e. `(a) |(b)` -> `(a b)`
+    Join,
+    /// Swaps the forms before and after the position, i.e. `a |b` -> `b a`
+    Transpose,
+}
+
+impl FromStr for StructuralOp {
+    type Err = Error;
+
+    fn from_str(operation: &str) -> Result<Self, Self::Err> {
+        Ok(match operation {
+            "slurp_forward" => StructuralOp::SlurpForward,
+            "slurp_backward" => StructuralOp::SlurpBackward,
+            "barf_forward" => StructuralOp::BarfForward,
+            "barf_backward" => StructuralOp::BarfBackward,
+            "raise" => StructuralOp::Raise,
+            "splice" => StructuralOp::Splice,
+            "split" => StructuralOp::Split,
+            "join" => StructuralOp::Join,
+            "transpose" => StructuralOp::Transpose,
+            _ => {
+                return Err(Error::InvalidArgument {
+                    name: "operation",
+                    value: operation.to_string(),
+                })
+            }
+        })
+    }
+}
+
+/// Form in a list, either an atom or a pair (list, string) along with any prefix attached to it,
+/// such as `'(a b)` or `#{}`
+#[derive(Debug, Clone, PartialEq)]
+struct Sexp {
+    start: Position,
+    /// Exclusive
+    end: Position,
+    pair: Option<(MatchWithLine, MatchWithLine)>,
+}
+
+fn start_of(match_: &MatchWithLine) -> Position {
+    Position::new(match_.line, match_.col)
+}
+
+fn end_of(match_: &MatchWithLine) -> Position {
+    Position::new(match_.line, match_.col + match_.len())
+}
+
+fn delete(start: Position, end: Position) -> TextEdit {
+    replace(start, end, String::new())
+}
+
+fn insert(pos: Position, text: String) -> TextEdit {
+    replace(pos, pos, text)
+}
+
+fn replace(start: Position, end: Position, text: String) -> TextEdit {
+    TextEdit {
+        start_line: start.line,
+        start_col: start.col,
+        end_line: end.line,
+        end_col: end.col,
+        text,
+    }
+}
+
+fn is_list(token: &Token) -> bool {
+    matches!(token, Token::Delimiter(_, _))
+}
+
+fn is_list_or_string(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Delimiter(_, _) | Token::String(_) | Token::BlockString(_, _)
+    )
+}
+
+impl ParsedBuffer {
+    /// Gets the edits for the structural editing operation at the position, ordered from the
+    /// end of the buffer so they may be applied one by one. The position is treated as a cursor
+    /// between characters, so a position on an opening is outside of its list. Forms are
+    /// found from the parsed pairs, so strings, comments and character literals such as `\(`
+    /// are respected
+    pub fn structural_edit(
+        &self,
+        lines: &[&str],
+        line_number: usize,
+        col: usize,
+        operation: StructuralOp,
+    ) -> Option<Vec<TextEdit>> {
+        let pos = Position::new(line_number, col);
+        match operation {
+            StructuralOp::SlurpForward => self.slurp_forward(lines, pos),
+            StructuralOp::SlurpBackward => self.slurp_backward(lines, pos),
+            StructuralOp::BarfForward => self.barf_forward(lines, pos),
+            StructuralOp::BarfBackward => self.barf_backward(lines, pos),
+            StructuralOp::Raise => self.raise(lines, pos),
+            StructuralOp::Splice => self.splice(pos),
+            StructuralOp::Split => self.split(lines, pos),
+            StructuralOp::Join => self.join(lines, pos),
+            StructuralOp::Transpose => self.transpose(lines, pos),
+        }
+    }
+
+    fn slurp_forward(&self, lines: &[&str], pos: Position) -> Option<Vec<TextEdit>> {
+        let (opening, closing) = self.containing_pair(pos, is_list)?;
+        let next = self
+            .siblings_of(lines, &opening)
+            .into_iter()
+            .find(|sexp| sexp.start >= end_of(&closing))?;
+
+        // Drop the whitespace when slurping into an empty list
+        let delete_end = if self.children_of(lines, &opening, &closing).is_empty() {
+            next.start
+        } else {
+            end_of(&closing)
+        };
+        Some(vec![
+            insert(next.end, closing.token.closing()?.to_string()),
+            delete(start_of(&closing), delete_end),
+        ])
+    }
+
+    fn slurp_backward(&self, lines: &[&str], pos: Position) -> Option<Vec<TextEdit>> {
+        let (opening, closing) = self.containing_pair(pos, is_list)?;
+        let prev = self
+            .siblings_of(lines, &opening)
+            .into_iter()
+            .rfind(|sexp| sexp.end <= start_of(&opening))?;
+
+        let delete_start = if self.children_of(lines, &opening, &closing).is_empty() {
+            prev.end
+        } else {
+            start_of(&opening)
+        };
+        Some(vec![
+            delete(delete_start, end_of(&opening)),
+            insert(prev.start, opening.token.opening().to_string()),
+        ])
+    }
+
+    fn barf_forward(&self, lines: &[&str], pos: Position) -> Option<Vec<TextEdit>> {
+        let (opening, closing) = self.containing_pair(pos, is_list)?;
+        let children = self.children_of(lines, &opening, &closing);
+        let new_closing_pos = match children.len() {
+            0 => return None,
+            1 => end_of(&opening),
+            len => children[len - 2].end,
+        };
+
+        let mut text = closing.token.closing()?.to_string();
+        if !self
+            .char_at(lines, new_closing_pos)
+            .is_some_and(char::is_whitespace)
+        {
+            text.push(' ');
+        }
+        Some(vec![
+            delete(start_of(&closing), end_of(&closing)),
+            insert(new_closing_pos, text),
+        ])
+    }
+
+    fn barf_backward(&self, lines: &[&str], pos: Position) -> Option<Vec<TextEdit>> {
+        let (opening, closing) = self.containing_pair(pos, is_list)?;
+        let children = self.children_of(lines, &opening, &closing);
+        let new_opening_pos = match children.len() {
+            0 => return None,
+            1 => start_of(&closing),
+            _ => children[1].start,
+        };
+
+        let mut text = opening.token.opening().to_string();
+        if !self
+            .char_before(lines, new_opening_pos)
+            .is_some_and(char::is_whitespace)
+        {
+            text.insert(0, ' ');
+        }
+        Some(vec![
+            insert(new_opening_pos, text),
+            delete(start_of(&opening), end_of(&opening)),
+        ])
+    }
+
+    fn raise(&self, lines: &[&str], pos: Position) -> Option<Vec<TextEdit>> {
+        let (opening, closing) = self.containing_pair(pos, is_list)?;
+        let sexp = self
+            .children_of(lines, &opening, &closing)
+            .into_iter()
+            .find(|sexp| sexp.end > pos)?;
+
+        Some(vec![replace(
+            start_of(&opening),
+            end_of(&closing),
+            self.text_between(lines, sexp.start, sexp.end),
+        )])
+    }
+
+    fn splice(&self, pos: Position) -> Option<Vec<TextEdit>> {
+        let (opening, closing) = self.containing_pair(pos, is_list)?;
+        Some(vec![
+            delete(start_of(&closing), end_of(&closing)),
+            delete(start_of(&opening), end_of(&opening)),
+        ])
+    }
+
+    fn split(&self, lines: &[&str], pos: Position) -> Option<Vec<TextEdit>> {
+        let (opening, closing) = self.containing_pair(pos, is_list_or_string)?;
+        // Strings only have an opening
+        let closing_text = closing.token.closing().unwrap_or(opening.token.opening());
+        let text = format!("{} {}", closing_text, opening.token.opening());
+        if !is_list(&opening.token) {
+            return Some(vec![insert(pos, text)]);
+        }
+
+        // (a |  b) -> (a) (b)
+        let line = lines[pos.line];
+        let min_col = if opening.line == pos.line {
+            opening.col + opening.len()
+        } else {
+            0
+        };
+        let max_col = if closing.line == pos.line {
+            closing.col
+        } else {
+            line.len()
+        };
+        let start = min_col + line[min_col..pos.col].trim_end().len();
+        let end = max_col - line[pos.col..max_col].trim_start().len();
+        Some(vec![replace(
+            Position::new(pos.line, start),
+            Position::new(pos.line, end),
+            text,
+        )])
+    }
+
+    fn join(&self, lines: &[&str], pos: Position) -> Option<Vec<TextEdit>> {
+        let (prev, next) = self.sexps_around(lines, pos)?;
+        let (Some((_, prev_closing)), Some((next_opening, _))) = (prev.pair, next.pair) else {
+            return None;
+        };
+        // Joining would leave the prefix of the second form inside the first
+        if prev_closing.token != next_opening.token || next.start != start_of(&next_opening) {
+            return None;
+        }
+
+        if is_list(&next_opening.token) {
+            Some(vec![
+                delete(start_of(&next_opening), end_of(&next_opening)),
+                delete(start_of(&prev_closing), end_of(&prev_closing)),
+            ])
+        } else {
+            // "a" "b" -> "ab"
+            Some(vec![delete(start_of(&prev_closing), end_of(&next_opening))])
+        }
+    }
+
+    fn transpose(&self, lines: &[&str], pos: Position) -> Option<Vec<TextEdit>> {
+        let (prev, next) = self.sexps_around(lines, pos)?;
+        Some(vec![
+            replace(
+                next.start,
+                next.end,
+                self.text_between(lines, prev.start, prev.end),
+            ),
+            replace(
+                prev.start,
+                prev.end,
+                self.text_between(lines, next.start, next.end),
+            ),
+        ])
+    }
+
+    /// Gets the innermost pair accepted by the predicate with the position between its opening
+    /// and closing
+    fn containing_pair(
+        &self,
+        pos: Position,
+        predicate: fn(&Token) -> bool,
+    ) -> Option<(MatchWithLine, MatchWithLine)> {
+        self.enclosing_pairs(pos.line, pos.col)
+            .find(|(opening, closing)| {
+                predicate(&opening.token) && end_of(opening) <= pos && pos <= start_of(closing)
+            })
+    }
+
+    /// Gets the last form ending at or before the position and the first form starting at or
+    /// after it, in the innermost list containing the position
+    fn sexps_around(&self, lines: &[&str], pos: Position) -> Option<(Sexp, Sexp)> {
+        let siblings = match self.containing_pair(pos, is_list) {
+            Some((opening, closing)) => self.children_of(lines, &opening, &closing),
+            None => self.sexps_between(lines, Position::new(0, 0), self.buffer_end()),
+        };
+        let next_idx = siblings.iter().position(|sexp| sexp.start >= pos)?;
+        let prev = siblings[..next_idx]
+            .last()
+            .filter(|sexp| sexp.end <= pos)?
+            .clone();
+        Some((prev, siblings[next_idx].clone()))
+    }
+
+    fn children_of(
+        &self,
+        lines: &[&str],
+        opening: &MatchWithLine,
+        closing: &MatchWithLine,
+    ) -> Vec<Sexp> {
+        self.sexps_between(lines, end_of(opening), start_of(closing))
+    }
+
+    /// Gets the forms in the list containing the opening, or the top level forms
+    fn siblings_of(&self, lines: &[&str], opening: &MatchWithLine) -> Vec<Sexp> {
+        match self.containing_pair(start_of(opening), is_list) {
+            Some((parent_opening, parent_closing)) => {
+                self.children_of(lines, &parent_opening, &parent_closing)
+            }
+            None => self.sexps_between(lines, Position::new(0, 0), self.buffer_end()),
+        }
+    }
+
+    /// Gets the forms in the range, skipping whitespace and comments
+    fn sexps_between(&self, lines: &[&str], start: Position, end: Position) -> Vec<Sexp> {
+        let mut sexps = vec![];
+        let mut pos = start;
+        let mut prefix_start = None;
+
+        while pos < end {
+            let line = lines[pos.line];
+            let Some(char) = line[pos.col..].chars().next() else {
+                pos = Position::new(pos.line + 1, 0);
+                continue;
+            };
+            if char.is_whitespace() {
+                pos.col += char.len_utf8();
+                continue;
+            }
+
+            let line_matches = &self.matches_by_line[pos.line];
+            match line_matches.iter().find(|match_| match_.col == pos.col) {
+                Some(match_) if matches!(match_.token, Token::LineComment(_)) => {
+                    pos = Position::new(pos.line + 1, 0);
+                }
+                Some(match_) if match_.kind == Kind::Opening => {
+                    let Some((opening, closing)) = self.match_pair(pos.line, pos.col) else {
+                        pos.col += match_.len();
+                        continue;
+                    };
+                    let pair_start = prefix_start.take().unwrap_or(pos);
+                    pos = end_of(&closing);
+                    if !matches!(opening.token, Token::BlockComment(_, _)) {
+                        sexps.push(Sexp {
+                            start: pair_start,
+                            end: pos,
+                            pair: Some((opening, closing)),
+                        });
+                    }
+                }
+                // Unmatched closing
+                Some(match_) => pos.col += match_.len(),
+                None => {
+                    let max_col = if pos.line == end.line {
+                        end.col
+                    } else {
+                        line.len()
+                    };
+                    let next_match = line_matches
+                        .iter()
+                        .find(|match_| match_.col > pos.col && match_.col < max_col);
+                    let atom_end = line[pos.col..max_col]
+                        .find(char::is_whitespace)
+                        .map(|idx| pos.col + idx)
+                        .unwrap_or(max_col)
+                        .min(next_match.map(|match_| match_.col).unwrap_or(max_col));
+
+                    // Prefix such as `'` or `#` directly before a list or string
+                    let is_prefix = next_match.is_some_and(|match_| {
+                        match_.col == atom_end
+                            && match_.kind == Kind::Opening
+                            && !matches!(match_.token, Token::BlockComment(_, _))
+                    });
+                    if is_prefix {
+                        prefix_start.get_or_insert(pos);
+                    } else {
+                        sexps.push(Sexp {
+                            start: prefix_start.take().unwrap_or(pos),
+                            end: Position::new(pos.line, atom_end),
+                            pair: None,
+                        });
+                    }
+                    pos.col = atom_end;
+                }
+            }
+        }
+
+        sexps
+    }
+
+    fn buffer_end(&self) -> Position {
+        let last_line = self.line_count().saturating_sub(1);
+        Position::new(last_line, self.line_len(last_line))
+    }
+
+    fn char_at(&self, lines: &[&str], pos: Position) -> Option<char> {
+        lines.get(pos.line)?.get(pos.col..)?.chars().next()
+    }
+
+    fn char_before(&self, lines: &[&str], pos: Position) -> Option<char> {
+        lines.get(pos.line)?.get(..pos.col)?.chars().next_back()
+    }
+
+    /// Gets the text in the range, with lines joined by `\n`
+    fn text_between(&self, lines: &[&str], start: Position, end: Position) -> String {
+        if start.line == end.line {
+            return lines[start.line][start.col..end.col].to_string();
+        }
+        let mut text = lines[start.line][start.col..].to_string();
+        for line in &lines[start.line + 1..end.line] {
+            text.push('\n');
+            text.push_str(line);
+        }
+        text.push('\n');
+        text.push_str(&lines[end.line][..end.col]);
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::apply_edits;
+
+    /// Runs the operation at the `|` in the text
+    fn edit(filetype: &str, text: &str, operation: &str) -> Option<String> {
+        let (line, col) = text
+            .split('\n')
+            .enumerate()
+            .find_map(|(line, text)| text.find('|').map(|col| (line, col)))
+            .unwrap();
+        let text = text.replacen('|', "", 1);
+        let lines = text.split('\n').collect::<Vec<_>>();
+        ParsedBuffer::parse(filetype, 4, &lines)
+            .unwrap()
+            .structural_edit(&lines, line, col, operation.parse().unwrap())
+            .map(|edits| apply_edits(&lines, &edits))
+    }
+
+    #[test]
+    fn test_slurp() {
+        assert_eq!(
+            edit("clojure", "(a (b|) c d)", "slurp_forward").as_deref(),
+            Some("(a (b c) d)")
+        );
+        assert_eq!(
+            edit("clojure", "(a (|) \"c d\")", "slurp_forward").as_deref(),
+            Some("(a (\"c d\"))")
+        );
+        assert_eq!(
+            edit("clojure", "(a '{:b 1} (|c))", "slurp_backward").as_deref(),
+            Some("(a ('{:b 1} c))")
+        );
+        assert_eq!(edit("clojure", "(a (b|))", "slurp_forward"), None);
+    }
+
+    #[test]
+    fn test_barf() {
+        assert_eq!(
+            edit("clojure", "(a (b| c d))", "barf_forward").as_deref(),
+            Some("(a (b c) d)")
+        );
+        assert_eq!(
+            edit("clojure", "(|a)", "barf_forward").as_deref(),
+            Some("() a")
+        );
+        assert_eq!(
+            edit("clojure", "(a (b| c d))", "barf_backward").as_deref(),
+            Some("(a b (c d))")
+        );
+        assert_eq!(
+            edit("clojure", "(a|)", "barf_backward").as_deref(),
+            Some("a ()")
+        );
+    }
+
+    #[test]
+    fn test_raise_and_splice() {
+        assert_eq!(
+            edit("clojure", "(if x (a |(b c) d))", "raise").as_deref(),
+            Some("(if x (b c))")
+        );
+        assert_eq!(
+            edit("clojure", "(a (b |c))", "splice").as_deref(),
+            Some("(a b c)")
+        );
+    }
+
+    #[test]
+    fn test_split_and_join() {
+        assert_eq!(
+            edit("clojure", "(a b |  c)", "split").as_deref(),
+            Some("(a b) (c)")
+        );
+        assert_eq!(
+            edit("clojure", "\"a b|c\"", "split").as_deref(),
+            Some("\"a b\" \"c\"")
+        );
+        assert_eq!(
+            edit("clojure", "[a b] |[c]", "join").as_deref(),
+            Some("[a b c]")
+        );
+        assert_eq!(
+            edit("clojure", "\"ab\" |\"c\"", "join").as_deref(),
+            Some("\"abc\"")
+        );
+        assert_eq!(edit("clojure", "(a) |[c]", "join"), None);
+    }
+
+    #[test]
+    fn test_transpose() {
+        assert_eq!(
+            edit("clojure", "(f (a b) |\"c\")", "transpose").as_deref(),
+            Some("(f \"c\" (a b))")
+        );
+        assert_eq!(
+            edit("scheme", "(a ; x\n |b)", "transpose").as_deref(),
+            Some("(b ; x\n a)")
+        );
+    }
+
+    #[test]
+    fn test_char_literals() {
+        // `\(` is a character, not a list
+        assert_eq!(
+            edit("clojure", "(a (b|) \\( c)", "slurp_forward").as_deref(),
+            Some("(a (b \\() c)")
+        );
+        assert_eq!(
+            edit("scheme", "(a (b|) #\\) c)", "slurp_forward").as_deref(),
+            Some("(a (b #\\)) c)")
+        );
+    }
+}
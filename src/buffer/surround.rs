@@ -0,0 +1,301 @@
+use mlua::{FromLua, IntoLua};
+
+use super::{ParsedBuffer, TextObjectKind};
+use crate::parser::MatchWithLine;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SurroundOptions {
+    /// Removes the whitespace after the opening and before the closing on the same lines
+    pub trim_padding: bool,
+    /// Removes the line of the closing when nothing else is left on it, only when deleting
+    pub remove_lone_closing: bool,
+}
+
+impl FromLua for SurroundOptions {
+    fn from_lua(value: mlua::Value, lua: &mlua::Lua) -> mlua::Result<Self> {
+        let table = mlua::Table::from_lua(value, lua)?;
+        Ok(Self {
+            trim_padding: table
+                .get::<Option<bool>>("trim_padding")?
+                .unwrap_or_default(),
+            remove_lone_closing: table
+                .get::<Option<bool>>("remove_lone_closing")?
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// Replaces the text in the range, 0-indexed with an exclusive end, like
+/// `nvim_buf_set_text`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextEdit {
+    pub start_line: usize,
+    pub start_col: usize,
+    pub end_line: usize,
+    pub end_col: usize,
+    pub text: String,
+}
+
+impl IntoLua for TextEdit {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+
+        table.set("start_line", self.start_line)?;
+        table.set("start_col", self.start_col)?;
+        table.set("end_line", self.end_line)?;
+        table.set("end_col", self.end_col)?;
+        table.set("text", self.text)?;
+
+        (&table).into_lua(lua)
+    }
+}
+
+/// Applies the edits in order to the lines, returning the text joined by newlines
+#[cfg(test)]
+pub(crate) fn apply_edits(lines: &[&str], edits: &[TextEdit]) -> String {
+    let mut text = lines.join("\n");
+    for edit in edits {
+        let offset = |line: usize, col: usize| {
+            text.split('\n')
+                .take(line)
+                .map(|line| line.len() + 1)
+                .sum::<usize>()
+                + col
+        };
+        let range = offset(edit.start_line, edit.start_col)..offset(edit.end_line, edit.end_col);
+        text.replace_range(range, &edit.text);
+    }
+    text
+}
+
+impl ParsedBuffer {
+    /// Gets the edits removing the opening and closing of the innermost pair of the given kind
+    /// containing the position, or of the pair from `surrounding_match_pair` without a kind.
+    /// The edits are ordered from the end of the buffer, so they may be applied one by one
+    pub fn surround_delete(
+        &self,
+        lines: &[&str],
+        line_number: usize,
+        col: usize,
+        target: Option<&TextObjectKind>,
+        options: SurroundOptions,
+    ) -> Option<Vec<TextEdit>> {
+        let (opening, closing) = self.surround_target(line_number, col, target)?;
+        let (opening_edit, mut closing_edit) =
+            self.surround_edits(lines, &opening, &closing, options);
+
+        // {
+        //   foo
+        // }|
+        if options.remove_lone_closing && opening.line != closing.line {
+            let layout = self.line_layout(closing.line);
+            let lone = layout.content_start >= closing_edit.start_col
+                && layout.content_end <= closing_edit.end_col;
+            if lone {
+                closing_edit.start_line = closing.line - 1;
+                closing_edit.start_col = self.line_len(closing.line - 1);
+                closing_edit.end_col = self.line_len(closing.line);
+            }
+        }
+
+        Some(vec![closing_edit, opening_edit])
+    }
+
+    /// Gets the edits replacing the opening and closing of the pair, found the same way as in
+    /// `surround_delete`, with the new opening and closing
+    #[allow(clippy::too_many_arguments)]
+    pub fn surround_change(
+        &self,
+        lines: &[&str],
+        line_number: usize,
+        col: usize,
+        target: Option<&TextObjectKind>,
+        new_opening: &str,
+        new_closing: &str,
+        options: SurroundOptions,
+    ) -> Option<Vec<TextEdit>> {
+        let (opening, closing) = self.surround_target(line_number, col, target)?;
+        let (mut opening_edit, mut closing_edit) =
+            self.surround_edits(lines, &opening, &closing, options);
+        opening_edit.text = new_opening.to_string();
+        closing_edit.text = new_closing.to_string();
+
+        Some(vec![closing_edit, opening_edit])
+    }
+
+    fn surround_target(
+        &self,
+        line_number: usize,
+        col: usize,
+        target: Option<&TextObjectKind>,
+    ) -> Option<(MatchWithLine, MatchWithLine)> {
+        match target {
+            Some(kind) => self
+                .enclosing_pairs(line_number, col)
+                .find(|(opening, _)| kind.matches(&opening.token)),
+            None => self.surrounding_match_pair(line_number, col),
+        }
+    }
+
+    /// Gets the edits deleting the opening and closing, including the padding when trimmed
+    fn surround_edits(
+        &self,
+        lines: &[&str],
+        opening: &MatchWithLine,
+        closing: &MatchWithLine,
+        options: SurroundOptions,
+    ) -> (TextEdit, TextEdit) {
+        let mut opening_end = opening.col + opening.len();
+        let mut closing_start = closing.col;
+
+        if options.trim_padding {
+            let line = lines[opening.line];
+            let limit = if opening.line == closing.line {
+                closing.col
+            } else {
+                line.len()
+            };
+            opening_end +=
+                line[opening_end..limit].len() - line[opening_end..limit].trim_start().len();
+
+            let line = lines[closing.line];
+            let limit = if opening.line == closing.line {
+                opening_end
+            } else {
+                0
+            };
+            closing_start -=
+                line[limit..closing_start].len() - line[limit..closing_start].trim_end().len();
+        }
+
+        (
+            TextEdit {
+                start_line: opening.line,
+                start_col: opening.col,
+                end_line: opening.line,
+                end_col: opening_end,
+                text: String::new(),
+            },
+            TextEdit {
+                start_line: closing.line,
+                start_col: closing_start,
+                end_line: closing.line,
+                end_col: closing.col + closing.len(),
+                text: String::new(),
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delete(
+        lines: &[&str],
+        (line, col): (usize, usize),
+        target: Option<&str>,
+        options: SurroundOptions,
+    ) -> Option<Vec<String>> {
+        let target = target.map(|target| target.parse().unwrap());
+        ParsedBuffer::parse("rust", 4, lines)
+            .unwrap()
+            .surround_delete(lines, line, col, target.as_ref(), options)
+            .map(|edits| {
+                apply_edits(lines, &edits)
+                    .split('\n')
+                    .map(|line| line.to_string())
+                    .collect()
+            })
+    }
+
+    #[test]
+    fn test_delete() {
+        let lines = &["f(a, [b], c)"];
+        assert_eq!(
+            delete(lines, (0, 6), None, SurroundOptions::default()),
+            Some(vec!["f(a, b, c)".to_string()])
+        );
+        assert_eq!(
+            delete(lines, (0, 6), Some("("), SurroundOptions::default()),
+            Some(vec!["fa, [b], c".to_string()])
+        );
+        assert_eq!(
+            delete(lines, (0, 0), Some("{"), SurroundOptions::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_delete_trim_padding() {
+        let options = SurroundOptions {
+            trim_padding: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            delete(&["x = ( a )"], (0, 6), None, options),
+            Some(vec!["x = a".to_string()])
+        );
+        assert_eq!(
+            delete(&["x = (  )"], (0, 6), None, options),
+            Some(vec!["x = ".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_delete_lone_closing() {
+        let lines = &["if a {", "    b", "}", "c"];
+        let options = SurroundOptions {
+            remove_lone_closing: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            delete(lines, (1, 4), Some("{"), options),
+            Some(vec![
+                "if a ".to_string(),
+                "    b".to_string(),
+                "c".to_string()
+            ])
+        );
+        assert_eq!(
+            delete(lines, (1, 4), Some("{"), SurroundOptions::default()),
+            Some(vec![
+                "if a ".to_string(),
+                "    b".to_string(),
+                "".to_string(),
+                "c".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_delete_string() {
+        // Escaped quotes don't end the string
+        let lines = &[r#"x = "a \" b";"#];
+        assert_eq!(
+            delete(lines, (0, 6), Some("string"), SurroundOptions::default()),
+            Some(vec![r#"x = a \" b;"#.to_string()])
+        );
+    }
+
+    #[test]
+    fn test_change() {
+        let lines = &["x = ( a )"];
+        let edits = ParsedBuffer::parse("rust", 4, lines)
+            .unwrap()
+            .surround_change(
+                lines,
+                0,
+                6,
+                Some(&TextObjectKind::Delimiter),
+                "[",
+                "]",
+                SurroundOptions {
+                    trim_padding: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(apply_edits(lines, &edits), "x = [a]");
+    }
+}
@@ -0,0 +1,328 @@
+use std::str::FromStr;
+
+use mlua::IntoLua;
+
+use super::ParsedBuffer;
+use crate::error::Error;
+use crate::parser::{Kind, MatchWithLine, Token};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextObjectKind {
+    /// Pair where either the opening or closing is the given text, i.e. `(`, `)` or `"`
+    Token(String),
+    /// Any delimiter pair
+    Delimiter,
+    /// Strings and block strings
+    String,
+    BlockComment,
+    /// Inline or block span with the given name
+    Span(String),
+}
+
+impl TextObjectKind {
+    pub fn matches(&self, token: &Token) -> bool {
+        match (self, token) {
+            (TextObjectKind::Token(text), token) => {
+                token.opening() == text || token.closing() == Some(text.as_str())
+            }
+            (TextObjectKind::Delimiter, Token::Delimiter(_, _)) => true,
+            (TextObjectKind::String, Token::String(_) | Token::BlockString(_, _)) => true,
+            (TextObjectKind::BlockComment, Token::BlockComment(_, _)) => true,
+            (
+                TextObjectKind::Span(name),
+                Token::InlineSpan(span, _, _) | Token::BlockSpan(span, _, _),
+            ) => name == span,
+            _ => false,
+        }
+    }
+}
+
+/// Parses `delimiter`, `string`, `block_comment`, `span:<name>`, or otherwise treats the text as
+/// the opening or closing of a pair
+impl FromStr for TextObjectKind {
+    type Err = Error;
+
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        Ok(match kind {
+            "" => {
+                return Err(Error::InvalidArgument {
+                    name: "kind",
+                    value: kind.to_string(),
+                })
+            }
+            "delimiter" => TextObjectKind::Delimiter,
+            "string" => TextObjectKind::String,
+            "block_comment" => TextObjectKind::BlockComment,
+            _ => match kind.strip_prefix("span:") {
+                Some(span) => TextObjectKind::Span(span.to_string()),
+                None => TextObjectKind::Token(kind.to_string()),
+            },
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextObjectMode {
+    /// Excludes the opening and closing, along with the whitespace after the opening and
+    /// before the closing on multi-line pairs
+    Inner,
+    /// Includes the opening and closing
+    Around,
+}
+
+impl FromStr for TextObjectMode {
+    type Err = Error;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "inner" | "i" => Ok(TextObjectMode::Inner),
+            "around" | "a" => Ok(TextObjectMode::Around),
+            _ => Err(Error::InvalidArgument {
+                name: "mode",
+                value: mode.to_string(),
+            }),
+        }
+    }
+}
+
+/// Range of a text object, 0-indexed with an exclusive end
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextObject {
+    pub start_line: usize,
+    pub start_col: usize,
+    pub end_line: usize,
+    pub end_col: usize,
+}
+
+impl IntoLua for TextObject {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+
+        table.set("start_line", self.start_line)?;
+        table.set("start_col", self.start_col)?;
+        table.set("end_line", self.end_line)?;
+        table.set("end_col", self.end_col)?;
+
+        (&table).into_lua(lua)
+    }
+}
+
+impl ParsedBuffer {
+    /// Gets every matched pair (delimiters, strings, comments and spans) containing the position,
+    /// innermost first. A position on the opening or closing of a pair counts as inside it
+    pub fn enclosing_pairs(
+        &self,
+        line_number: usize,
+        col: usize,
+    ) -> impl Iterator<Item = (MatchWithLine, MatchWithLine)> + '_ {
+        let pair_at_pos = self
+            .match_at(line_number, col)
+            .filter(|match_| match_.kind != Kind::NonPair && match_.stack_height.is_some())
+            .and_then(|match_| self.match_pair(line_number, match_.col));
+
+        // Start from the opening, so that the pair at the position isn't skipped over
+        let (line_number, col) = pair_at_pos
+            .as_ref()
+            .map(|(opening, _)| (opening.line, opening.col))
+            .unwrap_or((line_number, col));
+
+        pair_at_pos.into_iter().chain(
+            self.enclosing_openings(line_number, col, |match_| match_.kind != Kind::NonPair)
+                .filter_map(|opening| self.match_pair(opening.line, opening.col)),
+        )
+    }
+
+    /// Gets the range of the `count`-th (starting at 1) pair of the given kind containing the
+    /// position
+    pub fn text_object(
+        &self,
+        line_number: usize,
+        col: usize,
+        kind: &TextObjectKind,
+        mode: TextObjectMode,
+        count: usize,
+    ) -> Option<TextObject> {
+        let (opening, closing) = self
+            .enclosing_pairs(line_number, col)
+            .filter(|(opening, _)| kind.matches(&opening.token))
+            .nth(count.max(1) - 1)?;
+
+        Some(self.pair_range(&opening, &closing, mode))
+    }
+
+    pub(super) fn pair_range(
+        &self,
+        opening: &MatchWithLine,
+        closing: &MatchWithLine,
+        mode: TextObjectMode,
+    ) -> TextObject {
+        match mode {
+            TextObjectMode::Around => TextObject {
+                start_line: opening.line,
+                start_col: opening.col,
+                end_line: closing.line,
+                end_col: closing.col + closing.len(),
+            },
+            TextObjectMode::Inner => self.inner_range(opening, closing),
+        }
+    }
+
+    fn inner_range(&self, opening: &MatchWithLine, closing: &MatchWithLine) -> TextObject {
+        let mut start = (opening.line, opening.col + opening.len());
+        let mut end = (closing.line, closing.col);
+
+        if opening.line != closing.line {
+            // {|
+            //   foo
+            // }
+            if self.line_layout(opening.line).content_end <= start.1 {
+                start = (opening.line + 1, 0);
+            }
+            // {
+            //   foo|
+            // }
+            if self.line_layout(closing.line).content_start >= closing.col {
+                end = (closing.line - 1, self.line_len(closing.line - 1));
+            }
+            // Nothing but whitespace inside
+            if start > end {
+                end = start;
+            }
+        }
+
+        TextObject {
+            start_line: start.0,
+            start_col: start.1,
+            end_line: end.0,
+            end_col: end.1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_object(
+        filetype: &str,
+        lines: &[&str],
+        (line, col): (usize, usize),
+        kind: &str,
+        mode: TextObjectMode,
+        count: usize,
+    ) -> Option<((usize, usize), (usize, usize))> {
+        ParsedBuffer::parse(filetype, 4, lines)
+            .unwrap()
+            .text_object(line, col, &kind.parse().unwrap(), mode, count)
+            .map(|obj| ((obj.start_line, obj.start_col), (obj.end_line, obj.end_col)))
+    }
+
+    #[test]
+    fn test_delimiter() {
+        let lines = &["f(a, [b, c], d)"];
+        assert_eq!(
+            text_object("rust", lines, (0, 6), "(", TextObjectMode::Inner, 1),
+            Some(((0, 2), (0, 14)))
+        );
+        assert_eq!(
+            text_object("rust", lines, (0, 6), ")", TextObjectMode::Around, 1),
+            Some(((0, 1), (0, 15)))
+        );
+        assert_eq!(
+            text_object("rust", lines, (0, 6), "delimiter", TextObjectMode::Inner, 1),
+            Some(((0, 6), (0, 10)))
+        );
+        assert_eq!(
+            text_object("rust", lines, (0, 6), "delimiter", TextObjectMode::Inner, 2),
+            Some(((0, 2), (0, 14)))
+        );
+        assert_eq!(
+            text_object("rust", lines, (0, 5), "[", TextObjectMode::Around, 1),
+            Some(((0, 5), (0, 11)))
+        );
+        assert_eq!(
+            text_object("rust", lines, (0, 6), "delimiter", TextObjectMode::Inner, 3),
+            None
+        );
+    }
+
+    #[test]
+    fn test_multi_line_inner() {
+        let lines = &["fn main() {", "    foo();", "}"];
+        assert_eq!(
+            text_object("rust", lines, (1, 4), "{", TextObjectMode::Inner, 1),
+            Some(((1, 0), (1, 10)))
+        );
+        assert_eq!(
+            text_object(
+                "rust",
+                &["{ a", "  b }"],
+                (1, 2),
+                "{",
+                TextObjectMode::Inner,
+                1
+            ),
+            Some(((0, 1), (1, 4)))
+        );
+        assert_eq!(
+            text_object("rust", &["{", "}"], (0, 0), "{", TextObjectMode::Inner, 1),
+            Some(((1, 0), (1, 0)))
+        );
+    }
+
+    #[test]
+    fn test_string_and_comment() {
+        let lines = &["x = \"a (b) \\\" c\" /* d */"];
+        assert_eq!(
+            text_object("c", lines, (0, 7), "string", TextObjectMode::Inner, 1),
+            Some(((0, 5), (0, 15)))
+        );
+        assert_eq!(
+            text_object("c", lines, (0, 7), "\"", TextObjectMode::Around, 1),
+            Some(((0, 4), (0, 16)))
+        );
+        assert_eq!(
+            text_object(
+                "c",
+                lines,
+                (0, 20),
+                "block_comment",
+                TextObjectMode::Around,
+                1
+            ),
+            Some(((0, 17), (0, 24)))
+        );
+        assert_eq!(
+            text_object(
+                "c",
+                lines,
+                (0, 22),
+                "block_comment",
+                TextObjectMode::Inner,
+                1
+            ),
+            Some(((0, 19), (0, 22)))
+        );
+
+        let lines = &["x = \"\"\"", "abc", "\"\"\""];
+        assert_eq!(
+            text_object("python", lines, (1, 1), "string", TextObjectMode::Inner, 1),
+            Some(((1, 0), (1, 3)))
+        );
+    }
+
+    #[test]
+    fn test_span() {
+        assert_eq!(
+            text_object(
+                "markdown",
+                &["a ~~b~~ c"],
+                (0, 4),
+                "span:strikethrough",
+                TextObjectMode::Inner,
+                1
+            ),
+            Some(((0, 4), (0, 5)))
+        );
+    }
+}
@@ -0,0 +1,244 @@
+use mlua::IntoLua;
+
+use super::{ParsedBuffer, TextObject, TextObjectMode};
+use crate::parser::{Kind, MatchWithLine, Token};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PairTreeNode {
+    Pair {
+        opening: MatchWithLine,
+        closing: MatchWithLine,
+        children: Vec<PairTreeNode>,
+    },
+    /// Opening or closing without a match
+    Unmatched(MatchWithLine),
+}
+
+fn position_into_lua(lua: &mlua::Lua, match_: &MatchWithLine) -> mlua::Result<mlua::Table> {
+    let table = lua.create_table()?;
+    table.set("line", match_.line)?;
+    table.set("col", match_.col)?;
+    Ok(table)
+}
+
+fn token_into_lua(lua: &mlua::Lua, table: &mlua::Table, token: &Token) -> mlua::Result<()> {
+    let token_table = lua.create_table()?;
+    token_table.set(1, token.opening())?;
+    if let Some(closing) = token.closing() {
+        token_table.set(2, closing)?;
+    }
+    table.set("token", token_table)?;
+
+    if let Token::InlineSpan(span, _, _) | Token::BlockSpan(span, _, _) = token {
+        table.set("span", *span)?;
+    }
+    Ok(())
+}
+
+impl IntoLua for PairTreeNode {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+
+        match self {
+            PairTreeNode::Pair {
+                opening,
+                closing,
+                children,
+            } => {
+                table.set("open", position_into_lua(lua, &opening)?)?;
+                table.set("close", position_into_lua(lua, &closing)?)?;
+                token_into_lua(lua, &table, &opening.token)?;
+                table.set("children", children)?;
+            }
+            PairTreeNode::Unmatched(match_) => {
+                let side = if match_.kind == Kind::Opening {
+                    "open"
+                } else {
+                    "close"
+                };
+                table.set(side, position_into_lua(lua, &match_)?)?;
+                token_into_lua(lua, &table, &match_.token)?;
+                table.set("error", true)?;
+                table.set("children", lua.create_table()?)?;
+            }
+        }
+
+        (&table).into_lua(lua)
+    }
+}
+
+/// Pair that has been opened but not yet closed while building the tree
+struct OpenPair {
+    opening: MatchWithLine,
+    children: Vec<PairTreeNode>,
+}
+
+impl ParsedBuffer {
+    /// Builds the nested structure of the pairs (delimiters, strings, comments and spans) that
+    /// overlap the line range (exclusive). Pairs which start before the range are included
+    /// as roots when they extend into it, but only their children inside the range are included
+    pub fn pair_tree(&self, start_line: usize, end_line: usize) -> Vec<PairTreeNode> {
+        let mut roots = vec![];
+        let mut stack: Vec<OpenPair> = self
+            .enclosing_openings(start_line, 0, |match_| match_.kind != Kind::NonPair)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .map(|opening| OpenPair {
+                opening,
+                children: vec![],
+            })
+            .collect();
+
+        fn push_node(roots: &mut Vec<PairTreeNode>, stack: &mut [OpenPair], node: PairTreeNode) {
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(node),
+                None => roots.push(node),
+            }
+        }
+
+        for match_ in self
+            .iter_from(start_line, 0)
+            .take_while(|match_| match_.line < end_line)
+            .filter(|match_| match_.kind != Kind::NonPair)
+        {
+            if match_.stack_height.is_none() {
+                push_node(&mut roots, &mut stack, PairTreeNode::Unmatched(match_));
+                continue;
+            }
+
+            if match_.kind == Kind::Opening {
+                stack.push(OpenPair {
+                    opening: match_,
+                    children: vec![],
+                });
+                continue;
+            }
+
+            // Close all the pairs up to and including the one for this closing
+            while let Some(open_pair) = stack.pop() {
+                let is_match = open_pair.opening.token == match_.token
+                    && open_pair.opening.stack_height == match_.stack_height;
+                let node = if is_match {
+                    PairTreeNode::Pair {
+                        opening: open_pair.opening,
+                        closing: match_.clone(),
+                        children: open_pair.children,
+                    }
+                } else {
+                    self.close_open_pair(open_pair)
+                };
+                push_node(&mut roots, &mut stack, node);
+
+                if is_match {
+                    break;
+                }
+            }
+        }
+
+        // Pairs which close after the range
+        while let Some(open_pair) = stack.pop() {
+            let node = self.close_open_pair(open_pair);
+            push_node(&mut roots, &mut stack, node);
+        }
+
+        roots
+    }
+
+    fn close_open_pair(&self, open_pair: OpenPair) -> PairTreeNode {
+        match self.match_pair(open_pair.opening.line, open_pair.opening.col) {
+            Some((_, closing)) => PairTreeNode::Pair {
+                opening: open_pair.opening,
+                closing,
+                children: open_pair.children,
+            },
+            None => PairTreeNode::Unmatched(open_pair.opening),
+        }
+    }
+
+    /// Gets the ranges for expanding the selection from the position, innermost first.
+    /// Each enclosing pair contributes its inner range followed by its outer range
+    pub fn selection_ranges(&self, line_number: usize, col: usize) -> Vec<TextObject> {
+        let mut ranges: Vec<TextObject> = vec![];
+        for (opening, closing) in self.enclosing_pairs(line_number, col) {
+            for mode in [TextObjectMode::Inner, TextObjectMode::Around] {
+                let range = self.pair_range(&opening, &closing, mode);
+                if ranges.last() != Some(&range) {
+                    ranges.push(range);
+                }
+            }
+        }
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Flattens the tree into (depth, opening line, opening col, is error)
+    fn flatten(nodes: &[PairTreeNode], depth: usize, out: &mut Vec<(usize, usize, usize, bool)>) {
+        for node in nodes {
+            match node {
+                PairTreeNode::Pair {
+                    opening, children, ..
+                } => {
+                    out.push((depth, opening.line, opening.col, false));
+                    flatten(children, depth + 1, out);
+                }
+                PairTreeNode::Unmatched(match_) => out.push((depth, match_.line, match_.col, true)),
+            }
+        }
+    }
+
+    fn tree(
+        lines: &[&str],
+        start_line: usize,
+        end_line: usize,
+    ) -> Vec<(usize, usize, usize, bool)> {
+        let buffer = ParsedBuffer::parse("rust", 4, lines).unwrap();
+        let mut out = vec![];
+        flatten(&buffer.pair_tree(start_line, end_line), 0, &mut out);
+        out
+    }
+
+    #[test]
+    fn test_pair_tree() {
+        let lines = &["fn a() {", "    b(\"c\", [d]);", "}", "e)"];
+        assert_eq!(
+            tree(lines, 0, 4),
+            vec![
+                (0, 0, 4, false),
+                (0, 0, 7, false),
+                (1, 1, 5, false),
+                (2, 1, 6, false),
+                (2, 1, 11, false),
+                (0, 3, 1, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pair_tree_partial_range() {
+        let lines = &["{", "    (a)", "    [b]", "}"];
+        assert_eq!(tree(lines, 2, 3), vec![(0, 0, 0, false), (1, 2, 4, false)]);
+
+        let buffer = ParsedBuffer::parse("rust", 4, lines).unwrap();
+        let roots = buffer.pair_tree(1, 2);
+        let PairTreeNode::Pair { closing, .. } = &roots[0] else {
+            panic!("expected pair");
+        };
+        assert_eq!((closing.line, closing.col), (3, 0));
+    }
+
+    #[test]
+    fn test_selection_ranges() {
+        let buffer = ParsedBuffer::parse("rust", 4, &["f(a, [b])"]).unwrap();
+        let ranges = buffer
+            .selection_ranges(0, 6)
+            .into_iter()
+            .map(|r| (r.start_col, r.end_col))
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, vec![(6, 7), (5, 8), (2, 8), (1, 9)]);
+    }
+}
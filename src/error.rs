@@ -0,0 +1,139 @@
+use std::fmt;
+
+use mlua::IntoLua;
+
+/// Errors returned to Lua when an export is called with arguments that don't line up with
+/// the parsed buffer. These are returned as the second value of a `nil, err` pair, so that
+/// callers can tell "no result" apart from "called incorrectly"
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    UnknownBuffer(usize),
+    UnsupportedFiletype(String),
+    LineOutOfRange {
+        line: usize,
+        line_count: usize,
+    },
+    ColOutOfRange {
+        line: usize,
+        col: usize,
+        line_len: usize,
+    },
+    InvalidTokenType(u8),
+    StaleVersion {
+        version: u64,
+        current_version: u64,
+    },
+    InvalidArgument {
+        name: &'static str,
+        value: String,
+    },
+    StaleText,
+    Panicked {
+        bufnr: usize,
+        message: String,
+    },
+}
+
+impl Error {
+    /// Stable identifier for matching on the error kind from Lua
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::UnknownBuffer(_) => "unknown_buffer",
+            Error::UnsupportedFiletype(_) => "unsupported_filetype",
+            Error::LineOutOfRange { .. } => "line_out_of_range",
+            Error::ColOutOfRange { .. } => "col_out_of_range",
+            Error::InvalidTokenType(_) => "invalid_token_type",
+            Error::StaleVersion { .. } => "stale_version",
+            Error::InvalidArgument { .. } => "invalid_argument",
+            Error::StaleText => "stale_text",
+            Error::Panicked { .. } => "panicked",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnknownBuffer(bufnr) => write!(f, "buffer {bufnr} has not been parsed"),
+            Error::UnsupportedFiletype(filetype) => {
+                write!(f, "filetype \"{filetype}\" is not supported")
+            }
+            Error::LineOutOfRange { line, line_count } => write!(
+                f,
+                "line {line} is out of range for buffer with {line_count} lines"
+            ),
+            Error::ColOutOfRange {
+                line,
+                col,
+                line_len,
+            } => write!(
+                f,
+                "col {col} is out of range for line {line} with length {line_len}"
+            ),
+            Error::InvalidTokenType(token_type) => {
+                write!(f, "token type {token_type} is not valid")
+            }
+            Error::StaleVersion {
+                version,
+                current_version,
+            } => write!(
+                f,
+                "version {version} is older than the parsed version {current_version}"
+            ),
+            Error::InvalidArgument { name, value } => {
+                write!(f, "\"{value}\" is not a valid value for {name}")
+            }
+            Error::StaleText => write!(f, "buffer text has changed since the last parse"),
+            Error::Panicked { bufnr, message } => {
+                write!(f, "panicked while handling buffer {bufnr}: {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl IntoLua for Error {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+
+        table.set("code", self.code())?;
+        table.set("message", self.to_string())?;
+        match self {
+            Error::UnknownBuffer(bufnr) => table.set("bufnr", bufnr)?,
+            Error::UnsupportedFiletype(filetype) => table.set("filetype", filetype)?,
+            Error::LineOutOfRange { line, line_count } => {
+                table.set("line", line)?;
+                table.set("line_count", line_count)?;
+            }
+            Error::ColOutOfRange {
+                line,
+                col,
+                line_len,
+            } => {
+                table.set("line", line)?;
+                table.set("col", col)?;
+                table.set("line_len", line_len)?;
+            }
+            Error::InvalidTokenType(token_type) => table.set("token_type", token_type)?,
+            Error::StaleVersion {
+                version,
+                current_version,
+            } => {
+                table.set("version", version)?;
+                table.set("current_version", current_version)?;
+            }
+            Error::InvalidArgument { name, value } => {
+                table.set("name", name)?;
+                table.set("value", value)?;
+            }
+            Error::StaleText => {}
+            Error::Panicked { bufnr, message } => {
+                table.set("bufnr", bufnr)?;
+                table.set("panic", message)?;
+            }
+        }
+
+        (&table).into_lua(lua)
+    }
+}
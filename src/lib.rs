@@ -2,65 +2,262 @@
 #![feature(int_roundings)]
 
 use mlua::prelude::*;
-use parser::matcher::TokenType;
-use std::collections::HashMap;
-use std::sync::{LazyLock, Mutex, MutexGuard};
+use parser::matcher::{MatchFilter, TokenType};
+use std::any::Any;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::thread;
 
-use buffer::ParsedBuffer;
-use parser::{Match, MatchWithLine};
+use autopair::{autopair_action, Action, BufferSnapshot, Key, Rule};
+use buffer::{
+    Context, DepthMode, Diagnostic, EditSimulation, FoldOptions, Folds, PairTreeNode, Parinfer,
+    ParinferMode, ParsedBuffer, Position, RangeMatches, Scope, SpanRange, StructuralOp,
+    SurroundOptions, TextEdit, TextObject, TextObjectKind, TextObjectMode,
+};
+use error::Error;
+use parser::indent::IndentStyle;
+use parser::{Kind, Match, MatchWithLine};
 
+pub mod autopair;
 pub mod buffer;
+pub mod error;
 pub mod parser;
 
-static PARSED_BUFFERS: LazyLock<Mutex<HashMap<usize, ParsedBuffer>>> =
-    LazyLock::new(|| Mutex::new(HashMap::new()));
-
-fn get_parsed_buffers<'a>() -> MutexGuard<'a, HashMap<usize, ParsedBuffer>> {
-    match PARSED_BUFFERS.lock() {
-        Ok(lock) => lock,
-        Err(_) => {
-            // Reset the mutex
-            PARSED_BUFFERS.clear_poison();
-            let mut parsed_buffers = PARSED_BUFFERS.lock().unwrap();
-            *parsed_buffers = HashMap::new();
-            parsed_buffers
-        }
+type SharedParsedBuffer = Arc<RwLock<ParsedBuffer>>;
+
+/// Each buffer sits behind its own lock so that queries on different buffers (and concurrent
+/// queries on the same buffer) don't block each other. The outer lock is only held long
+/// enough to clone the `Arc`
+static PARSED_BUFFERS: LazyLock<RwLock<HashMap<usize, SharedParsedBuffer>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+fn get_parsed_buffer(bufnr: usize) -> Option<SharedParsedBuffer> {
+    PARSED_BUFFERS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&bufnr)
+        .cloned()
+}
+
+fn insert_parsed_buffer(bufnr: usize, parsed_buffer: ParsedBuffer) {
+    PARSED_BUFFERS
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(bufnr, Arc::new(RwLock::new(parsed_buffer)));
+}
+
+/// Removes the buffer from the map, but only if it hasn't been replaced in the meantime
+fn invalidate_parsed_buffer(bufnr: usize, parsed_buffer: &SharedParsedBuffer) {
+    let mut parsed_buffers = PARSED_BUFFERS
+        .write()
+        .unwrap_or_else(PoisonError::into_inner);
+    if parsed_buffers
+        .get(&bufnr)
+        .is_some_and(|current| Arc::ptr_eq(current, parsed_buffer))
+    {
+        parsed_buffers.remove(&bufnr);
     }
 }
 
-fn parse_buffer(
-    _lua: &Lua,
-    (bufnr, tab_width, filetype, lines, start_line, old_end_line, new_end_line): (
-        usize,
-        u8,
-        String,
-        Vec<String>,
-        Option<usize>,
-        Option<usize>,
-        Option<usize>,
-    ),
-) -> LuaResult<(bool, bool)> {
-    let lines_ref = lines.iter().map(|str| str.as_ref()).collect::<Vec<_>>();
+fn read_parsed_buffer(parsed_buffer: &SharedParsedBuffer) -> RwLockReadGuard<'_, ParsedBuffer> {
+    parsed_buffer.read().unwrap_or_else(PoisonError::into_inner)
+}
 
-    let mut parsed_buffers = get_parsed_buffers();
+fn write_parsed_buffer(parsed_buffer: &SharedParsedBuffer) -> RwLockWriteGuard<'_, ParsedBuffer> {
+    parsed_buffer
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Collapses queries that may not find a result, so that "no result" becomes `nil, nil`
+fn flatten_lua_return<T>(
+    (value, err): (Option<Option<T>>, Option<Error>),
+) -> (Option<T>, Option<Error>) {
+    (value.flatten(), err)
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(|s| s.as_str()))
+        .unwrap_or("unknown panic")
+}
+
+fn panic_error(bufnr: usize, payload: Box<dyn Any + Send>) -> LuaError {
+    LuaError::runtime(format!(
+        "blink.pairs: panicked while handling buffer {bufnr}: {}",
+        panic_message(payload.as_ref())
+    ))
+}
+
+/// Lua-facing return value: `value, nil` on success or `nil, err` when the export was called
+/// incorrectly. "No result" is represented by `nil, nil`
+type LuaReturn<T> = LuaResult<(Option<T>, Option<Error>)>;
+
+fn lua_return<T>(result: Result<T, Error>) -> (Option<T>, Option<Error>) {
+    match result {
+        Ok(value) => (Some(value), None),
+        Err(err) => (None, Some(err)),
+    }
+}
+
+/// Runs the query against the buffer with a shared lock, returning an error if the buffer
+/// hasn't been parsed. Panics are returned as Lua errors and leave the buffer untouched
+fn with_parsed_buffer<T>(
+    bufnr: usize,
+    f: impl FnOnce(&ParsedBuffer) -> Result<T, Error>,
+) -> LuaReturn<T> {
+    catch_parsed_buffer(bufnr, f)
+        .map(lua_return)
+        .map_err(|payload| panic_error(bufnr, payload))
+}
+
+/// Like `with_parsed_buffer`, but hands back the panic payload for callers that report panics
+/// alongside other errors
+fn catch_parsed_buffer<T>(
+    bufnr: usize,
+    f: impl FnOnce(&ParsedBuffer) -> Result<T, Error>,
+) -> thread::Result<Result<T, Error>> {
+    let Some(parsed_buffer) = get_parsed_buffer(bufnr) else {
+        return Ok(Err(Error::UnknownBuffer(bufnr)));
+    };
+    let parsed_buffer = read_parsed_buffer(&parsed_buffer);
+    catch_unwind(AssertUnwindSafe(|| f(&parsed_buffer)))
+}
+
+/// Gets the text of the lines (end exclusive, `-1` for the last line) from Neovim
+fn buffer_lines(lua: &Lua, bufnr: usize, start: i64, end: i64) -> LuaResult<Vec<String>> {
+    let api = lua
+        .globals()
+        .get::<LuaTable>("vim")?
+        .get::<LuaTable>("api")?;
+    api.get::<LuaFunction>("nvim_buf_get_lines")?
+        .call((bufnr, start, end, false))
+}
+
+/// Runs the query like `with_parsed_buffer`, for the queries which read the text of the buffer
+/// rather than only the parse. The text is fetched before taking the lock, and must line up
+/// with the parse
+fn with_buffer_text<T>(
+    lua: &Lua,
+    bufnr: usize,
+    f: impl FnOnce(&ParsedBuffer, &[&str]) -> Result<T, Error>,
+) -> LuaReturn<T> {
+    let lines = buffer_lines(lua, bufnr, 0, -1)?;
+    let lines = lines.iter().map(String::as_str).collect::<Vec<_>>();
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_text(&lines)?;
+        f(parsed_buffer, &lines)
+    })
+}
+
+/// Runs the query like `with_buffer_text`, but only fetches the text of the lines picked by
+/// `text_lines` from the parse, for the queries which only read a few lines
+fn with_lines_text<T>(
+    lua: &Lua,
+    bufnr: usize,
+    text_lines: impl FnOnce(&ParsedBuffer) -> Result<BTreeSet<usize>, Error>,
+    f: impl FnOnce(&ParsedBuffer, &BTreeMap<usize, String>) -> Result<T, Error>,
+) -> LuaReturn<T> {
+    let line_numbers = match with_parsed_buffer(bufnr, text_lines)? {
+        (Some(line_numbers), _) => line_numbers,
+        (None, err) => return Ok((None, err)),
+    };
+    let lines = line_numbers
+        .into_iter()
+        .map(|line| {
+            let text = buffer_lines(lua, bufnr, line as i64, line as i64 + 1)?.pop();
+            Ok((line, text.unwrap_or_default()))
+        })
+        .collect::<LuaResult<BTreeMap<_, _>>>()?;
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        for (&line, text) in &lines {
+            parsed_buffer.validate_line_text(line, text)?;
+        }
+        f(parsed_buffer, &lines)
+    })
+}
+
+type ParseBufferArgs = (
+    usize,
+    u8,
+    String,
+    Vec<String>,
+    Option<usize>,
+    Option<usize>,
+    Option<usize>,
+    Option<u64>,
+    Option<String>,
+);
+
+/// Parses the buffer, incrementally if it has been parsed before. Returns whether the state at
+/// the end of the edited range changed, in which case the caller should do a full reparse
+fn parse_buffer(_lua: &Lua, args: ParseBufferArgs) -> LuaReturn<bool> {
+    parse_buffer_lines(args)
+}
+
+fn parse_buffer_lines(
+    (
+        bufnr,
+        tab_width,
+        filetype,
+        lines,
+        start_line,
+        old_end_line,
+        new_end_line,
+        version,
+        depth_mode,
+    ): ParseBufferArgs,
+) -> LuaReturn<bool> {
+    let lines_ref = lines.iter().map(|str| str.as_ref()).collect::<Vec<_>>();
+    let depth_mode = match depth_mode.as_deref().map(DepthMode::from_str).transpose() {
+        Ok(depth_mode) => depth_mode.unwrap_or_default(),
+        Err(err) => return Ok(lua_return(Err(err))),
+    };
 
     // Incremental parse
-    if let Some(parsed_buffer) = parsed_buffers.get_mut(&bufnr) {
-        Ok(parsed_buffer.reparse_range(
-            &filetype,
-            tab_width,
-            &lines_ref,
-            start_line,
-            old_end_line,
-            new_end_line,
-        ))
+    if let Some(parsed_buffer) = get_parsed_buffer(bufnr) {
+        let result = {
+            let mut guard = write_parsed_buffer(&parsed_buffer);
+            if let Err(err) = guard.check_version(version) {
+                return Ok(lua_return(Err(err)));
+            }
+            catch_unwind(AssertUnwindSafe(|| {
+                let state_changed = guard.reparse_range(
+                    &filetype,
+                    tab_width,
+                    &lines_ref,
+                    start_line,
+                    old_end_line,
+                    new_end_line,
+                )?;
+                guard.version = version.or(guard.version);
+                guard.set_depth_mode(depth_mode);
+                Ok(state_changed)
+            }))
+        };
+
+        // The buffer may have been left half-updated, so drop it and let the next
+        // parse start from scratch
+        result.map(lua_return).map_err(|payload| {
+            invalidate_parsed_buffer(bufnr, &parsed_buffer);
+            panic_error(bufnr, payload)
+        })
     }
     // Full parse
-    else if let Some(parsed_buffer) = ParsedBuffer::parse(&filetype, tab_width, &lines_ref) {
-        parsed_buffers.insert(bufnr, parsed_buffer);
-        Ok((true, false))
-    } else {
-        Ok((false, false))
+    else {
+        let parsed_buffer = catch_unwind(|| ParsedBuffer::parse(&filetype, tab_width, &lines_ref))
+            .map_err(|payload| panic_error(bufnr, payload))?;
+
+        Ok(lua_return(parsed_buffer.map(|mut parsed_buffer| {
+            parsed_buffer.version = version;
+            parsed_buffer.set_depth_mode(depth_mode);
+            insert_parsed_buffer(bufnr, parsed_buffer);
+            false
+        })))
     }
 }
 
@@ -71,84 +268,668 @@ fn supports_filetype(_lua: &Lua, (filetype,): (String,)) -> LuaResult<bool> {
 fn get_line_matches(
     _lua: &Lua,
     (bufnr, line_number, token_type): (usize, usize, Option<u8>),
-) -> LuaResult<Vec<Match>> {
-    let parsed_buffers = get_parsed_buffers();
-    let token_type = token_type
-        // TODO: don't ignore the error
-        .and_then(|token_type| token_type.try_into().ok())
-        .unwrap_or(TokenType::Delimiter);
-
-    if let Some(parsed_buffer) = parsed_buffers.get(&bufnr) {
-        if let Some(line_matches) = parsed_buffer.line_matches(line_number) {
-            return Ok(line_matches
-                .iter()
-                .filter(|m| token_type.matches(&m.token))
-                .cloned()
-                .collect());
-        }
-    }
+) -> LuaReturn<Vec<Match>> {
+    let token_type = match token_type.map(TokenType::try_from).transpose() {
+        Ok(token_type) => token_type.unwrap_or(TokenType::Delimiter),
+        Err(err) => return Ok(lua_return(Err(err))),
+    };
 
-    Ok(Vec::new())
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_line(line_number)?;
+        Ok(parsed_buffer
+            .line_matches(line_number)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|m| token_type.matches(&m.token))
+            .collect())
+    })
 }
 
-fn get_span_at(_lua: &Lua, (bufnr, row, col): (usize, usize, usize)) -> LuaResult<Option<String>> {
-    Ok(get_parsed_buffers()
-        .get(&bufnr)
-        .and_then(|parsed_buffer| parsed_buffer.span_at(row, col)))
+/// Gets the matches in the line range (exclusive), filtered by a `MatchFilter` bitmask
+fn get_range_matches(
+    _lua: &Lua,
+    (bufnr, start_line, end_line, filter): (usize, usize, usize, Option<u16>),
+) -> LuaReturn<RangeMatches> {
+    let filter = match filter.map(MatchFilter::try_from).transpose() {
+        Ok(filter) => filter.unwrap_or_default(),
+        Err(err) => return Ok(lua_return(Err(err))),
+    };
+
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_line_range(start_line, end_line)?;
+        Ok(parsed_buffer.range_matches(start_line, end_line, filter))
+    })
 }
 
-fn get_match_at(_lua: &Lua, (bufnr, row, col): (usize, usize, usize)) -> LuaResult<Option<Match>> {
-    Ok(get_parsed_buffers()
-        .get(&bufnr)
-        .and_then(|parsed_buffer| parsed_buffer.match_at(row, col)))
+fn get_span_at(_lua: &Lua, (bufnr, row, col): (usize, usize, usize)) -> LuaReturn<Vec<SpanRange>> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_position(row, col)?;
+        Ok(parsed_buffer.span_at(row, col))
+    })
+}
+
+fn get_spans_in_range(
+    _lua: &Lua,
+    (bufnr, start_line, end_line): (usize, usize, usize),
+) -> LuaReturn<Vec<SpanRange>> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_line_range(start_line, end_line)?;
+        Ok(parsed_buffer.spans_in_range(start_line, end_line))
+    })
+}
+
+fn get_match_at(_lua: &Lua, (bufnr, row, col): (usize, usize, usize)) -> LuaReturn<Match> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_position(row, col)?;
+        Ok(parsed_buffer.match_at(row, col))
+    })
+    .map(flatten_lua_return)
+}
+
+fn context_at(_lua: &Lua, (bufnr, row, col): (usize, usize, usize)) -> LuaReturn<Context> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_position(row, col)?;
+        Ok(parsed_buffer.context_at(row, col))
+    })
 }
 
 fn get_match_pair(
     _lua: &Lua,
     (bufnr, row, col): (usize, usize, usize),
-) -> LuaResult<Option<Vec<MatchWithLine>>> {
-    Ok(get_parsed_buffers()
-        .get(&bufnr)
-        .and_then(|parsed_buffer| parsed_buffer.match_pair(row, col))
-        .map(|(open, close)| vec![open, close]))
+) -> LuaReturn<Vec<MatchWithLine>> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_position(row, col)?;
+        Ok(parsed_buffer
+            .match_pair(row, col)
+            .map(|(open, close)| vec![open, close]))
+    })
+    .map(flatten_lua_return)
 }
 
 fn get_surrounding_match_pair(
     _lua: &Lua,
     (bufnr, row, col): (usize, usize, usize),
-) -> LuaResult<Option<Vec<MatchWithLine>>> {
-    Ok(get_parsed_buffers()
-        .get(&bufnr)
-        .and_then(|parsed_buffer| parsed_buffer.surrounding_match_pair(row, col))
-        .map(|(open, close)| vec![open, close]))
+) -> LuaReturn<Vec<MatchWithLine>> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_position(row, col)?;
+        Ok(parsed_buffer
+            .surrounding_match_pair(row, col)
+            .map(|(open, close)| vec![open, close]))
+    })
+    .map(flatten_lua_return)
 }
 
 fn get_unmatched_opening_before(
     _lua: &Lua,
     (bufnr, opening, closing, row, col): (usize, String, String, usize, usize),
-) -> LuaResult<Option<MatchWithLine>> {
-    Ok(get_parsed_buffers().get(&bufnr).and_then(|parsed_buffer| {
-        parsed_buffer.unmatched_opening_before(&opening, &closing, row, col)
-    }))
+) -> LuaReturn<MatchWithLine> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_position(row, col)?;
+        Ok(parsed_buffer.unmatched_opening_before(&opening, &closing, row, col))
+    })
+    .map(flatten_lua_return)
 }
 
 fn get_unmatched_closing_after(
     _lua: &Lua,
     (bufnr, opening, closing, row, col): (usize, String, String, usize, usize),
-) -> LuaResult<Option<MatchWithLine>> {
-    Ok(get_parsed_buffers().get(&bufnr).and_then(|parsed_buffer| {
-        parsed_buffer.unmatched_closing_after(&opening, &closing, row, col)
-    }))
+) -> LuaReturn<MatchWithLine> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_position(row, col)?;
+        Ok(parsed_buffer.unmatched_closing_after(&opening, &closing, row, col))
+    })
+    .map(flatten_lua_return)
 }
 
 fn get_indent_levels(
     _lua: &Lua,
     (bufnr, start_line, end_line): (usize, usize, usize),
-) -> LuaResult<Vec<u8>> {
-    Ok(get_parsed_buffers()
-        .get(&bufnr)
-        .map(|parsed_buffer| parsed_buffer.get_indent_levels(start_line, end_line))
-        .unwrap_or_default())
+) -> LuaReturn<Vec<u16>> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_line_range(start_line, end_line)?;
+        Ok(parsed_buffer.get_indent_levels(start_line, end_line))
+    })
+}
+
+fn detect_indent(lua: &Lua, bufnr: usize) -> LuaReturn<IndentStyle> {
+    with_buffer_text(lua, bufnr, |parsed_buffer, lines| {
+        Ok(parsed_buffer.detect_indent(lines))
+    })
+}
+
+fn get_diagnostics(
+    _lua: &Lua,
+    (bufnr, start_line, end_line): (usize, usize, usize),
+) -> LuaReturn<Vec<Diagnostic>> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_line_range(start_line, end_line)?;
+        Ok(parsed_buffer.diagnostics(start_line, end_line))
+    })
+}
+
+fn get_next_opening(
+    _lua: &Lua,
+    (bufnr, row, col, filter): (usize, usize, usize, Option<String>),
+) -> LuaReturn<MatchWithLine> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_position(row, col)?;
+        Ok(parsed_buffer.next_at_depth(row, col, Kind::Opening, filter.as_deref()))
+    })
+    .map(flatten_lua_return)
+}
+
+fn get_prev_opening(
+    _lua: &Lua,
+    (bufnr, row, col, filter): (usize, usize, usize, Option<String>),
+) -> LuaReturn<MatchWithLine> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_position(row, col)?;
+        Ok(parsed_buffer.prev_at_depth(row, col, Kind::Opening, filter.as_deref()))
+    })
+    .map(flatten_lua_return)
+}
+
+fn get_next_closing(
+    _lua: &Lua,
+    (bufnr, row, col, filter): (usize, usize, usize, Option<String>),
+) -> LuaReturn<MatchWithLine> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_position(row, col)?;
+        Ok(parsed_buffer.next_at_depth(row, col, Kind::Closing, filter.as_deref()))
+    })
+    .map(flatten_lua_return)
+}
+
+fn get_prev_closing(
+    _lua: &Lua,
+    (bufnr, row, col, filter): (usize, usize, usize, Option<String>),
+) -> LuaReturn<MatchWithLine> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_position(row, col)?;
+        Ok(parsed_buffer.prev_at_depth(row, col, Kind::Closing, filter.as_deref()))
+    })
+    .map(flatten_lua_return)
+}
+
+fn get_parent_pair(
+    _lua: &Lua,
+    (bufnr, row, col, filter): (usize, usize, usize, Option<String>),
+) -> LuaReturn<Vec<MatchWithLine>> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_position(row, col)?;
+        Ok(parsed_buffer
+            .parent_pair(row, col, filter.as_deref())
+            .map(|(open, close)| vec![open, close]))
+    })
+    .map(flatten_lua_return)
+}
+
+fn get_first_child_pair(
+    _lua: &Lua,
+    (bufnr, row, col, filter): (usize, usize, usize, Option<String>),
+) -> LuaReturn<Vec<MatchWithLine>> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_position(row, col)?;
+        Ok(parsed_buffer
+            .first_child_pair(row, col, filter.as_deref())
+            .map(|(open, close)| vec![open, close]))
+    })
+    .map(flatten_lua_return)
+}
+
+fn get_last_child_pair(
+    _lua: &Lua,
+    (bufnr, row, col, filter): (usize, usize, usize, Option<String>),
+) -> LuaReturn<Vec<MatchWithLine>> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_position(row, col)?;
+        Ok(parsed_buffer
+            .last_child_pair(row, col, filter.as_deref())
+            .map(|(open, close)| vec![open, close]))
+    })
+    .map(flatten_lua_return)
+}
+
+fn get_next_sibling_pair(
+    _lua: &Lua,
+    (bufnr, row, col, filter): (usize, usize, usize, Option<String>),
+) -> LuaReturn<Vec<MatchWithLine>> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_position(row, col)?;
+        Ok(parsed_buffer
+            .next_sibling_pair(row, col, filter.as_deref())
+            .map(|(open, close)| vec![open, close]))
+    })
+    .map(flatten_lua_return)
+}
+
+fn get_prev_sibling_pair(
+    _lua: &Lua,
+    (bufnr, row, col, filter): (usize, usize, usize, Option<String>),
+) -> LuaReturn<Vec<MatchWithLine>> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_position(row, col)?;
+        Ok(parsed_buffer
+            .prev_sibling_pair(row, col, filter.as_deref())
+            .map(|(open, close)| vec![open, close]))
+    })
+    .map(flatten_lua_return)
+}
+
+fn get_tab_out_closing(
+    _lua: &Lua,
+    (bufnr, row, col, filter): (usize, usize, usize, Option<String>),
+) -> LuaReturn<MatchWithLine> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_position(row, col)?;
+        Ok(parsed_buffer.tab_out_closing(row, col, filter.as_deref()))
+    })
+    .map(flatten_lua_return)
+}
+
+fn get_text_object(
+    _lua: &Lua,
+    (bufnr, row, col, kind, mode, count): (usize, usize, usize, String, String, Option<usize>),
+) -> LuaReturn<TextObject> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_position(row, col)?;
+        let kind = kind.parse::<TextObjectKind>()?;
+        let mode = mode.parse::<TextObjectMode>()?;
+        Ok(parsed_buffer.text_object(row, col, &kind, mode, count.unwrap_or(1)))
+    })
+    .map(flatten_lua_return)
+}
+
+fn get_pair_tree(
+    _lua: &Lua,
+    (bufnr, start_line, end_line): (usize, usize, usize),
+) -> LuaReturn<Vec<PairTreeNode>> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_line_range(start_line, end_line)?;
+        Ok(parsed_buffer.pair_tree(start_line, end_line))
+    })
+}
+
+fn get_folds(
+    _lua: &Lua,
+    (bufnr, start_line, end_line, options): (usize, usize, usize, Option<FoldOptions>),
+) -> LuaReturn<Folds> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_line_range(start_line, end_line)?;
+        Ok(parsed_buffer.folds(start_line, end_line, &options.unwrap_or_default()))
+    })
+}
+
+fn get_scope_at(_lua: &Lua, (bufnr, row, col): (usize, usize, usize)) -> LuaReturn<Scope> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_position(row, col)?;
+        Ok(parsed_buffer.scope_at(row, col))
+    })
+    .map(flatten_lua_return)
+}
+
+fn get_scope_guides(
+    _lua: &Lua,
+    (bufnr, start_line, end_line): (usize, usize, usize),
+) -> LuaReturn<Vec<Vec<usize>>> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_line_range(start_line, end_line)?;
+        Ok(parsed_buffer.scope_guides(start_line, end_line))
+    })
+}
+
+/// Converts to the `indentexpr` convention of `-1` for keeping the current indentation
+fn indent_into_lua(indent: Option<usize>) -> i64 {
+    indent.map(|indent| indent as i64).unwrap_or(-1)
+}
+
+fn suggest_indent(lua: &Lua, (bufnr, line, shiftwidth): (usize, usize, usize)) -> LuaReturn<i64> {
+    with_lines_text(
+        lua,
+        bufnr,
+        |parsed_buffer| {
+            parsed_buffer.validate_line(line)?;
+            Ok(parsed_buffer.indent_text_lines(line, line + 1))
+        },
+        |parsed_buffer, lines| {
+            Ok(indent_into_lua(
+                parsed_buffer.suggest_indent(lines, line, shiftwidth),
+            ))
+        },
+    )
+}
+
+fn reindent_range(
+    lua: &Lua,
+    (bufnr, start_line, end_line, shiftwidth): (usize, usize, usize, usize),
+) -> LuaReturn<Vec<i64>> {
+    with_lines_text(
+        lua,
+        bufnr,
+        |parsed_buffer| {
+            parsed_buffer.validate_line_range(start_line, end_line)?;
+            Ok(parsed_buffer.indent_text_lines(start_line, end_line))
+        },
+        |parsed_buffer, lines| {
+            Ok(parsed_buffer
+                .reindent_range(lines, start_line, end_line, shiftwidth)
+                .into_iter()
+                .map(indent_into_lua)
+                .collect())
+        },
+    )
+}
+
+type AutopairActionArgs = (usize, usize, usize, String, Vec<Rule>, LuaFunction);
+
+/// Decides the action for the key typed at the position. The `is_active` function is called
+/// with the (1-indexed) index of a rule and the mode, or nil for the rule's `when` condition.
+/// The buffer isn't locked while it's called, so it may run other queries on the buffer
+fn get_autopair_action(
+    lua: &Lua,
+    (bufnr, row, col, key, rules, is_active): AutopairActionArgs,
+) -> LuaReturn<Action> {
+    // Only the line of the cursor is needed
+    let line = buffer_lines(lua, bufnr, row as i64, row as i64 + 1)?
+        .pop()
+        .unwrap_or_default();
+    let snapshot = with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_position(row, col)?;
+        parsed_buffer.validate_line_text(row, &line)?;
+        let key = key.parse::<Key>()?;
+        let snapshot = BufferSnapshot::new(parsed_buffer, row, col, &key, &rules);
+        Ok((key, snapshot))
+    })?;
+    let (key, snapshot) = match snapshot {
+        (Some(snapshot), _) => snapshot,
+        (None, err) => return Ok((None, err)),
+    };
+
+    let action = autopair_action(&snapshot, &line, col, &key, &rules, |idx, mode| {
+        is_active.call::<bool>((idx + 1, mode.map(|mode| mode.as_str())))
+    })?;
+    Ok((Some(action), None))
+}
+
+fn simulate_edit(
+    lua: &Lua,
+    (bufnr, row, col, text): (usize, usize, usize, String),
+) -> LuaReturn<EditSimulation> {
+    with_buffer_text(lua, bufnr, |parsed_buffer, lines| {
+        parsed_buffer.validate_position(row, col)?;
+        parsed_buffer.simulate_edit(lines, row, col, &text)
+    })
+}
+
+fn expression_end_after(
+    lua: &Lua,
+    (bufnr, row, col): (usize, usize, usize),
+) -> LuaReturn<Position> {
+    with_buffer_text(lua, bufnr, |parsed_buffer, lines| {
+        parsed_buffer.validate_position(row, col)?;
+        Ok(parsed_buffer.expression_end_after(lines, row, col))
+    })
+    .map(flatten_lua_return)
+}
+
+fn expression_start_before(
+    lua: &Lua,
+    (bufnr, row, col): (usize, usize, usize),
+) -> LuaReturn<Position> {
+    with_buffer_text(lua, bufnr, |parsed_buffer, lines| {
+        parsed_buffer.validate_position(row, col)?;
+        Ok(parsed_buffer.expression_start_before(lines, row, col))
+    })
+    .map(flatten_lua_return)
+}
+
+type SurroundDeleteArgs = (usize, usize, usize, Option<String>, Option<SurroundOptions>);
+
+fn surround_delete(
+    lua: &Lua,
+    (bufnr, row, col, target, options): SurroundDeleteArgs,
+) -> LuaReturn<Vec<TextEdit>> {
+    with_buffer_text(lua, bufnr, |parsed_buffer, lines| {
+        parsed_buffer.validate_position(row, col)?;
+        let target = target
+            .map(|target| target.parse::<TextObjectKind>())
+            .transpose()?;
+        Ok(parsed_buffer.surround_delete(
+            lines,
+            row,
+            col,
+            target.as_ref(),
+            options.unwrap_or_default(),
+        ))
+    })
+    .map(flatten_lua_return)
+}
+
+type SurroundChangeArgs = (
+    usize,
+    usize,
+    usize,
+    Option<String>,
+    String,
+    String,
+    Option<SurroundOptions>,
+);
+
+fn surround_change(
+    lua: &Lua,
+    (bufnr, row, col, target, new_opening, new_closing, options): SurroundChangeArgs,
+) -> LuaReturn<Vec<TextEdit>> {
+    with_buffer_text(lua, bufnr, |parsed_buffer, lines| {
+        parsed_buffer.validate_position(row, col)?;
+        let target = target
+            .map(|target| target.parse::<TextObjectKind>())
+            .transpose()?;
+        Ok(parsed_buffer.surround_change(
+            lines,
+            row,
+            col,
+            target.as_ref(),
+            &new_opening,
+            &new_closing,
+            options.unwrap_or_default(),
+        ))
+    })
+    .map(flatten_lua_return)
+}
+
+fn structural_edit(
+    lua: &Lua,
+    (bufnr, row, col, operation): (usize, usize, usize, String),
+) -> LuaReturn<Vec<TextEdit>> {
+    with_buffer_text(lua, bufnr, |parsed_buffer, lines| {
+        parsed_buffer.validate_position(row, col)?;
+        let operation = operation.parse::<StructuralOp>()?;
+        Ok(parsed_buffer.structural_edit(lines, row, col, operation))
+    })
+    .map(flatten_lua_return)
+}
+
+/// Runs Parinfer over the buffer, with the cursor as a (0-indexed) row and col
+fn parinfer(
+    lua: &Lua,
+    (bufnr, mode, cursor_row, cursor_col): (usize, String, Option<usize>, Option<usize>),
+) -> LuaReturn<Parinfer> {
+    with_buffer_text(lua, bufnr, |parsed_buffer, lines| {
+        let mode = mode.parse::<ParinferMode>()?;
+        let cursor = match (cursor_row, cursor_col) {
+            (Some(row), Some(col)) => {
+                parsed_buffer.validate_position(row, col)?;
+                Some(Position::new(row, col))
+            }
+            _ => None,
+        };
+        Ok(parsed_buffer.parinfer(lines, mode, cursor))
+    })
+}
+
+/// Query run for every position by `batch_query`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BatchQuery {
+    MatchAt,
+    MatchPair,
+    SurroundingMatchPair,
+    SpanAt,
+    StackHeight,
+}
+
+impl FromStr for BatchQuery {
+    type Err = Error;
+
+    fn from_str(query: &str) -> Result<Self, Self::Err> {
+        match query {
+            "match_at" => Ok(BatchQuery::MatchAt),
+            "match_pair" => Ok(BatchQuery::MatchPair),
+            "surrounding_match_pair" => Ok(BatchQuery::SurroundingMatchPair),
+            "span_at" => Ok(BatchQuery::SpanAt),
+            "stack_height" => Ok(BatchQuery::StackHeight),
+            _ => Err(Error::InvalidArgument {
+                name: "query",
+                value: query.to_string(),
+            }),
+        }
+    }
+}
+
+impl BatchQuery {
+    fn run(
+        self,
+        parsed_buffer: &ParsedBuffer,
+        row: usize,
+        col: usize,
+    ) -> Result<Option<BatchValue>, Error> {
+        parsed_buffer.validate_position(row, col)?;
+        let into_pair = |(open, close)| BatchValue::Pair(open, close);
+        Ok(match self {
+            BatchQuery::MatchAt => parsed_buffer.match_at(row, col).map(BatchValue::Match),
+            BatchQuery::MatchPair => parsed_buffer.match_pair(row, col).map(into_pair),
+            BatchQuery::SurroundingMatchPair => parsed_buffer
+                .surrounding_match_pair(row, col)
+                .map(into_pair),
+            BatchQuery::SpanAt => Some(BatchValue::Spans(parsed_buffer.span_at(row, col))),
+            BatchQuery::StackHeight => Some(BatchValue::StackHeight(
+                parsed_buffer.stack_height_at(row, col),
+            )),
+        })
+    }
+}
+
+/// Result of a single position in `batch_query`
+#[derive(Debug, PartialEq)]
+enum BatchValue {
+    Match(Match),
+    Pair(MatchWithLine, MatchWithLine),
+    Spans(Vec<SpanRange>),
+    StackHeight(usize),
+}
+
+impl IntoLua for BatchValue {
+    fn into_lua(self, lua: &Lua) -> LuaResult<LuaValue> {
+        match self {
+            BatchValue::Match(match_) => match_.into_lua(lua),
+            BatchValue::Pair(open, close) => vec![open, close].into_lua(lua),
+            BatchValue::Spans(spans) => spans.into_lua(lua),
+            BatchValue::StackHeight(stack_height) => stack_height.into_lua(lua),
+        }
+    }
+}
+
+/// Results of `batch_query`, in the same order as the positions with `false` when there's no
+/// result, and the errors keyed by the (1-indexed) index of the position
+#[derive(Debug, PartialEq)]
+struct BatchResults {
+    results: Vec<Option<BatchValue>>,
+    errors: HashMap<usize, Error>,
+}
+
+impl IntoLua for BatchResults {
+    fn into_lua(self, lua: &Lua) -> LuaResult<LuaValue> {
+        let table = lua.create_table()?;
+
+        let results = (self.results.into_iter())
+            .map(|result| match result {
+                Some(value) => value.into_lua(lua),
+                None => Ok(LuaValue::Boolean(false)),
+            })
+            .collect::<LuaResult<Vec<_>>>()?;
+        table.set("results", results)?;
+        table.set("errors", self.errors)?;
+
+        (&table).into_lua(lua)
+    }
+}
+
+/// Runs the query for each `{ bufnr, row, col }`, taking the lock of each buffer only once
+fn batch_query(
+    _lua: &Lua,
+    (query, positions): (String, Vec<[usize; 3]>),
+) -> LuaReturn<BatchResults> {
+    Ok(lua_return(run_batch(&query, &positions)))
+}
+
+fn run_batch(query: &str, positions: &[[usize; 3]]) -> Result<BatchResults, Error> {
+    let query = query.parse::<BatchQuery>()?;
+
+    let mut indices_by_buffer: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (idx, [bufnr, _, _]) in positions.iter().enumerate() {
+        indices_by_buffer.entry(*bufnr).or_default().push(idx);
+    }
+
+    let mut batch = BatchResults {
+        results: positions.iter().map(|_| None).collect(),
+        errors: HashMap::new(),
+    };
+    for (bufnr, indices) in indices_by_buffer {
+        // A panic in one buffer is reported for its positions without failing the batch
+        let (results, err) = match catch_parsed_buffer(bufnr, |parsed_buffer| {
+            Ok(indices
+                .iter()
+                .map(|&idx| {
+                    let [_, row, col] = positions[idx];
+                    (idx, query.run(parsed_buffer, row, col))
+                })
+                .collect::<Vec<_>>())
+        }) {
+            Ok(result) => lua_return(result),
+            Err(payload) => (
+                None,
+                Some(Error::Panicked {
+                    bufnr,
+                    message: panic_message(payload.as_ref()).to_string(),
+                }),
+            ),
+        };
+
+        if let Some(err) = err {
+            for &idx in &indices {
+                batch.errors.insert(idx + 1, err.clone());
+            }
+        }
+        for (idx, result) in results.into_iter().flatten() {
+            match result {
+                Ok(value) => batch.results[idx] = value,
+                Err(err) => {
+                    batch.errors.insert(idx + 1, err);
+                }
+            }
+        }
+    }
+
+    Ok(batch)
+}
+
+fn get_selection_ranges(
+    _lua: &Lua,
+    (bufnr, row, col): (usize, usize, usize),
+) -> LuaReturn<Vec<TextObject>> {
+    with_parsed_buffer(bufnr, |parsed_buffer| {
+        parsed_buffer.validate_position(row, col)?;
+        Ok(parsed_buffer.selection_ranges(row, col))
+    })
 }
 
 // NOTE: skip_memory_check greatly improves performance
@@ -159,8 +940,14 @@ fn blink_pairs(lua: &Lua) -> LuaResult<LuaTable> {
     exports.set("parse_buffer", lua.create_function(parse_buffer)?)?;
     exports.set("supports_filetype", lua.create_function(supports_filetype)?)?;
     exports.set("get_line_matches", lua.create_function(get_line_matches)?)?;
+    exports.set("get_range_matches", lua.create_function(get_range_matches)?)?;
     exports.set("get_span_at", lua.create_function(get_span_at)?)?;
+    exports.set(
+        "get_spans_in_range",
+        lua.create_function(get_spans_in_range)?,
+    )?;
     exports.set("get_match_at", lua.create_function(get_match_at)?)?;
+    exports.set("context_at", lua.create_function(context_at)?)?;
     exports.set("get_match_pair", lua.create_function(get_match_pair)?)?;
     exports.set(
         "get_surrounding_match_pair",
@@ -175,5 +962,230 @@ fn blink_pairs(lua: &Lua) -> LuaResult<LuaTable> {
         lua.create_function(get_unmatched_closing_after)?,
     )?;
     exports.set("get_indent_levels", lua.create_function(get_indent_levels)?)?;
+    exports.set("detect_indent", lua.create_function(detect_indent)?)?;
+    exports.set("get_diagnostics", lua.create_function(get_diagnostics)?)?;
+    exports.set("get_next_opening", lua.create_function(get_next_opening)?)?;
+    exports.set("get_prev_opening", lua.create_function(get_prev_opening)?)?;
+    exports.set("get_next_closing", lua.create_function(get_next_closing)?)?;
+    exports.set("get_prev_closing", lua.create_function(get_prev_closing)?)?;
+    exports.set("get_parent_pair", lua.create_function(get_parent_pair)?)?;
+    exports.set(
+        "get_first_child_pair",
+        lua.create_function(get_first_child_pair)?,
+    )?;
+    exports.set(
+        "get_last_child_pair",
+        lua.create_function(get_last_child_pair)?,
+    )?;
+    exports.set(
+        "get_next_sibling_pair",
+        lua.create_function(get_next_sibling_pair)?,
+    )?;
+    exports.set(
+        "get_prev_sibling_pair",
+        lua.create_function(get_prev_sibling_pair)?,
+    )?;
+    exports.set(
+        "get_tab_out_closing",
+        lua.create_function(get_tab_out_closing)?,
+    )?;
+    exports.set("get_text_object", lua.create_function(get_text_object)?)?;
+    exports.set("get_pair_tree", lua.create_function(get_pair_tree)?)?;
+    exports.set(
+        "get_selection_ranges",
+        lua.create_function(get_selection_ranges)?,
+    )?;
+    exports.set("get_folds", lua.create_function(get_folds)?)?;
+    exports.set("get_scope_at", lua.create_function(get_scope_at)?)?;
+    exports.set("get_scope_guides", lua.create_function(get_scope_guides)?)?;
+    exports.set("suggest_indent", lua.create_function(suggest_indent)?)?;
+    exports.set("reindent_range", lua.create_function(reindent_range)?)?;
+    exports.set(
+        "get_autopair_action",
+        lua.create_function(get_autopair_action)?,
+    )?;
+    exports.set("simulate_edit", lua.create_function(simulate_edit)?)?;
+    exports.set(
+        "expression_end_after",
+        lua.create_function(expression_end_after)?,
+    )?;
+    exports.set(
+        "expression_start_before",
+        lua.create_function(expression_start_before)?,
+    )?;
+    exports.set("surround_delete", lua.create_function(surround_delete)?)?;
+    exports.set("surround_change", lua.create_function(surround_change)?)?;
+    exports.set("structural_edit", lua.create_function(structural_edit)?)?;
+    exports.set("parinfer", lua.create_function(parinfer)?)?;
+    exports.set("batch_query", lua.create_function(batch_query)?)?;
     Ok(exports)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(bufnr: usize, lines: &[&str]) {
+        insert_parsed_buffer(bufnr, ParsedBuffer::parse("rust", 4, lines).unwrap());
+    }
+
+    #[test]
+    fn test_panic_invalidates_only_affected_buffer() {
+        parse(1001, &["{", "}"]);
+        parse(1002, &["(", ")"]);
+
+        // A tab width of 0 panics when rematching the unclosed `(` by indent
+        let lines = ["{", "(", "}"].map(String::from).to_vec();
+        let result = parse_buffer_lines((
+            1001,
+            0,
+            "rust".to_string(),
+            lines,
+            Some(0),
+            Some(2),
+            Some(3),
+            None,
+            None,
+        ));
+
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("panicked while handling buffer 1001"));
+        assert!(get_parsed_buffer(1001).is_none());
+        assert!(with_parsed_buffer(1002, |buffer| Ok(buffer.match_at(0, 0)))
+            .unwrap()
+            .0
+            .flatten()
+            .is_some());
+    }
+
+    #[test]
+    fn test_query_panic_returns_error() {
+        parse(1003, &["{", "}"]);
+
+        let result = with_parsed_buffer::<()>(1003, |_| panic!("query failed"));
+        assert!(result.unwrap_err().to_string().contains("query failed"));
+
+        // Read panics don't invalidate the buffer
+        assert!(get_parsed_buffer(1003).is_some());
+    }
+
+    #[test]
+    fn test_query_errors() {
+        parse(1005, &["{", "}"]);
+
+        assert_eq!(
+            with_parsed_buffer(1006, |_| Ok(())).unwrap(),
+            (None, Some(Error::UnknownBuffer(1006)))
+        );
+        assert_eq!(
+            with_parsed_buffer(1005, |buffer| buffer.validate_position(2, 0)).unwrap(),
+            (
+                None,
+                Some(Error::LineOutOfRange {
+                    line: 2,
+                    line_count: 2
+                })
+            )
+        );
+        assert_eq!(
+            with_parsed_buffer(1005, |buffer| buffer.validate_position(0, 2)).unwrap(),
+            (
+                None,
+                Some(Error::ColOutOfRange {
+                    line: 0,
+                    col: 2,
+                    line_len: 1
+                })
+            )
+        );
+        assert_eq!(
+            with_parsed_buffer(1005, |buffer| buffer.validate_position(0, 1)).unwrap(),
+            (Some(()), None)
+        );
+    }
+
+    #[test]
+    fn test_batch_query() {
+        parse(1007, &["(a)", "b"]);
+        parse(1008, &["{", "}"]);
+
+        let batch = run_batch(
+            "match_at",
+            &[
+                [1008, 1, 0],
+                [1007, 0, 2],
+                [1009, 0, 0],
+                [1007, 0, 1],
+                [1007, 5, 0],
+                [1008, 0, 0],
+            ],
+        )
+        .unwrap();
+
+        // Results are in the order of the positions, even when grouped by buffer
+        let cols = (batch.results.iter())
+            .map(|result| match result {
+                Some(BatchValue::Match(match_)) => Some((match_.kind, match_.col)),
+                None => None,
+                Some(value) => panic!("unexpected value {value:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cols,
+            vec![
+                Some((Kind::Closing, 0)),
+                Some((Kind::Closing, 2)),
+                None,
+                None,
+                None,
+                Some((Kind::Opening, 0)),
+            ]
+        );
+
+        // Errors are keyed by the 1-indexed position, and an unknown buffer in the middle of
+        // the batch doesn't affect the positions around it
+        assert_eq!(
+            batch.errors,
+            HashMap::from([
+                (3, Error::UnknownBuffer(1009)),
+                (
+                    5,
+                    Error::LineOutOfRange {
+                        line: 5,
+                        line_count: 2
+                    }
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_batch_query_invalid_query() {
+        assert_eq!(
+            run_batch("foo", &[[1007, 0, 0]]),
+            Err(Error::InvalidArgument {
+                name: "query",
+                value: "foo".to_string()
+            })
+        );
+        assert_eq!(
+            run_batch("stack_height", &[]),
+            Ok(BatchResults {
+                results: vec![],
+                errors: HashMap::new()
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalidate_ignores_replaced_buffer() {
+        parse(1004, &["{", "}"]);
+        let old = get_parsed_buffer(1004).unwrap();
+        parse(1004, &["(", ")"]);
+
+        invalidate_parsed_buffer(1004, &old);
+        assert!(get_parsed_buffer(1004).is_some());
+    }
+}
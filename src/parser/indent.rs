@@ -1,45 +1,180 @@
 //! Module for calculating indentation levels in source code.
 
+use mlua::IntoLua;
+
 /// Calculate indentation levels with a custom tab width.
 ///
 /// Returns a vector where each element represents the indentation level
-/// (in spaces) of a line that contains non-whitespace characters.
+/// (in spaces) of a line that contains non-whitespace characters, saturating at `u16::MAX`.
 /// Lines that contain only whitespace receive the previous line's indentation.
 ///
 /// # Examples
 ///
 /// ```
-/// use blink_pairs::indent::indent_levels;
+/// use blink_pairs::parser::indent::indent_levels;
 ///
 /// let src = ["fn main() {", "\tprintln!(\"hello\");", "}"];
 /// let indents = indent_levels(&src, 8);
-/// assert_eq!(indents, vec![0, 4, 0]);
+/// assert_eq!(indents, vec![0, 8, 0]);
 /// ```
-pub fn indent_levels(lines: &[&str], tab_width: u8) -> Vec<u8> {
+pub fn indent_levels(lines: &[&str], tab_width: u8) -> Vec<u16> {
     let mut last_indent = None;
     let mut indents = Vec::with_capacity(lines.len());
-    'outer: for line in lines {
-        let mut indent: u8 = 0;
-        for c in line.as_bytes() {
-            match c {
-                b' ' => indent = indent.saturating_add(1),
-                b'\t' => indent = indent.saturating_add(tab_width),
-                _ => {
-                    indents.push(indent);
-                    last_indent = Some(indent);
-                    continue 'outer;
-                }
-            }
+    for line in lines {
+        let whitespace_len = line.len() - line.trim_start_matches([' ', '\t']).len();
+        let indent = text_width(&line[..whitespace_len], tab_width).min(u16::MAX as usize) as u16;
+        if whitespace_len < line.len() {
+            indents.push(indent);
+            last_indent = Some(indent);
+        } else {
+            // this line is entirely whitespace, so use the previous line's indentation.
+            indents.push(*last_indent.get_or_insert(indent));
         }
-        // this line is entirely whitespace, so use the previous line's indentation.
-        indents.push(*last_indent.get_or_insert(indent));
     }
     indents
 }
 
+/// Width of the text in columns, where tabs count as `tab_width` columns like in
+/// [`indent_levels`] and any other character as one column
+pub fn text_width(text: &str, tab_width: u8) -> usize {
+    text.chars()
+        .map(|char| match char {
+            '\t' => tab_width as usize,
+            _ => 1,
+        })
+        .sum()
+}
+
+/// Indentation style inferred from the lines of a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndentStyle {
+    /// Whether the file is indented with spaces rather than tabs, or `None` when no lines
+    /// are indented
+    pub expand_tab: Option<bool>,
+    /// Most common increase in indentation between consecutive lines indented with spaces,
+    /// or `None` when the file is indented with tabs or no lines are indented
+    pub shiftwidth: Option<u16>,
+    /// Lines which mix tabs and spaces in their indentation, or are indented differently
+    /// from the rest of the file
+    pub mixed_lines: Vec<usize>,
+    /// Between 0 and 1, from the share of lines agreeing with `expand_tab` multiplied by
+    /// the share of indentation increases agreeing with `shiftwidth`
+    pub confidence: f32,
+}
+
+impl IntoLua for IndentStyle {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+
+        table.set("expand_tab", self.expand_tab)?;
+        table.set("shiftwidth", self.shiftwidth)?;
+        table.set("mixed_lines", self.mixed_lines)?;
+        table.set("confidence", self.confidence)?;
+
+        (&table).into_lua(lua)
+    }
+}
+
+/// Largest indentation increase considered when inferring the shiftwidth, since larger
+/// increases are likely alignment rather than indentation
+const MAX_SHIFTWIDTH: u16 = 8;
+
+/// Infers whether the lines are indented with tabs or spaces, and the shiftwidth from
+/// a histogram of the increases in indentation between consecutive non-blank lines.
+/// Lines for which `skip` returns true (i.e. inside block comments) are ignored
+///
+/// # Examples
+///
+/// ```
+/// use blink_pairs::parser::indent::detect_indent;
+///
+/// let src = ["fn main() {", "  if foo {", "    bar();", "  }", "}"];
+/// let style = detect_indent(&src, |_| false);
+/// assert_eq!(style.expand_tab, Some(true));
+/// assert_eq!(style.shiftwidth, Some(2));
+/// ```
+pub fn detect_indent(lines: &[&str], skip: impl Fn(usize) -> bool) -> IndentStyle {
+    let mut tab_lines = vec![];
+    let mut space_lines = vec![];
+    let mut mixed_lines = vec![];
+    let mut deltas = [0usize; MAX_SHIFTWIDTH as usize + 1];
+
+    // Indentation of the previous non-blank line, when indented with spaces only
+    let mut prev_spaces: Option<u16> = None;
+    for (line_number, line) in lines.iter().enumerate() {
+        if skip(line_number) || line.trim().is_empty() {
+            continue;
+        }
+
+        let whitespace = &line[..line.len() - line.trim_start_matches([' ', '\t']).len()];
+        let has_tabs = whitespace.contains('\t');
+        let has_spaces = whitespace.contains(' ');
+        match (has_tabs, has_spaces) {
+            (true, true) => mixed_lines.push(line_number),
+            (true, false) => tab_lines.push(line_number),
+            (false, true) => space_lines.push(line_number),
+            (false, false) => {}
+        }
+
+        let spaces = (!has_tabs).then_some(whitespace.len().min(u16::MAX as usize) as u16);
+        if let (Some(prev), Some(spaces)) = (prev_spaces, spaces) {
+            if spaces > prev && spaces - prev <= MAX_SHIFTWIDTH {
+                deltas[(spaces - prev) as usize] += 1;
+            }
+        }
+        prev_spaces = spaces;
+    }
+
+    let indented_count = tab_lines.len() + space_lines.len();
+    if indented_count == 0 {
+        return IndentStyle {
+            expand_tab: None,
+            shiftwidth: None,
+            mixed_lines,
+            confidence: 0.,
+        };
+    }
+
+    let expand_tab = space_lines.len() >= tab_lines.len();
+    let (majority, minority) = if expand_tab {
+        (space_lines, tab_lines)
+    } else {
+        (tab_lines, space_lines)
+    };
+    let mut confidence = majority.len() as f32 / (indented_count + mixed_lines.len()) as f32;
+
+    mixed_lines.extend(minority);
+    mixed_lines.sort();
+
+    let mut shiftwidth = None;
+    if expand_tab {
+        // Prefers the smaller shiftwidth on ties
+        let (width, count) = deltas
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, count)| **count)
+            .unwrap();
+        let total = deltas.iter().sum::<usize>();
+        if total > 0 {
+            shiftwidth = Some(width as u16);
+            confidence *= *count as f32 / total as f32;
+        } else {
+            confidence = 0.;
+        }
+    }
+
+    IndentStyle {
+        expand_tab: Some(expand_tab),
+        shiftwidth,
+        mixed_lines,
+        confidence,
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::indent_levels;
+    use super::{detect_indent, indent_levels};
 
     #[test]
     fn test_basic_indentation() {
@@ -138,4 +273,48 @@ mod tests {
         let result = indent_levels(&src, 4);
         assert_eq!(result, vec![0, 4, 4]);
     }
+
+    #[test]
+    fn test_deep_indentation_does_not_saturate() {
+        let line = " ".repeat(300) + "a";
+        let result = indent_levels(&[&line, "\t\tb"], 200);
+        assert_eq!(result, vec![300, 400]);
+    }
+
+    #[test]
+    fn test_detect_spaces() {
+        let src = [
+            "def a():",
+            "    if b:",
+            "        c()",
+            "",
+            "    d(1,",
+            "      2)",
+            "\te()",
+        ];
+        let style = detect_indent(&src, |_| false);
+        assert_eq!(style.expand_tab, Some(true));
+        assert_eq!(style.shiftwidth, Some(4));
+        assert_eq!(style.mixed_lines, vec![6]);
+        // 4 of 5 lines use spaces, and 2 of 3 increases are 4 spaces
+        assert!((style.confidence - 0.8 * 2. / 3.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_detect_tabs() {
+        let src = ["a {", "\tb {", "\t\tc", "\t \td", "\t}", "}"];
+        let style = detect_indent(&src, |_| false);
+        assert_eq!(style.expand_tab, Some(false));
+        assert_eq!(style.shiftwidth, None);
+        assert_eq!(style.mixed_lines, vec![3]);
+        assert_eq!(style.confidence, 0.75);
+    }
+
+    #[test]
+    fn test_detect_skipped_and_unindented() {
+        let src = ["/*", " * a", " */", "b"];
+        let style = detect_indent(&src, |line| line == 1 || line == 2);
+        assert_eq!(style.expand_tab, None);
+        assert_eq!(style.confidence, 0.);
+    }
 }
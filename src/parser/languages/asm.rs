@@ -0,0 +1,12 @@
+use crate::parser::*;
+use matcher_macros::define_matcher;
+
+define_matcher!(Asm {
+    delimiters: [
+        "(" => ")",
+        "[" => "]",
+        "{" => "}"
+    ],
+    line_comment: [";"],
+    string: ["\"", "'"]
+});
@@ -0,0 +1,24 @@
+use crate::parser::*;
+use matcher_macros::define_matcher;
+
+// Bracket arguments and comments close on the same number of `=`, up to 3 of them
+define_matcher!(CMake {
+    delimiters: [
+        "(" => ")",
+        "{" => "}"
+    ],
+    line_comment: ["#"],
+    block_comment: [
+        "#[[" => "]]",
+        "#[=[" => "]=]",
+        "#[==[" => "]==]",
+        "#[===[" => "]===]"
+    ],
+    string: ["\""],
+    block_string: [
+        "[[" => "]]",
+        "[=[" => "]=]",
+        "[==[" => "]==]",
+        "[===[" => "]===]"
+    ]
+});
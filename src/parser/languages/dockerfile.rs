@@ -0,0 +1,83 @@
+use crate::parser::*;
+
+/// Dockerfile, where quotes and comments only start at the start of a word, so that apostrophes
+/// in text such as `LABEL maintainer=O'Brien` or `RUN echo don't` aren't strings and
+/// `${VAR#prefix}` isn't a comment
+#[derive(Default)]
+pub struct Dockerfile {
+    /// Text of the current line, for looking at the characters before the tokens
+    line: String,
+}
+
+impl Dockerfile {
+    /// Whether the column is at the start of the line, or after whitespace or the punctuation
+    /// which separates words in instructions and shell commands
+    fn is_word_start(&self, col: usize) -> bool {
+        self.line[..col].chars().next_back().is_none_or(|char| {
+            char.is_whitespace() || matches!(char, '=' | '[' | '(' | '{' | ',' | ';' | '|' | '&')
+        })
+    }
+}
+
+impl Matcher for Dockerfile {
+    const TOKENS: &[u8] = b"\"#'()[]{}";
+
+    fn line(&mut self, text: &str, state: State) -> State {
+        self.line.clear();
+        self.line.push_str(text);
+        state
+    }
+
+    fn call<I>(
+        &mut self,
+        _matches_by_line: &mut Vec<Vec<Match>>,
+        matches: &mut Vec<Match>,
+        _tokens: &mut MultiPeek<I>,
+        state: State,
+        token: CharPos,
+        escaped: bool,
+    ) -> State
+    where
+        I: Iterator<Item = CharPos>,
+    {
+        match (state, token.byte) {
+            // Backslashes only escape in double quoted strings
+            (State::InString(quote), b'"' | b'\'')
+                if quote.as_bytes()[0] == token.byte && (quote == "'" || !escaped) =>
+            {
+                matches.push(Match::new(Kind::Closing, Token::String(quote), token.col));
+                State::Normal
+            }
+            (State::Normal, _) if escaped => state,
+            (State::Normal, b'"' | b'\'') if self.is_word_start(token.col) => {
+                let quote = match token.byte {
+                    b'"' => "\"",
+                    _ => "'",
+                };
+                matches.push(Match::new(Kind::Opening, Token::String(quote), token.col));
+                State::InString(quote)
+            }
+            (State::Normal, b'#') if self.is_word_start(token.col) => {
+                matches.push(Match::line_comment("#", token.col));
+                State::InLineComment
+            }
+            (State::Normal, b'(' | b'[' | b'{' | b')' | b']' | b'}') => {
+                let (kind, opening, closing) = match token.byte {
+                    b'(' => (Kind::Opening, "(", ")"),
+                    b'[' => (Kind::Opening, "[", "]"),
+                    b'{' => (Kind::Opening, "{", "}"),
+                    b')' => (Kind::Closing, "(", ")"),
+                    b']' => (Kind::Closing, "[", "]"),
+                    _ => (Kind::Closing, "{", "}"),
+                };
+                matches.push(Match::new(
+                    kind,
+                    Token::Delimiter(opening, closing),
+                    token.col,
+                ));
+                State::Normal
+            }
+            _ => state,
+        }
+    }
+}
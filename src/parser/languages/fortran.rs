@@ -0,0 +1,11 @@
+use crate::parser::*;
+use matcher_macros::define_matcher;
+
+define_matcher!(Fortran {
+    delimiters: [
+        "(" => ")",
+        "[" => "]"
+    ],
+    line_comment: ["!"],
+    string: ["\"", "'"]
+});
@@ -0,0 +1,13 @@
+use crate::parser::*;
+use matcher_macros::define_matcher;
+
+define_matcher!(GraphQl {
+    delimiters: [
+        "(" => ")",
+        "[" => "]",
+        "{" => "}"
+    ],
+    line_comment: ["#"],
+    string: ["\""],
+    block_string: ["\"\"\"" => "\"\"\""]
+});
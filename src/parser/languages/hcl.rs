@@ -0,0 +1,13 @@
+use crate::parser::*;
+use matcher_macros::define_matcher;
+
+define_matcher!(Hcl {
+    delimiters: [
+        "(" => ")",
+        "[" => "]",
+        "{" => "}"
+    ],
+    line_comment: ["#", "//"],
+    block_comment: ["/*" => "*/"],
+    string: ["\""]
+});
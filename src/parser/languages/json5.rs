@@ -0,0 +1,12 @@
+use crate::parser::*;
+use matcher_macros::define_matcher;
+
+define_matcher!(Json5 {
+    delimiters: [
+        "[" => "]",
+        "{" => "}"
+    ],
+    line_comment: ["//"],
+    block_comment: ["/*" => "*/"],
+    string: ["\"", "'"]
+});
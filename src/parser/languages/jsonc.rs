@@ -0,0 +1,13 @@
+use crate::parser::*;
+use matcher_macros::define_matcher;
+
+// JSON with comments, e.g. VS Code and TypeScript configs
+define_matcher!(Jsonc {
+    delimiters: [
+        "[" => "]",
+        "{" => "}"
+    ],
+    line_comment: ["//"],
+    block_comment: ["/*" => "*/"],
+    string: ["\""]
+});
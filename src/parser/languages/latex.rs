@@ -1,14 +1,193 @@
 use crate::parser::*;
-use matcher_macros::define_matcher;
-
-define_matcher!(Latex {
-    delimiters: [
-        "(" => ")",
-        "[" => "]",
-        "{" => "}"
-    ],
-    line_comment: ["%"],
-    string: ["\""],
-    char: ["'"],
-    block_string: ["$" => "$", "$$" => "$$"]
-});
+
+/// Commands which size the following delimiter, as pairs of the opening and closing command
+const SIZING_COMMANDS: &[(&str, &str)] = &[
+    ("\\left", "\\right"),
+    ("\\bigl", "\\bigr"),
+    ("\\Bigl", "\\Bigr"),
+    ("\\biggl", "\\biggr"),
+    ("\\Biggl", "\\Biggr"),
+];
+
+/// LaTeX, with `\begin{name}` and `\end{name}` matched by name, sizing commands such as
+/// `\left(` and `\right)` matched regardless of their delimiter, and math (`$`, `$$`, `\(` and
+/// `\[`) as a span in which delimiters are still matched
+#[derive(Default)]
+pub struct Latex {
+    /// Text of the current line, for looking at the commands before the tokens
+    line: String,
+}
+
+impl Latex {
+    /// Gets the sizing command directly before the column, with whether it's the opening
+    fn sizing_command_before(&self, col: usize) -> Option<(usize, Token, Kind)> {
+        let before = &self.line[..col];
+        SIZING_COMMANDS.iter().find_map(|&(open, close)| {
+            let token = Token::Delimiter(open, close);
+            if before.ends_with(open) {
+                Some((col - open.len(), token, Kind::Opening))
+            } else if before.ends_with(close) {
+                Some((col - close.len(), token, Kind::Closing))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Gets the `\begin{name}` or `\end{name}` with the `{` at the column, as a match of the
+    /// command paired by the name, and the column of the `}`
+    fn environment_at(&self, col: usize) -> Option<(Match, usize)> {
+        let before = &self.line[..col];
+        let (command_col, kind) = if before.ends_with("\\begin") {
+            (col - "\\begin".len(), Kind::Opening)
+        } else if before.ends_with("\\end") {
+            (col - "\\end".len(), Kind::Closing)
+        } else {
+            return None;
+        };
+
+        let name_len = self.line[col + 1..].find('}')?;
+        let name = &self.line[col + 1..col + 1 + name_len];
+        let environment = Token::Delimiter("\\begin", "\\end");
+        Some((
+            Match::new(kind, environment, command_col).with_name(name),
+            col + 1 + name_len,
+        ))
+    }
+}
+
+impl Matcher for Latex {
+    const TOKENS: &[u8] = b"$%().[]{|}";
+
+    fn line(&mut self, text: &str, state: State) -> State {
+        self.line.clear();
+        self.line.push_str(text);
+        state
+    }
+
+    fn call<I>(
+        &mut self,
+        _matches_by_line: &mut Vec<Vec<Match>>,
+        matches: &mut Vec<Match>,
+        tokens: &mut MultiPeek<I>,
+        state: State,
+        token: CharPos,
+        escaped: bool,
+    ) -> State
+    where
+        I: Iterator<Item = CharPos>,
+    {
+        // Delimiters after a sizing command, including the escaped `\{` and `\}`
+        if matches!(
+            token.byte,
+            b'(' | b')' | b'[' | b']' | b'{' | b'}' | b'.' | b'|'
+        ) {
+            let command_end = if escaped { token.col - 1 } else { token.col };
+            if let Some((col, sizing, kind)) = self.sizing_command_before(command_end) {
+                matches.push(Match::new(kind, sizing, col));
+                return state;
+            }
+        }
+
+        // Environments
+        if token.byte == b'{' && !escaped {
+            if let Some((environment, end_col)) = self.environment_at(token.col) {
+                matches.push(environment);
+                // Skip the tokens in the name and the `}`
+                tokens.reset_peek();
+                while tokens
+                    .peek()
+                    .is_some_and(|next| next.col <= end_col && next.byte != b'\n')
+                {
+                    tokens.next();
+                }
+                return state;
+            }
+        }
+
+        match (token.byte, escaped) {
+            (b'%', false) => {
+                // Comments don't change the state, so math continues onto the next line
+                matches.push(Match::line_comment("%", token.col));
+                tokens.reset_peek();
+                while tokens.peek().is_some_and(|next| next.byte != b'\n') {
+                    tokens.next();
+                }
+                state
+            }
+
+            // Math
+            (b'(' | b'[', true) => {
+                let (opening, closing) = match token.byte {
+                    b'(' => ("\\(", "\\)"),
+                    _ => ("\\[", "\\]"),
+                };
+                if !state.can_open_span(&[]) {
+                    return state;
+                }
+                let span = Token::BlockSpan("math", opening, closing);
+                matches.push(Match::new(Kind::Opening, span, token.col - 1));
+                state.open_span(OpenSpan::block("math", opening))
+            }
+            (b')' | b']', true) => {
+                let opening = match token.byte {
+                    b')' => "\\(",
+                    _ => "\\[",
+                };
+                match state.spans().top() {
+                    Some(span) if span.name == "math" && span.opening == opening => {
+                        let closing = match token.byte {
+                            b')' => "\\)",
+                            _ => "\\]",
+                        };
+                        let span = Token::BlockSpan("math", opening, closing);
+                        matches.push(Match::new(Kind::Closing, span, token.col - 1));
+                        state.close_span()
+                    }
+                    _ => state,
+                }
+            }
+            (b'$', false) => {
+                tokens.reset_peek();
+                let opening = match tokens.peek() {
+                    Some(next) if next.byte == b'$' && next.col == token.col + 1 => {
+                        tokens.next();
+                        "$$"
+                    }
+                    _ => "$",
+                };
+                let span = Token::BlockSpan("math", opening, opening);
+                match state.spans().top() {
+                    Some(top) if top.name == "math" && top.opening == opening => {
+                        matches.push(Match::new(Kind::Closing, span, token.col));
+                        state.close_span()
+                    }
+                    _ if state.can_open_span(&[]) => {
+                        matches.push(Match::new(Kind::Opening, span, token.col));
+                        state.open_span(OpenSpan::block("math", opening))
+                    }
+                    _ => state,
+                }
+            }
+
+            // `\{` and `\}` are literal braces
+            (b'(' | b'[' | b'{' | b')' | b']' | b'}', false) => {
+                let (kind, opening, closing) = match token.byte {
+                    b'(' => (Kind::Opening, "(", ")"),
+                    b'[' => (Kind::Opening, "[", "]"),
+                    b'{' => (Kind::Opening, "{", "}"),
+                    b')' => (Kind::Closing, "(", ")"),
+                    b']' => (Kind::Closing, "[", "]"),
+                    _ => (Kind::Closing, "{", "}"),
+                };
+                matches.push(Match::new(
+                    kind,
+                    Token::Delimiter(opening, closing),
+                    token.col,
+                ));
+                state
+            }
+            _ => state,
+        }
+    }
+}
@@ -0,0 +1,12 @@
+use crate::parser::*;
+use matcher_macros::define_matcher;
+
+// Quotes aren't strings in make, and apostrophes are common in the text passed to functions
+// such as `$(info it's done)`
+define_matcher!(Make {
+    delimiters: [
+        "(" => ")",
+        "{" => "}"
+    ],
+    line_comment: ["#"]
+});
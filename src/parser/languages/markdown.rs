@@ -1,17 +1,282 @@
 use crate::parser::*;
-use matcher_macros::define_matcher;
-
-define_matcher!(Markdown {
-    inline_span: {
-        math: "$" => "$",
-        italic: "_" => "_",
-        bold: "*" => "*",
-        bold: "**" => "**",
-        strikethrough: "~~" => "~~"
-    },
-    block_span: {
-        math: "$$" => "$$",
-        code: "```" => "```"
-    },
-});
 
+const BACKTICKS: &str = "````````````````";
+const TILDES: &str = "~~~~~~~~~~~~~~~~";
+/// Spans which may contain other spans and continue until the end of the paragraph, unlike
+/// code and math
+const EMPHASIS: &[&str] = &["italic", "bold", "strikethrough"];
+
+/// Markdown, following the CommonMark rules for emphasis, where `*` and `_` runs open or close
+/// emphasis based on the characters around them, and code spans close on a run of the same
+/// number of backticks. Emphasis may span lines, until the end of the paragraph
+#[derive(Default)]
+pub struct Markdown {
+    /// Text of the current line, for looking at the characters around the tokens
+    line: String,
+    /// Number of unclosed parentheses in link destinations on the current line
+    link_parens: usize,
+}
+
+impl Markdown {
+    /// Whether the column is preceded only by up to 3 spaces, as required for code fences
+    fn is_line_start(&self, col: usize) -> bool {
+        col <= 3 && self.line[..col].bytes().all(|byte| byte == b' ')
+    }
+
+    fn is_followed_by_digit(&self, col: usize) -> bool {
+        self.line[col..].starts_with(|char: char| char.is_ascii_digit())
+    }
+
+    /// Whether the delimiter run at the column may open and close emphasis
+    fn flanking(&self, byte: u8, col: usize, len: usize) -> (bool, bool) {
+        // The start and end of the line count as whitespace
+        let before = self.line[..col].chars().next_back().unwrap_or(' ');
+        let after = self.line[col + len..].chars().next().unwrap_or(' ');
+
+        let left_flanking = !after.is_whitespace()
+            && (!is_punctuation(after) || before.is_whitespace() || is_punctuation(before));
+        let right_flanking = !before.is_whitespace()
+            && (!is_punctuation(before) || after.is_whitespace() || is_punctuation(after));
+
+        match byte {
+            // Avoids emphasis inside of words, e.g. `snake_case`
+            b'_' => (
+                left_flanking && (!right_flanking || is_punctuation(before)),
+                right_flanking && (!left_flanking || is_punctuation(after)),
+            ),
+            _ => (left_flanking, right_flanking),
+        }
+    }
+
+    fn emphasis(
+        &self,
+        matches: &mut Vec<Match>,
+        mut state: State,
+        token: CharPos,
+        len: usize,
+    ) -> State {
+        let (can_open, can_close) = self.flanking(token.byte, token.col, len);
+        let (single, double) = match token.byte {
+            b'*' => (Some("*"), "**"),
+            b'_' => (Some("_"), "__"),
+            _ => (None, "~~"),
+        };
+
+        let mut col = token.col;
+        let mut remaining = len;
+
+        // Close the emphasis opened by the same character, innermost first
+        while let Some(span) = state.spans().top().filter(|span| {
+            can_close
+                && EMPHASIS.contains(&span.name)
+                && span.opening.as_bytes()[0] == token.byte
+                && span.opening.len() <= remaining
+        }) {
+            let token = Token::BlockSpan(span.name, span.opening, span.opening);
+            matches.push(Match::new(Kind::Closing, token, col));
+            col += span.opening.len();
+            remaining -= span.opening.len();
+            state = state.close_span();
+        }
+
+        if !can_open {
+            return state;
+        }
+        // `***` opens both, with the bold nested in the italic
+        let openings = match (remaining, single) {
+            (1, Some(single)) => vec![("italic", single)],
+            (2, Some(_)) => vec![("bold", double)],
+            (3, Some(single)) => vec![("italic", single), ("bold", double)],
+            (2, None) => vec![("strikethrough", double)],
+            _ => vec![],
+        };
+        for (name, opening) in openings {
+            if !state.can_open_span(EMPHASIS) {
+                break;
+            }
+            let token = Token::BlockSpan(name, opening, opening);
+            matches.push(Match::new(Kind::Opening, token, col));
+            col += opening.len();
+            state = state.open_span(OpenSpan::block(name, opening));
+        }
+        state
+    }
+}
+
+impl Matcher for Markdown {
+    const TOKENS: &[u8] = b"$()*[]_`~";
+
+    fn line(&mut self, text: &str, state: State) -> State {
+        self.line.clear();
+        self.line.push_str(text);
+        self.link_parens = 0;
+
+        // Blank lines end the paragraph, along with its emphasis
+        if text.trim().is_empty() {
+            close_emphasis(state)
+        } else {
+            state
+        }
+    }
+
+    fn call<I>(
+        &mut self,
+        _matches_by_line: &mut Vec<Vec<Match>>,
+        matches: &mut Vec<Match>,
+        tokens: &mut MultiPeek<I>,
+        state: State,
+        token: CharPos,
+        escaped: bool,
+    ) -> State
+    where
+        I: Iterator<Item = CharPos>,
+    {
+        // Length of the run of the same character, skipping the rest of the run
+        let mut len = 1;
+        if matches!(token.byte, b'$' | b'*' | b'_' | b'`' | b'~') {
+            tokens.reset_peek();
+            while tokens
+                .peek()
+                .is_some_and(|next| next.byte == token.byte && next.col == token.col + len)
+            {
+                len += 1;
+            }
+            for _ in 1..len {
+                tokens.next();
+            }
+        }
+
+        // Code and math only end on their closing
+        match state.spans().top() {
+            Some(span) if span.name == "code" => {
+                let closes = token.byte == span.opening.as_bytes()[0]
+                    && match span.block {
+                        true => len >= span.opening.len() && self.is_line_start(token.col),
+                        false => len == span.opening.len(),
+                    };
+                if !closes {
+                    return state;
+                }
+                let closing = match span.block {
+                    true => Token::BlockSpan("code", span.opening, span.opening),
+                    false => Token::InlineSpan("code", span.opening, span.opening),
+                };
+                matches.push(Match::new(Kind::Closing, closing, token.col));
+                return state.close_span();
+            }
+            Some(span) if span.name == "math" => {
+                if token.byte != b'$'
+                    || len != span.opening.len()
+                    || self.is_followed_by_digit(token.col + len)
+                {
+                    return state;
+                }
+                let closing = match span.block {
+                    true => Token::BlockSpan("math", "$$", "$$"),
+                    false => Token::InlineSpan("math", "$", "$"),
+                };
+                matches.push(Match::new(Kind::Closing, closing, token.col));
+                return state.close_span();
+            }
+            _ => {}
+        }
+
+        if escaped {
+            return state;
+        }
+
+        match token.byte {
+            // Fenced code blocks, which also end the paragraph
+            b'`' | b'~' if len >= 3 && self.is_line_start(token.col) => {
+                let fences = if token.byte == b'`' {
+                    BACKTICKS
+                } else {
+                    TILDES
+                };
+                let fence = &fences[..len.min(fences.len())];
+                let state = close_emphasis(state);
+                matches.push(Match::new(
+                    Kind::Opening,
+                    Token::BlockSpan("code", fence, fence),
+                    token.col,
+                ));
+                state.open_span(OpenSpan::block("code", fence))
+            }
+            b'`' if state.can_open_span(EMPHASIS) => {
+                let ticks = &BACKTICKS[..len.min(BACKTICKS.len())];
+                matches.push(Match::new(
+                    Kind::Opening,
+                    Token::InlineSpan("code", ticks, ticks),
+                    token.col,
+                ));
+                state.open_span(OpenSpan::inline("code", ticks))
+            }
+            // `$` followed by a digit is a price, e.g. `$5`
+            b'$' if len <= 2
+                && !self.is_followed_by_digit(token.col + len)
+                && state.can_open_span(EMPHASIS) =>
+            {
+                let span = match len {
+                    1 => Token::InlineSpan("math", "$", "$"),
+                    _ => Token::BlockSpan("math", "$$", "$$"),
+                };
+                let open_span = match len {
+                    1 => OpenSpan::inline("math", "$"),
+                    _ => OpenSpan::block("math", "$$"),
+                };
+                matches.push(Match::new(Kind::Opening, span, token.col));
+                state.open_span(open_span)
+            }
+            b'*' | b'_' | b'~' => self.emphasis(matches, state, token, len),
+
+            // Links and images, where parentheses only count as the destination of a link
+            b'[' => {
+                matches.push(Match::new(
+                    Kind::Opening,
+                    Token::Delimiter("[", "]"),
+                    token.col,
+                ));
+                state
+            }
+            b']' => {
+                matches.push(Match::new(
+                    Kind::Closing,
+                    Token::Delimiter("[", "]"),
+                    token.col,
+                ));
+                state
+            }
+            b'(' if self.link_parens > 0 || self.line[..token.col].ends_with(']') => {
+                self.link_parens += 1;
+                matches.push(Match::new(
+                    Kind::Opening,
+                    Token::Delimiter("(", ")"),
+                    token.col,
+                ));
+                state
+            }
+            b')' if self.link_parens > 0 => {
+                self.link_parens -= 1;
+                matches.push(Match::new(
+                    Kind::Closing,
+                    Token::Delimiter("(", ")"),
+                    token.col,
+                ));
+                state
+            }
+            _ => state,
+        }
+    }
+}
+
+/// Leaves the emphasis spans, keeping the code and math blocks
+fn close_emphasis(state: State) -> State {
+    let mut spans = state.spans();
+    spans.retain(|span| !EMPHASIS.contains(&span.name));
+    State::from_spans(spans)
+}
+
+fn is_punctuation(char: char) -> bool {
+    char.is_ascii_punctuation()
+        || (!char.is_ascii() && !char.is_alphanumeric() && !char.is_whitespace())
+}
@@ -1,77 +1,117 @@
+mod ada;
+mod asm;
 mod c;
 mod clojure;
+mod cmake;
 mod cpp;
 mod csharp;
+mod css;
+mod d;
 mod dart;
+mod dockerfile;
 mod elixir;
 mod erlang;
 mod fennel;
+mod fortran;
 mod fsharp;
 mod go;
+mod graphql;
 mod haskell;
 mod haxe;
+mod hcl;
 mod java;
 mod javascript;
 mod json;
+mod json5;
+mod jsonc;
+mod julia;
 mod kotlin;
 mod latex;
 mod lean;
 mod lua;
+mod make;
 mod markdown;
+mod nim;
 mod nix;
 mod objc;
 mod ocaml;
 mod perl;
 mod php;
+mod powershell;
+mod proto;
 mod python;
 mod r;
 mod ruby;
 mod rust;
 mod scala;
 mod scheme;
+mod scss;
 mod shell;
 mod sql;
 mod swift;
 mod toml;
 mod typst;
+mod verilog;
+mod vhdl;
 mod vim;
+mod yaml;
 mod zig;
 
+pub use ada::Ada;
+pub use asm::Asm;
 pub use c::C;
 pub use clojure::Clojure;
+pub use cmake::CMake;
 pub use cpp::Cpp;
 pub use csharp::CSharp;
+pub use css::Css;
+pub use d::D;
 pub use dart::Dart;
+pub use dockerfile::Dockerfile;
 pub use elixir::Elixir;
 pub use erlang::Erlang;
 pub use fennel::Fennel;
+pub use fortran::Fortran;
 pub use fsharp::FSharp;
 pub use go::Go;
+pub use graphql::GraphQl;
 pub use haskell::Haskell;
 pub use haxe::Haxe;
+pub use hcl::Hcl;
 pub use java::Java;
 pub use javascript::JavaScript;
 pub use json::Json;
+pub use json5::Json5;
+pub use jsonc::Jsonc;
+pub use julia::Julia;
 pub use kotlin::Kotlin;
 pub use latex::Latex;
 pub use lean::Lean;
 pub use lua::Lua;
+pub use make::Make;
 pub use markdown::Markdown;
+pub use nim::Nim;
 pub use nix::Nix;
 pub use objc::ObjC;
 pub use ocaml::OCaml;
 pub use perl::Perl;
 pub use php::Php;
+pub use powershell::PowerShell;
+pub use proto::Proto;
 pub use python::Python;
 pub use r::R;
 pub use ruby::Ruby;
 pub use rust::Rust;
 pub use scala::Scala;
 pub use scheme::Scheme;
+pub use scss::Scss;
 pub use shell::Shell;
 pub use sql::Sql;
 pub use swift::Swift;
 pub use toml::Toml;
 pub use typst::Typst;
+pub use verilog::Verilog;
+pub use vhdl::Vhdl;
 pub use vim::Vim;
+pub use yaml::Yaml;
 pub use zig::Zig;
@@ -0,0 +1,15 @@
+use crate::parser::*;
+use matcher_macros::define_matcher;
+
+define_matcher!(Nim {
+    delimiters: [
+        "(" => ")",
+        "[" => "]",
+        "{" => "}"
+    ],
+    line_comment: ["#"],
+    block_comment: ["#[" => "]#"],
+    char: ["'"],
+    string: ["\""],
+    block_string: ["\"\"\"" => "\"\"\""]
+});
@@ -0,0 +1,141 @@
+use crate::parser::*;
+
+/// PowerShell, where the backtick is the escape character rather than the backslash, quotes
+/// are escaped inside of strings by doubling them (e.g. `'it''s'`), and here-strings only close
+/// at the start of a line
+#[derive(Default)]
+pub struct PowerShell {
+    /// Text of the current line, for looking at the characters after the tokens
+    line: String,
+    /// Column escaped by the last backtick on the current line
+    escaped_col: Option<usize>,
+}
+
+impl PowerShell {
+    fn byte_after(&self, token: CharPos) -> Option<u8> {
+        self.line.as_bytes().get(token.col + 1).copied()
+    }
+}
+
+impl Matcher for PowerShell {
+    const TOKENS: &[u8] = b"\"#'()<>@[]`{}";
+
+    fn line(&mut self, text: &str, state: State) -> State {
+        self.line.clear();
+        self.line.push_str(text);
+        self.escaped_col = None;
+        state
+    }
+
+    fn call<I>(
+        &mut self,
+        _matches_by_line: &mut Vec<Vec<Match>>,
+        matches: &mut Vec<Match>,
+        tokens: &mut MultiPeek<I>,
+        state: State,
+        token: CharPos,
+        _escaped: bool,
+    ) -> State
+    where
+        I: Iterator<Item = CharPos>,
+    {
+        // Backslashes are literal, e.g. `"C:\Temp\"`
+        let escaped = self.escaped_col == Some(token.col);
+
+        match (state, token.byte) {
+            // Backticks escape in code and double quoted strings, but not in single quoted ones
+            (State::Normal | State::InString("\""), b'`') if !escaped => {
+                self.escaped_col = Some(token.col + 1);
+                state
+            }
+
+            // Doubled quotes are escaped quotes
+            (State::InString(quote), b'"' | b'\'') if quote.as_bytes()[0] == token.byte => {
+                if quote == "\"" && escaped {
+                    return state;
+                }
+                if self.byte_after(token) == Some(token.byte) {
+                    skip_next(tokens, token.col + 1);
+                    return state;
+                }
+                matches.push(Match::new(Kind::Closing, Token::String(quote), token.col));
+                State::Normal
+            }
+            (State::InBlockString(opening), b'"' | b'\'')
+                if token.col == 0
+                    && opening.as_bytes()[1] == token.byte
+                    && self.byte_after(token) == Some(b'@') =>
+            {
+                skip_next(tokens, token.col + 1);
+                let closing = match token.byte {
+                    b'"' => "\"@",
+                    _ => "'@",
+                };
+                let here_string = Token::BlockString(opening, closing);
+                matches.push(Match::new(Kind::Closing, here_string, token.col));
+                State::Normal
+            }
+            (State::InBlockComment(_), b'#') if self.byte_after(token) == Some(b'>') => {
+                skip_next(tokens, token.col + 1);
+                let comment = Token::BlockComment("<#", "#>");
+                matches.push(Match::new(Kind::Closing, comment, token.col));
+                State::Normal
+            }
+            (State::Normal, _) if escaped => state,
+
+            (State::Normal, b'@') => {
+                let (opening, closing) = match self.byte_after(token) {
+                    Some(b'"') => ("@\"", "\"@"),
+                    Some(b'\'') => ("@'", "'@"),
+                    _ => return state,
+                };
+                skip_next(tokens, token.col + 1);
+                let here_string = Token::BlockString(opening, closing);
+                matches.push(Match::new(Kind::Opening, here_string, token.col));
+                State::InBlockString(opening)
+            }
+            (State::Normal, b'<') if self.byte_after(token) == Some(b'#') => {
+                skip_next(tokens, token.col + 1);
+                let comment = Token::BlockComment("<#", "#>");
+                matches.push(Match::new(Kind::Opening, comment, token.col));
+                State::InBlockComment("<#")
+            }
+            (State::Normal, b'#') => {
+                matches.push(Match::line_comment("#", token.col));
+                State::InLineComment
+            }
+            (State::Normal, b'"' | b'\'') => {
+                let quote = match token.byte {
+                    b'"' => "\"",
+                    _ => "'",
+                };
+                matches.push(Match::new(Kind::Opening, Token::String(quote), token.col));
+                State::InString(quote)
+            }
+            (State::Normal, b'(' | b'[' | b'{' | b')' | b']' | b'}') => {
+                let (kind, opening, closing) = match token.byte {
+                    b'(' => (Kind::Opening, "(", ")"),
+                    b'[' => (Kind::Opening, "[", "]"),
+                    b'{' => (Kind::Opening, "{", "}"),
+                    b')' => (Kind::Closing, "(", ")"),
+                    b']' => (Kind::Closing, "[", "]"),
+                    _ => (Kind::Closing, "{", "}"),
+                };
+                matches.push(Match::new(
+                    kind,
+                    Token::Delimiter(opening, closing),
+                    token.col,
+                ));
+                State::Normal
+            }
+            _ => state,
+        }
+    }
+}
+
+fn skip_next(tokens: &mut MultiPeek<impl Iterator<Item = CharPos>>, col: usize) {
+    tokens.reset_peek();
+    if tokens.peek().is_some_and(|next| next.col == col) {
+        tokens.next();
+    }
+}
@@ -9,5 +9,6 @@ define_matcher!(Scheme {
     ],
     line_comment: [";"],
     block_comment: [],
-    string: ["\""]
+    string: ["\""],
+    literal_escapes: true
 });
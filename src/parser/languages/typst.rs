@@ -1,13 +1,417 @@
 use crate::parser::*;
-use matcher_macros::define_matcher;
-
-define_matcher!(Typst {
-    delimiters: [
-        "(" => ")",
-        "[" => "]",
-        "{" => "}"
-    ],
-    line_comment: ["//"],
-    block_comment: ["/*" => "*/"],
-    string: ["\""]
-});
+
+const BACKTICKS: &str = "````````````````";
+const EQUALS: &str = "======";
+/// Every span, as any span may contain the other spans
+const SPANS: &[&str] = &[
+    "code", "content", "math", "strong", "emph", "heading", "raw", "string", "comment",
+];
+/// Spans of markup which don't change the mode, where `strong` and `emph` continue until the
+/// end of the paragraph
+const FORMATTING: &[&str] = &["strong", "emph", "heading"];
+/// Keywords which start a statement in code mode continuing until the end of the line, e.g.
+/// `#let x = 1`
+const KEYWORDS: &[&str] = &[
+    "context", "for", "if", "import", "include", "let", "set", "show", "while",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Markup,
+    Code,
+    Math,
+    Raw,
+    String,
+    Comment,
+}
+
+impl Mode {
+    fn of(state: State) -> Self {
+        match state.spans().top().map(|span| span.name) {
+            Some("code") => Mode::Code,
+            Some("math") => Mode::Math,
+            Some("raw") => Mode::Raw,
+            Some("string") => Mode::String,
+            Some("comment") => Mode::Comment,
+            _ => Mode::Markup,
+        }
+    }
+}
+
+/// Typst, which switches between markup, code (`#expr` and `{}` blocks) and math (`$...$`),
+/// with `[]` content blocks going back to markup from code. The modes are tracked as spans,
+/// so `span_at` reports the mode along with the `*strong*`, `_emph_`, raw and heading spans of
+/// markup. Delimiters are matched in every mode, including the brackets which switch the mode,
+/// which enter the span of the block. Brackets of the same type as the block which are open
+/// inside of it are counted in the span of the block, so that their closing doesn't close it
+#[derive(Default)]
+pub struct Typst {
+    /// Text of the current line, for looking at the characters around the tokens
+    line: String,
+    /// Column after the last closing of a code or content block on the current line, where
+    /// another block continues the call, e.g. `#f(x)[y]`
+    call_end: Option<usize>,
+}
+
+impl Typst {
+    fn byte_after(&self, token: CharPos) -> Option<u8> {
+        self.line.as_bytes().get(token.col + 1).copied()
+    }
+
+    fn is_line_start(&self, col: usize) -> bool {
+        self.line[..col].trim().is_empty()
+    }
+
+    /// Whether the bracket at the column follows `#` or a function called with `#`, e.g.
+    /// `#{`, `#f(` or `#x.at(`
+    fn is_call(&self, col: usize) -> bool {
+        if self.call_end == Some(col) {
+            return true;
+        }
+        let before = &self.line[..col];
+        let hash = before.trim_end_matches(|char: char| {
+            char.is_alphanumeric() || matches!(char, '_' | '-' | '.')
+        });
+        let ident = &before[hash.len()..];
+        hash.ends_with('#')
+            && !hash.ends_with("\\#")
+            && (ident.is_empty()
+                || ident.starts_with(|char: char| char.is_alphabetic() || char == '_'))
+    }
+
+    /// Keyword directly after the `#` at the column
+    fn keyword_after(&self, col: usize) -> Option<&'static str> {
+        let after = &self.line[col + 1..];
+        let ident_len = after
+            .find(|char: char| !char.is_alphanumeric() && char != '_' && char != '-')
+            .unwrap_or(after.len());
+        KEYWORDS
+            .iter()
+            .find(|&&keyword| keyword == &after[..ident_len])
+            .copied()
+    }
+
+    fn comment(
+        &self,
+        matches: &mut Vec<Match>,
+        tokens: &mut MultiPeek<impl Iterator<Item = CharPos>>,
+        state: State,
+        token: CharPos,
+        mode: Mode,
+    ) -> State {
+        match self.byte_after(token) {
+            // `//` after `:` is part of a link in markup, e.g. `https://typst.app`
+            Some(b'/') if mode != Mode::Markup || !self.line[..token.col].ends_with(':') => {
+                // Comments don't change the state, so the mode continues onto the next line
+                matches.push(Match::line_comment("//", token.col));
+                tokens.reset_peek();
+                while tokens.peek().is_some_and(|next| next.byte != b'\n') {
+                    tokens.next();
+                }
+                state
+            }
+            Some(b'*') if state.can_open_span(SPANS) => {
+                skip_next(tokens, token.col + 1);
+                let comment = Token::BlockSpan("comment", "/*", "*/");
+                matches.push(Match::new(Kind::Opening, comment, token.col));
+                state.open_span(OpenSpan::block("comment", "/*"))
+            }
+            _ => state,
+        }
+    }
+
+    /// Gets the code or content block which sets the mode, along with its index in the spans
+    /// counting from the outermost
+    fn enclosing_block(state: State) -> Option<(usize, OpenSpan)> {
+        let spans = state.spans();
+        let (depth, span) = spans
+            .iter()
+            .enumerate()
+            .find(|(_, span)| !FORMATTING.contains(&span.name))
+            .filter(|(_, span)| is_block(span))?;
+        Some((spans.len() - 1 - depth, span))
+    }
+
+    /// Adds to the count of brackets of the same type as the enclosing block open inside of it
+    fn add_nested(state: State, idx: usize, delta: i16) -> State {
+        let mut spans = state.spans();
+        spans.update(idx, |span| {
+            span.nested = span.nested.saturating_add_signed(delta)
+        });
+        State::from_spans(spans)
+    }
+
+    fn opening(
+        &mut self,
+        matches: &mut Vec<Match>,
+        state: State,
+        token: CharPos,
+        mode: Mode,
+    ) -> State {
+        let (opening, closing) = pair(token.byte);
+        let delimiter = Match::new(Kind::Opening, Token::Delimiter(opening, closing), token.col);
+        let span = match (mode, token.byte) {
+            (Mode::Code, b'[') => Some("content"),
+            // Statements such as `#let x = (` continue onto the next lines in the brackets
+            (Mode::Code, _) if state.spans().top().is_some_and(|top| !top.block) => Some("code"),
+            (Mode::Markup, b'[') if self.is_call(token.col) => Some("content"),
+            (Mode::Markup, _) if self.is_call(token.col) => Some("code"),
+            _ => None,
+        }
+        .filter(|_| state.can_open_span(SPANS));
+
+        match span {
+            Some(span) => {
+                matches.push(delimiter.with_span(span));
+                state.open_span(OpenSpan::block(span, opening))
+            }
+            None => {
+                matches.push(delimiter);
+                match Self::enclosing_block(state) {
+                    Some((idx, block)) if block.opening == opening => {
+                        Self::add_nested(state, idx, 1)
+                    }
+                    _ => state,
+                }
+            }
+        }
+    }
+
+    fn closing(
+        &mut self,
+        matches: &mut Vec<Match>,
+        state: State,
+        token: CharPos,
+        mode: Mode,
+    ) -> State {
+        let (opening, closing) = pair(token.byte);
+        let delimiter = Match::new(Kind::Closing, Token::Delimiter(opening, closing), token.col);
+        if let Some((idx, block)) = Self::enclosing_block(state) {
+            if block.opening == opening && block.nested > 0 {
+                matches.push(delimiter);
+                return Self::add_nested(state, idx, -1);
+            }
+        }
+
+        let name = if mode == Mode::Code {
+            "code"
+        } else {
+            "content"
+        };
+        let closes_span = state
+            .spans()
+            .top()
+            .is_some_and(|top| top.block && top.opening == opening && top.name == name);
+        if !closes_span {
+            matches.push(delimiter);
+            return state;
+        }
+
+        matches.push(delimiter.with_span(name));
+        let state = state.close_span();
+        if matches!(Mode::of(state), Mode::Markup | Mode::Math) {
+            self.call_end = Some(token.col + 1);
+        }
+        state
+    }
+
+    /// Opens or closes `*strong*` and `_emph_`, which can't start inside of words
+    fn formatting(&self, matches: &mut Vec<Match>, state: State, token: CharPos) -> State {
+        let (name, opening) = match token.byte {
+            b'*' => ("strong", "*"),
+            _ => ("emph", "_"),
+        };
+        let span = Token::BlockSpan(name, opening, opening);
+
+        if state.spans().top().is_some_and(|top| top.name == name) {
+            matches.push(Match::new(Kind::Closing, span, token.col));
+            return state.close_span();
+        }
+
+        let in_word = self.line[..token.col]
+            .chars()
+            .next_back()
+            .is_some_and(char::is_alphanumeric);
+        if in_word || !state.can_open_span(SPANS) {
+            return state;
+        }
+        matches.push(Match::new(Kind::Opening, span, token.col));
+        state.open_span(OpenSpan::block(name, opening))
+    }
+}
+
+impl Matcher for Typst {
+    const TOKENS: &[u8] = b"\"#$()*/=[]_`{}";
+
+    fn line(&mut self, text: &str, state: State) -> State {
+        self.line.clear();
+        self.line.push_str(text);
+        self.call_end = None;
+
+        // Blank lines end the paragraph, along with its formatting
+        if text.trim().is_empty() {
+            let mut spans = state.spans();
+            spans.retain(|span| !FORMATTING.contains(&span.name));
+            State::from_spans(spans)
+        } else {
+            state
+        }
+    }
+
+    fn call<I>(
+        &mut self,
+        _matches_by_line: &mut Vec<Vec<Match>>,
+        matches: &mut Vec<Match>,
+        tokens: &mut MultiPeek<I>,
+        state: State,
+        token: CharPos,
+        escaped: bool,
+    ) -> State
+    where
+        I: Iterator<Item = CharPos>,
+    {
+        // Length of the run of backticks or `=`, skipping the rest of the run
+        let mut len = 1;
+        if matches!(token.byte, b'`' | b'=') {
+            tokens.reset_peek();
+            while tokens
+                .peek()
+                .is_some_and(|next| next.byte == token.byte && next.col == token.col + len)
+            {
+                len += 1;
+            }
+            for _ in 1..len {
+                tokens.next();
+            }
+        }
+
+        // Raw text, strings and comments only end on their closing
+        let mode = Mode::of(state);
+        match mode {
+            Mode::Raw => {
+                let Some(span) = state.spans().top() else {
+                    return state;
+                };
+                if token.byte != b'`' || len != span.opening.len() {
+                    return state;
+                }
+                let closing = match span.block {
+                    true => Token::BlockSpan("raw", span.opening, span.opening),
+                    false => Token::InlineSpan("raw", span.opening, span.opening),
+                };
+                matches.push(Match::new(Kind::Closing, closing, token.col));
+                return state.close_span();
+            }
+            Mode::String => {
+                if token.byte != b'"' || escaped {
+                    return state;
+                }
+                let closing = Token::InlineSpan("string", "\"", "\"");
+                matches.push(Match::new(Kind::Closing, closing, token.col));
+                return state.close_span();
+            }
+            Mode::Comment => {
+                if token.byte != b'*' || self.byte_after(token) != Some(b'/') {
+                    return state;
+                }
+                skip_next(tokens, token.col + 1);
+                let closing = Token::BlockSpan("comment", "/*", "*/");
+                matches.push(Match::new(Kind::Closing, closing, token.col));
+                return state.close_span();
+            }
+            _ => {}
+        }
+
+        if escaped {
+            return state;
+        }
+
+        match (mode, token.byte) {
+            (_, b'/') => self.comment(matches, tokens, state, token, mode),
+
+            // Math
+            (Mode::Math, b'$') => {
+                let closing = Token::BlockSpan("math", "$", "$");
+                matches.push(Match::new(Kind::Closing, closing, token.col));
+                state.close_span()
+            }
+            (_, b'$') if state.can_open_span(SPANS) => {
+                let opening = Token::BlockSpan("math", "$", "$");
+                matches.push(Match::new(Kind::Opening, opening, token.col));
+                state.open_span(OpenSpan::block("math", "$"))
+            }
+
+            // Raw text, where three or more backticks may span lines and `` is empty
+            (Mode::Markup | Mode::Code, b'`') if len != 2 && state.can_open_span(SPANS) => {
+                let ticks = &BACKTICKS[..len.min(BACKTICKS.len())];
+                let (span, open_span) = match len {
+                    1 => (
+                        Token::InlineSpan("raw", ticks, ticks),
+                        OpenSpan::inline("raw", ticks),
+                    ),
+                    _ => (
+                        Token::BlockSpan("raw", ticks, ticks),
+                        OpenSpan::block("raw", ticks),
+                    ),
+                };
+                matches.push(Match::new(Kind::Opening, span, token.col));
+                state.open_span(open_span)
+            }
+
+            // Strings in code and math
+            (Mode::Code | Mode::Math, b'"') if state.can_open_span(SPANS) => {
+                let opening = Token::InlineSpan("string", "\"", "\"");
+                matches.push(Match::new(Kind::Opening, opening, token.col));
+                state.open_span(OpenSpan::inline("string", "\""))
+            }
+
+            // Statements such as `#let` continue until the end of the line, while expressions
+            // such as `#f(x)` are entered by their brackets
+            (Mode::Markup | Mode::Math, b'#')
+                if self.keyword_after(token.col).is_some() && state.can_open_span(SPANS) =>
+            {
+                let opening = Token::InlineSpan("code", "#", "#");
+                matches.push(Match::new(Kind::Opening, opening, token.col));
+                state.open_span(OpenSpan::inline("code", "#"))
+            }
+
+            // Markup
+            (Mode::Markup, b'*' | b'_') => self.formatting(matches, state, token),
+            (Mode::Markup, b'=')
+                if self.is_line_start(token.col)
+                    && len <= EQUALS.len()
+                    && self.line[token.col + len..].starts_with(' ')
+                    && state.can_open_span(SPANS) =>
+            {
+                let equals = &EQUALS[..len];
+                let heading = Token::InlineSpan("heading", equals, equals);
+                matches.push(Match::new(Kind::Opening, heading, token.col));
+                state.open_span(OpenSpan::inline("heading", equals))
+            }
+
+            (_, b'(' | b'[' | b'{') => self.opening(matches, state, token, mode),
+            (_, b')' | b']' | b'}') => self.closing(matches, state, token, mode),
+            _ => state,
+        }
+    }
+}
+
+fn pair(byte: u8) -> (&'static str, &'static str) {
+    match byte {
+        b'(' | b')' => ("(", ")"),
+        b'[' | b']' => ("[", "]"),
+        _ => ("{", "}"),
+    }
+}
+
+/// Whether the span is a code or content block entered by a bracket
+fn is_block(span: &OpenSpan) -> bool {
+    span.block && matches!(span.name, "code" | "content")
+}
+
+fn skip_next(tokens: &mut MultiPeek<impl Iterator<Item = CharPos>>, col: usize) {
+    tokens.reset_peek();
+    if tokens.peek().is_some_and(|next| next.col == col) {
+        tokens.next();
+    }
+}
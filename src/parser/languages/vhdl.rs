@@ -0,0 +1,13 @@
+use crate::parser::*;
+use matcher_macros::define_matcher;
+
+define_matcher!(Vhdl {
+    delimiters: [
+        "(" => ")",
+        "[" => "]"
+    ],
+    line_comment: ["--"],
+    block_comment: ["/*" => "*/"],
+    char: ["'"],
+    string: ["\""]
+});
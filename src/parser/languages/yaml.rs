@@ -0,0 +1,98 @@
+use crate::parser::*;
+
+/// YAML, where quotes only open a string at the start of a scalar, so that apostrophes in plain
+/// scalars such as `msg: don't` aren't strings
+#[derive(Default)]
+pub struct Yaml {
+    /// Text of the current line, for looking at the characters before the quotes
+    line: String,
+}
+
+impl Yaml {
+    /// Whether the column starts a scalar, at the start of the line, after a flow indicator or
+    /// after a block indicator followed by whitespace. Tags and anchors such as `!!str` and `&a`
+    /// are skipped
+    fn is_scalar_start(&self, col: usize) -> bool {
+        let mut before = &self.line[..col];
+        loop {
+            let trimmed = before.trim_end();
+            let spaced = trimmed.len() < before.len();
+            let word_start = trimmed
+                .rfind(|char: char| char.is_whitespace() || matches!(char, '[' | '{' | ','))
+                .map_or(0, |idx| idx + 1);
+            let word = &trimmed[word_start..];
+            if spaced && word.starts_with(['!', '&']) {
+                before = &trimmed[..word_start];
+                continue;
+            }
+
+            return match trimmed.chars().next_back() {
+                None | Some('[' | '{' | ',') => true,
+                // Values may follow quoted keys directly, e.g. `{"a":"b"}`
+                Some(':') => spaced || trimmed[..trimmed.len() - 1].ends_with(['"', '\'']),
+                Some('-' | '?') => spaced,
+                _ => false,
+            };
+        }
+    }
+}
+
+impl Matcher for Yaml {
+    const TOKENS: &[u8] = b"\"#'[]{}";
+
+    fn line(&mut self, text: &str, state: State) -> State {
+        self.line.clear();
+        self.line.push_str(text);
+        state
+    }
+
+    fn call<I>(
+        &mut self,
+        _matches_by_line: &mut Vec<Vec<Match>>,
+        matches: &mut Vec<Match>,
+        _tokens: &mut MultiPeek<I>,
+        state: State,
+        token: CharPos,
+        escaped: bool,
+    ) -> State
+    where
+        I: Iterator<Item = CharPos>,
+    {
+        match (state, token.byte) {
+            // Backslashes only escape in double quoted strings
+            (State::InString(quote), b'"' | b'\'')
+                if quote.as_bytes()[0] == token.byte && (quote == "'" || !escaped) =>
+            {
+                matches.push(Match::new(Kind::Closing, Token::String(quote), token.col));
+                State::Normal
+            }
+            (State::Normal, b'"' | b'\'') if self.is_scalar_start(token.col) => {
+                let quote = match token.byte {
+                    b'"' => "\"",
+                    _ => "'",
+                };
+                matches.push(Match::new(Kind::Opening, Token::String(quote), token.col));
+                State::InString(quote)
+            }
+            (State::Normal, b'#') if !escaped => {
+                matches.push(Match::line_comment("#", token.col));
+                State::InLineComment
+            }
+            (State::Normal, b'[' | b'{' | b']' | b'}') => {
+                let (kind, opening, closing) = match token.byte {
+                    b'[' => (Kind::Opening, "[", "]"),
+                    b'{' => (Kind::Opening, "{", "}"),
+                    b']' => (Kind::Closing, "[", "]"),
+                    _ => (Kind::Closing, "{", "}"),
+                };
+                matches.push(Match::new(
+                    kind,
+                    Token::Delimiter(opening, closing),
+                    token.col,
+                ));
+                State::Normal
+            }
+            _ => state,
+        }
+    }
+}
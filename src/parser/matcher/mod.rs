@@ -1,3 +1,5 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
 use itertools::MultiPeek;
 use mlua::IntoLua;
 
@@ -16,6 +18,13 @@ pub trait Matcher {
         Self::TOKENS
     }
 
+    /// Called at the start of each line with its text, for matchers which need to look at the
+    /// characters around the tokens. Returns the state to start the line with
+    #[inline(always)]
+    fn line(&mut self, _text: &str, state: State) -> State {
+        state
+    }
+
     fn call<I>(
         &mut self,
         matches_by_line: &mut Vec<Vec<Match>>,
@@ -35,6 +44,14 @@ pub struct Match {
     pub token: Token,
     pub col: usize,
     pub stack_height: Option<usize>,
+    /// Nesting depth among the pairs with the same token, set when using `DepthMode::PerType`
+    pub depth: Option<usize>,
+    /// Hash of the name which the opening and closing must share, for pairs matched by name
+    /// such as LaTeX environments
+    pub name: Option<u64>,
+    /// Block span entered by the delimiter, for delimiters which switch the mode of the text
+    /// between them such as Typst's code and content blocks
+    pub span: Option<&'static str>,
 }
 
 impl Match {
@@ -44,15 +61,40 @@ impl Match {
             token,
             col,
             stack_height: None,
+            depth: None,
+            name: None,
+            span: None,
         }
     }
 
+    /// Sets the name which the pair must share, in addition to the token
+    pub fn with_name(mut self, name: &str) -> Self {
+        let mut hasher = DefaultHasher::new();
+        name.hash(&mut hasher);
+        self.name = Some(hasher.finish());
+        self
+    }
+
+    /// Sets the block span entered by the delimiter
+    pub fn with_span(mut self, span: &'static str) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    /// Whether the closing may close the opening
+    pub fn pairs_with(&self, other: &Match) -> bool {
+        self.token == other.token && self.name == other.name
+    }
+
     pub fn new_with_stack(kind: Kind, token: Token, col: usize, stack_height: usize) -> Self {
         Self {
             kind,
             token,
             col,
             stack_height: Some(stack_height),
+            depth: None,
+            name: None,
+            span: None,
         }
     }
 
@@ -63,6 +105,9 @@ impl Match {
             line,
             col: self.col,
             stack_height: self.stack_height,
+            depth: self.depth,
+            name: self.name,
+            span: self.span,
         }
     }
 
@@ -72,9 +117,13 @@ impl Match {
             token: Token::LineComment(text),
             col,
             stack_height: None,
+            depth: None,
+            name: None,
+            span: None,
         }
     }
 
+    #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
         match self.kind {
             Kind::Opening | Kind::NonPair => self.token.opening().len(),
@@ -105,6 +154,9 @@ impl Match {
             token,
             col,
             stack_height,
+            depth: None,
+            name: None,
+            span: None,
         }
     }
 
@@ -119,6 +171,9 @@ impl Match {
             token,
             col,
             stack_height: None,
+            depth: None,
+            name: None,
+            span: None,
         }
     }
 }
@@ -135,11 +190,12 @@ impl IntoLua for Match {
             Token::InlineSpan(span, _, _) | Token::BlockSpan(span, _, _) => {
                 table.set("span", span)?;
             }
-            _ => {}
+            _ => table.set("span", self.span)?,
         }
 
         table.set("col", self.col)?;
         table.set("stack_height", self.stack_height)?;
+        table.set("depth", self.depth)?;
 
         (&table).into_lua(lua)
     }
@@ -152,6 +208,28 @@ pub struct MatchWithLine {
     pub line: usize,
     pub col: usize,
     pub stack_height: Option<usize>,
+    pub depth: Option<usize>,
+    pub name: Option<u64>,
+    pub span: Option<&'static str>,
+}
+
+impl MatchWithLine {
+    /// Whether the closing may close the opening
+    pub fn pairs_with(&self, other: &MatchWithLine) -> bool {
+        self.token == other.token && self.name == other.name
+    }
+
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        match self.kind {
+            Kind::Opening | Kind::NonPair => self.token.opening().len(),
+            Kind::Closing => self
+                .token
+                .closing()
+                .unwrap_or_else(|| self.token.opening())
+                .len(),
+        }
+    }
 }
 
 impl IntoLua for MatchWithLine {
@@ -166,12 +244,13 @@ impl IntoLua for MatchWithLine {
             Token::InlineSpan(span, _, _) | Token::BlockSpan(span, _, _) => {
                 table.set("span", span)?;
             }
-            _ => {}
+            _ => table.set("span", self.span)?,
         }
 
         table.set("line", self.line)?;
         table.set("col", self.col)?;
         table.set("stack_height", self.stack_height)?;
+        table.set("depth", self.depth)?;
 
         (&table).into_lua(lua)
     }
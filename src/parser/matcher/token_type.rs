@@ -1,4 +1,5 @@
-use super::Token;
+use super::{Match, Token};
+use crate::error::Error;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 #[repr(u8)]
@@ -8,25 +9,40 @@ pub enum TokenType {
     BlockString = 2,
     LineComment = 3,
     BlockComment = 4,
+    InlineSpan = 5,
+    BlockSpan = 6,
 }
 
 impl TokenType {
     pub fn matches(&self, token: &Token) -> bool {
         use TokenType::*;
-        match (self, token) {
+        matches!(
+            (self, token),
             (Delimiter, Token::Delimiter(_, _))
-            | (String, Token::String(_))
-            | (BlockString, Token::BlockString(_, _))
-            | (LineComment, Token::LineComment(_))
-            | (BlockComment, Token::BlockComment(_, _)) => true,
+                | (String, Token::String(_))
+                | (BlockString, Token::BlockString(_, _))
+                | (LineComment, Token::LineComment(_))
+                | (BlockComment, Token::BlockComment(_, _))
+                | (InlineSpan, Token::InlineSpan(_, _, _))
+                | (BlockSpan, Token::BlockSpan(_, _, _))
+        )
+    }
 
-            _ => false,
+    pub fn of(token: &Token) -> Self {
+        match token {
+            Token::Delimiter(_, _) => TokenType::Delimiter,
+            Token::String(_) => TokenType::String,
+            Token::BlockString(_, _) => TokenType::BlockString,
+            Token::LineComment(_) => TokenType::LineComment,
+            Token::BlockComment(_, _) => TokenType::BlockComment,
+            Token::InlineSpan(_, _, _) => TokenType::InlineSpan,
+            Token::BlockSpan(_, _, _) => TokenType::BlockSpan,
         }
     }
 }
 
 impl TryFrom<u8> for TokenType {
-    type Error = ();
+    type Error = Error;
 
     fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
@@ -35,7 +51,77 @@ impl TryFrom<u8> for TokenType {
             2 => Ok(TokenType::BlockString),
             3 => Ok(TokenType::LineComment),
             4 => Ok(TokenType::BlockComment),
-            _ => Err(()),
+            5 => Ok(TokenType::InlineSpan),
+            6 => Ok(TokenType::BlockSpan),
+            _ => Err(Error::InvalidTokenType(value)),
         }
     }
 }
+
+/// Bitmask selecting matches by token type, with bit `n` for the `TokenType` with value `n`,
+/// along with whether they have a pair. When neither `MATCHED` nor `UNMATCHED` is set, both
+/// are included
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MatchFilter(u16);
+
+impl MatchFilter {
+    pub const MATCHED: u16 = 1 << 7;
+    pub const UNMATCHED: u16 = 1 << 8;
+    const ALL: u16 = (1 << 9) - 1;
+
+    pub fn matches(&self, match_: &Match) -> bool {
+        let type_bit = 1 << TokenType::of(&match_.token) as u16;
+        let matched_bit = match match_.stack_height {
+            Some(_) => Self::MATCHED,
+            None => Self::UNMATCHED,
+        };
+        self.0 & type_bit != 0
+            && (self.0 & (Self::MATCHED | Self::UNMATCHED) == 0 || self.0 & matched_bit != 0)
+    }
+}
+
+impl Default for MatchFilter {
+    fn default() -> Self {
+        Self(1 << TokenType::Delimiter as u16)
+    }
+}
+
+impl TryFrom<u16> for MatchFilter {
+    type Error = Error;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        if value & !Self::ALL != 0 {
+            return Err(Error::InvalidArgument {
+                name: "filter",
+                value: value.to_string(),
+            });
+        }
+        Ok(Self(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Kind;
+
+    #[test]
+    fn test_match_filter() {
+        let matched = Match::delimiter('(', 0, Some(0));
+        let unmatched = Match::delimiter('(', 0, None);
+        let span = Match::new(Kind::Opening, Token::InlineSpan("code", "`", "`"), 0);
+
+        let delimiters = MatchFilter::default();
+        assert!(delimiters.matches(&matched) && delimiters.matches(&unmatched));
+        assert!(!delimiters.matches(&span));
+
+        let unmatched_delimiters = MatchFilter::try_from(1 | MatchFilter::UNMATCHED).unwrap();
+        assert!(!unmatched_delimiters.matches(&matched));
+        assert!(unmatched_delimiters.matches(&unmatched));
+
+        let spans = MatchFilter::try_from(1 << TokenType::InlineSpan as u16).unwrap();
+        assert!(spans.matches(&span));
+
+        assert!(MatchFilter::try_from(1 << 9).is_err());
+    }
+}
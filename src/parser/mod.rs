@@ -8,22 +8,40 @@ pub mod tokenize;
 
 pub use itertools::MultiPeek;
 pub use matcher::{Kind, Match, MatchWithLine, Matcher, Token};
-pub use parse::{parse, State};
+pub use parse::{parse, OpenSpan, Spans, State};
 pub use tokenize::{tokenize, CharPos};
 
 use crate::buffer::ParsedBuffer;
 
 #[rustfmt::skip]
 const FILETYPES: &[&str] = &[
-    "c", "clojure", "cpp", "csharp", "dart", "elixir", "erlang", "fennel", "fsharp", "go", "haskell",
-    "haxe", "java", "javascript", "typescript", "typescriptreact", "javascriptreact", "json",
-    "kotlin", "latex", "tex", "bib", "lean", "lua", "markdown", "nix", "objc", "ocaml", "perl",
-    "php", "python", "r", "ruby", "rust", "scala", "scheme", "shell", "sql", "swift", "toml", "typst", "vim",
-    "zig"
+    "ada", "asm", "c", "clojure", "cmake", "cpp", "csharp", "css", "d", "dart", "dockerfile",
+    "elixir", "erlang", "fennel", "fortran", "fsharp", "go", "graphql", "haskell", "haxe", "hcl",
+    "java", "javascript", "json", "json5", "jsonc", "julia", "kotlin", "latex", "lean", "lua",
+    "make", "markdown", "nim", "nix", "objc", "ocaml", "perl", "php", "powershell", "proto",
+    "python", "r", "ruby", "rust", "scala", "scheme", "scss", "shell", "sql", "swift", "toml",
+    "typst", "verilog", "vhdl", "vim", "yaml", "zig"
 ];
 
+/// Filetypes which share the matcher of another filetype, e.g. Neovim's `sh` for shell scripts
+#[rustfmt::skip]
+const ALIASES: &[(&str, &str)] = &[
+    ("bash", "shell"), ("bib", "latex"), ("cs", "csharp"), ("javascriptreact", "javascript"),
+    ("nasm", "asm"), ("ps1", "powershell"), ("sh", "shell"), ("systemverilog", "verilog"),
+    ("terraform", "hcl"), ("terraform-vars", "hcl"), ("tex", "latex"),
+    ("typescript", "javascript"), ("typescriptreact", "javascript"), ("zsh", "shell")
+];
+
+/// Gets the filetype of the matcher used for the filetype, resolving aliases
+pub fn resolve_filetype(filetype: &str) -> &str {
+    ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == filetype)
+        .map_or(filetype, |(_, target)| target)
+}
+
 pub fn supports_filetype(filetype: &str) -> bool {
-    FILETYPES.contains(&filetype)
+    FILETYPES.contains(&resolve_filetype(filetype))
 }
 
 #[rustfmt::skip]
@@ -33,45 +51,64 @@ pub fn parse_filetype(
     lines: &[&str],
     initial_state: State,
 ) -> Option<ParsedBuffer> {
-    match filetype {
+    match resolve_filetype(filetype) {
+        "ada" => Some(parse(tab_width, lines, initial_state, languages::Ada {})),
+        "asm" => Some(parse(tab_width, lines, initial_state, languages::Asm {})),
         "c" => Some(parse(tab_width, lines, initial_state, languages::C {})),
         "clojure" => Some(parse(tab_width, lines, initial_state, languages::Clojure {})),
+        "cmake" => Some(parse(tab_width, lines, initial_state, languages::CMake {})),
         "cpp" => Some(parse(tab_width, lines, initial_state, languages::Cpp {})),
         "csharp" => Some(parse(tab_width, lines, initial_state, languages::CSharp {})),
+        "css" => Some(parse(tab_width, lines, initial_state, languages::Css {})),
+        "d" => Some(parse(tab_width, lines, initial_state, languages::D {})),
         "dart" => Some(parse(tab_width, lines, initial_state, languages::Dart {})),
+        "dockerfile" => Some(parse(tab_width, lines, initial_state, languages::Dockerfile::default())),
         "elixir" => Some(parse(tab_width, lines, initial_state, languages::Elixir {})),
         "erlang" => Some(parse(tab_width, lines, initial_state, languages::Erlang {})),
         "fennel" => Some(parse(tab_width, lines, initial_state, languages::Fennel {})),
+        "fortran" => Some(parse(tab_width, lines, initial_state, languages::Fortran {})),
         "fsharp" => Some(parse(tab_width, lines, initial_state, languages::FSharp {})),
         "go" => Some(parse(tab_width, lines, initial_state, languages::Go {})),
+        "graphql" => Some(parse(tab_width, lines, initial_state, languages::GraphQl {})),
         "haskell" => Some(parse(tab_width, lines, initial_state, languages::Haskell {})),
         "haxe" => Some(parse(tab_width, lines, initial_state, languages::Haxe {})),
+        "hcl" => Some(parse(tab_width, lines, initial_state, languages::Hcl {})),
         "java" => Some(parse(tab_width, lines, initial_state, languages::Java {})),
-        "typescript" | "javascript" | "typescriptreact" | "javascriptreact" =>
-            Some(parse(tab_width, lines, initial_state, languages::JavaScript {})),
+        "javascript" => Some(parse(tab_width, lines, initial_state, languages::JavaScript {})),
         "json" => Some(parse(tab_width, lines, initial_state, languages::Json {})),
+        "json5" => Some(parse(tab_width, lines, initial_state, languages::Json5 {})),
+        "jsonc" => Some(parse(tab_width, lines, initial_state, languages::Jsonc {})),
+        "julia" => Some(parse(tab_width, lines, initial_state, languages::Julia {})),
         "kotlin" => Some(parse(tab_width, lines, initial_state, languages::Kotlin {})),
-        "latex" | "tex" | "bib" => Some(parse(tab_width, lines, initial_state, languages::Latex {})),
+        "latex" => Some(parse(tab_width, lines, initial_state, languages::Latex::default())),
         "lean" => Some(parse(tab_width, lines, initial_state, languages::Lean {})),
         "lua" => Some(parse(tab_width, lines, initial_state, languages::Lua {})),
-        "markdown" => Some(parse(tab_width, lines, initial_state, languages::Markdown {})),
+        "make" => Some(parse(tab_width, lines, initial_state, languages::Make {})),
+        "markdown" => Some(parse(tab_width, lines, initial_state, languages::Markdown::default())),
+        "nim" => Some(parse(tab_width, lines, initial_state, languages::Nim {})),
         "nix" => Some(parse(tab_width, lines, initial_state, languages::Nix {})),
         "objc" => Some(parse(tab_width, lines, initial_state, languages::ObjC {})),
         "ocaml" => Some(parse(tab_width, lines, initial_state, languages::OCaml {})),
         "perl" => Some(parse(tab_width, lines, initial_state, languages::Perl {})),
         "php" => Some(parse(tab_width, lines, initial_state, languages::Php {})),
+        "powershell" => Some(parse(tab_width, lines, initial_state, languages::PowerShell::default())),
+        "proto" => Some(parse(tab_width, lines, initial_state, languages::Proto {})),
         "python" => Some(parse(tab_width, lines, initial_state, languages::Python {})),
         "r" => Some(parse(tab_width, lines, initial_state, languages::R {})),
         "ruby" => Some(parse(tab_width, lines, initial_state, languages::Ruby {})),
         "rust" => Some(parse(tab_width, lines, initial_state, languages::Rust {})),
         "scala" => Some(parse(tab_width, lines, initial_state, languages::Scala {})),
         "scheme" => Some(parse(tab_width, lines, initial_state, languages::Scheme {})),
+        "scss" => Some(parse(tab_width, lines, initial_state, languages::Scss {})),
         "shell" => Some(parse(tab_width, lines, initial_state, languages::Shell {})),
         "sql" => Some(parse(tab_width, lines, initial_state, languages::Sql {})),
         "swift" => Some(parse(tab_width, lines, initial_state, languages::Swift {})),
         "toml" => Some(parse(tab_width, lines, initial_state, languages::Toml {})),
-        "typst" => Some(parse(tab_width, lines, initial_state, languages::Typst {})),
+        "typst" => Some(parse(tab_width, lines, initial_state, languages::Typst::default())),
+        "verilog" => Some(parse(tab_width, lines, initial_state, languages::Verilog {})),
+        "vhdl" => Some(parse(tab_width, lines, initial_state, languages::Vhdl {})),
         "vim" => Some(parse(tab_width, lines, initial_state, languages::Vim {})),
+        "yaml" => Some(parse(tab_width, lines, initial_state, languages::Yaml::default())),
         "zig" => Some(parse(tab_width, lines, initial_state, languages::Zig {})),
 
         _ => None,
@@ -1,6 +1,15 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{LazyLock, Mutex, PoisonError};
+
 use itertools::Itertools;
+use mlua::IntoLua;
 
-use crate::{buffer::ParsedBuffer, parser::indent::indent_levels};
+use crate::{
+    buffer::{LineLayout, ParsedBuffer},
+    parser::indent::indent_levels,
+};
 
 use super::{matcher::Matcher, tokenize::tokenize};
 
@@ -11,8 +20,243 @@ pub enum State {
     InBlockString(&'static str),
     InLineComment,
     InBlockComment(&'static str),
-    InInlineSpan(&'static str),
-    InBlockSpan(&'static str),
+    /// In spans, where the innermost span is an inline span
+    InInlineSpan(Spans),
+    /// In spans, where the innermost span is a block span
+    InBlockSpan(Spans),
+}
+
+impl State {
+    /// Gets the spans enclosing the state, which are empty outside of spans
+    pub fn spans(&self) -> Spans {
+        match self {
+            State::InInlineSpan(spans) | State::InBlockSpan(spans) => *spans,
+            _ => Spans::default(),
+        }
+    }
+
+    /// Gets the state for being in the spans
+    pub fn from_spans(spans: Spans) -> Self {
+        match spans.top() {
+            None => State::Normal,
+            Some(span) if span.block => State::InBlockSpan(spans),
+            Some(_) => State::InInlineSpan(spans),
+        }
+    }
+
+    /// Whether a span may be opened in the state, which is the case outside of spans, or when
+    /// the innermost span is one of the given spans which may contain other spans
+    pub fn can_open_span(&self, nesting_spans: &[&str]) -> bool {
+        match self {
+            State::Normal => true,
+            State::InInlineSpan(spans) | State::InBlockSpan(spans) => {
+                !spans.is_full()
+                    && spans
+                        .top()
+                        .is_some_and(|span| nesting_spans.contains(&span.name))
+            }
+            _ => false,
+        }
+    }
+
+    pub fn open_span(self, span: OpenSpan) -> Self {
+        let mut spans = self.spans();
+        spans.push(span);
+        State::from_spans(spans)
+    }
+
+    pub fn close_span(self) -> Self {
+        let mut spans = self.spans();
+        spans.pop();
+        State::from_spans(spans)
+    }
+}
+
+const MAX_SPANS: usize = 8;
+
+/// Span which has been opened but not closed yet
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct OpenSpan {
+    pub name: &'static str,
+    /// Text which opened the span, for spans with several openings
+    pub opening: &'static str,
+    /// Block spans continue onto the next lines, while inline spans end with the line
+    pub block: bool,
+    /// Brackets of the same type as the opening which are open inside of the span, for spans
+    /// closed by a bracket, i.e. the code and content blocks of Typst
+    pub nested: u16,
+}
+
+impl OpenSpan {
+    pub fn inline(name: &'static str, opening: &'static str) -> Self {
+        Self {
+            name,
+            opening,
+            block: false,
+            nested: 0,
+        }
+    }
+
+    pub fn block(name: &'static str, opening: &'static str) -> Self {
+        Self {
+            name,
+            opening,
+            block: true,
+            nested: 0,
+        }
+    }
+}
+
+/// Stack of nested spans, from the outermost to the innermost. The stacks are interned so that
+/// the state stays small and `Copy`, spans nested deeper than `MAX_SPANS` aren't opened
+#[derive(Clone, Copy, Default)]
+pub struct Spans(Option<&'static SpanNode>);
+
+/// Innermost span of an interned stack, pointing to the rest of the stack
+struct SpanNode {
+    span: OpenSpan,
+    parent: Spans,
+    len: usize,
+}
+
+/// Interned stacks keyed by the address of the parent node and the span pushed onto it. Every
+/// span name and opening is static, so the number of stacks is bounded by the languages and
+/// the brackets nested in blocks
+type SpanNodes = HashMap<(usize, OpenSpan), &'static SpanNode>;
+
+static SPAN_NODES: LazyLock<Mutex<SpanNodes>> = LazyLock::new(Default::default);
+
+thread_local! {
+    /// Stacks already interned by this thread, to avoid taking the lock while parsing
+    static LOCAL_SPAN_NODES: RefCell<SpanNodes> = RefCell::default();
+}
+
+impl Spans {
+    fn address(&self) -> usize {
+        self.0.map_or(0, |node| node as *const SpanNode as usize)
+    }
+
+    /// Gets the spans from the innermost to the outermost
+    pub fn iter(&self) -> impl Iterator<Item = OpenSpan> {
+        std::iter::successors(self.0, |node| node.parent.0).map(|node| node.span)
+    }
+
+    /// Gets the spans from the outermost to the innermost
+    pub fn to_vec(&self) -> Vec<OpenSpan> {
+        let mut spans = self.iter().collect::<Vec<_>>();
+        spans.reverse();
+        spans
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.map_or(0, |node| node.len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_none()
+    }
+
+    pub fn top(&self) -> Option<OpenSpan> {
+        self.0.map(|node| node.span)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == MAX_SPANS
+    }
+
+    pub fn push(&mut self, span: OpenSpan) {
+        if self.is_full() {
+            return;
+        }
+        let key = (self.address(), span);
+        let node = LOCAL_SPAN_NODES.with_borrow_mut(|local| {
+            *local.entry(key).or_insert_with(|| {
+                let mut nodes = SPAN_NODES.lock().unwrap_or_else(PoisonError::into_inner);
+                *nodes.entry(key).or_insert_with(|| {
+                    Box::leak(Box::new(SpanNode {
+                        span,
+                        parent: *self,
+                        len: self.len() + 1,
+                    }))
+                })
+            })
+        });
+        *self = Spans(Some(node));
+    }
+
+    pub fn pop(&mut self) -> Option<OpenSpan> {
+        let node = self.0?;
+        *self = node.parent;
+        Some(node.span)
+    }
+
+    /// Keeps only the spans accepted by the predicate
+    pub fn retain(&mut self, predicate: impl Fn(&OpenSpan) -> bool) {
+        let mut spans = Spans::default();
+        for span in self.to_vec().into_iter().filter(|span| predicate(span)) {
+            spans.push(span);
+        }
+        *self = spans;
+    }
+
+    /// Changes the span at the index, counting from the outermost span
+    pub fn update(&mut self, idx: usize, f: impl FnOnce(&mut OpenSpan)) {
+        let mut spans = self.to_vec();
+        if let Some(span) = spans.get_mut(idx) {
+            f(span);
+        }
+        *self = Spans::default();
+        for span in spans {
+            self.push(span);
+        }
+    }
+}
+
+/// Interned stacks are equal when they're the same node
+impl PartialEq for Spans {
+    fn eq(&self, other: &Self) -> bool {
+        self.address() == other.address()
+    }
+}
+
+impl fmt::Debug for Spans {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.to_vec()).finish()
+    }
+}
+
+impl IntoLua for State {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+
+        let kind = match self {
+            State::Normal => "normal",
+            State::InString(open) => {
+                table.set("delimiter", open)?;
+                "string"
+            }
+            State::InBlockString(open) => {
+                table.set("delimiter", open)?;
+                "block_string"
+            }
+            State::InLineComment => "line_comment",
+            State::InBlockComment(open) => {
+                table.set("delimiter", open)?;
+                "block_comment"
+            }
+            State::InInlineSpan(_) => "inline_span",
+            State::InBlockSpan(_) => "block_span",
+        };
+        let spans = self.spans();
+        if let Some(span) = spans.top() {
+            table.set("span", span.name)?;
+            let names = spans.to_vec().into_iter().map(|span| span.name);
+            table.set("spans", lua.create_sequence_from(names)?)?;
+        }
+        table.set("kind", kind)?;
+
+        (&table).into_lua(lua)
+    }
 }
 
 /// Given a matcher, runs the tokenizer on the lines and keeps track
@@ -46,6 +290,11 @@ pub fn parse<M: Matcher>(
 
     let mut tokens = tokens.multipeek();
 
+    let mut lines_iter = lines.iter();
+    if let Some(line) = lines_iter.next() {
+        state = matcher.line(line, state);
+    }
+
     while let Some(token) = tokens.next() {
         // New line
         if matches!(token.byte, b'\n') {
@@ -53,13 +302,19 @@ pub fn parse<M: Matcher>(
             line_matches = vec![];
             escaped_col = None;
 
-            if matches!(
-                state,
-                State::InString(_) | State::InLineComment | State::InInlineSpan(_)
-            ) {
-                state = State::Normal;
-            }
+            state = match state {
+                State::InString(_) | State::InLineComment => State::Normal,
+                // Inline spans end with the line, unlike the block spans they may be nested in
+                State::InInlineSpan(mut spans) | State::InBlockSpan(mut spans) => {
+                    spans.retain(|span| span.block);
+                    State::from_spans(spans)
+                }
+                _ => state,
+            };
             state_by_line.push(state);
+            if let Some(line) = lines_iter.next() {
+                state = matcher.line(line, state);
+            }
             continue;
         }
 
@@ -90,23 +345,25 @@ pub fn parse<M: Matcher>(
         matches_by_line,
         state_by_line,
         indent_levels,
+        line_layouts: lines.iter().map(|line| LineLayout::new(line)).collect(),
+        filetype: String::new(),
+        tab_width,
+        version: None,
+        depth_mode: Default::default(),
+        diagnostics: Default::default(),
     }
 }
 
 // TODO: come up with a better way to do testing
 #[cfg(test)]
 mod tests {
-    use crate::parser::{parse_filetype, Match, State};
+    use crate::buffer::ParsedBuffer;
+    use crate::parser::{Kind, Match, OpenSpan, State, Token};
 
     fn parse(filetype: &str, lines: &str) -> Vec<Vec<Match>> {
-        parse_filetype(
-            filetype,
-            4,
-            &lines.split('\n').collect::<Vec<_>>(),
-            State::Normal,
-        )
-        .unwrap()
-        .matches_by_line
+        ParsedBuffer::parse(filetype, 4, &lines.split('\n').collect::<Vec<_>>())
+            .unwrap()
+            .matches_by_line
     }
 
     #[test]
@@ -123,7 +380,7 @@ mod tests {
             parse("c", "// comment {}\n}"),
             vec![
                 vec![Match::line_comment("//", 0)],
-                vec![Match::delimiter('}', 0, Some(0))],
+                vec![Match::delimiter('}', 0, None)],
             ]
         );
 
@@ -131,14 +388,38 @@ mod tests {
             parse("c", "/* comment {} */\n}"),
             vec![
                 vec![
-                    Match::block_comment("/*", 0),
-                    Match::block_comment("*/", 14)
+                    Match {
+                        stack_height: Some(0),
+                        ..Match::block_comment("/*", 0)
+                    },
+                    Match {
+                        stack_height: Some(0),
+                        ..Match::block_comment("*/", 14)
+                    }
                 ],
-                vec![Match::delimiter('}', 0, Some(0))]
+                vec![Match::delimiter('}', 0, None)]
             ]
         );
     }
 
+    #[test]
+    fn test_state() {
+        // The span stack is kept out of line, so that every line's state stays small
+        assert_eq!(std::mem::size_of::<State>(), 24);
+
+        let state = State::Normal
+            .open_span(OpenSpan::block("code", "```"))
+            .open_span(OpenSpan::inline("math", "$"));
+        let spans = state.spans();
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans.top(), Some(OpenSpan::inline("math", "$")));
+        assert_eq!(
+            state.close_span(),
+            State::Normal.open_span(OpenSpan::block("code", "```"))
+        );
+        assert_eq!(state.close_span().close_span(), State::Normal);
+    }
+
     #[test]
     fn test_tex() {
         assert_eq!(
@@ -152,4 +433,743 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_latex() {
+        assert_eq!(
+            texts("tex", "\\begin{align*} \\{ [ \\end{align*}"),
+            vec![vec![("\\begin", 0), ("[", 18), ("\\end", 20)]]
+        );
+        // Environments are paired by name
+        let stack_heights = |lines: &str| {
+            parse("tex", lines)
+                .into_iter()
+                .flatten()
+                .map(|match_| match_.stack_height)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            stack_heights("\\begin{a} \\begin{b} \\end{b} \\end{a}"),
+            vec![Some(0), Some(1), Some(1), Some(0)]
+        );
+        assert_eq!(stack_heights("\\begin{a} \\end{b}"), vec![None, None]);
+        assert_eq!(
+            texts("tex", "\\left[ 0, 1 \\right) \\bigl\\{ \\bigr."),
+            vec![vec![
+                ("\\left", 0),
+                ("\\right", 12),
+                ("\\bigl", 20),
+                ("\\bigr", 28)
+            ]]
+        );
+        // Delimiters are matched in math, which continues after comments
+        assert_eq!(
+            texts("tex", "\\[ f(x) % $\n\\] $a$"),
+            vec![
+                vec![("\\[", 0), ("(", 4), (")", 6), ("%", 8)],
+                vec![("\\]", 0), ("$", 3), ("$", 5)]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_latex_mismatched_environments() {
+        // Mismatched names stay unmatched, without breaking the pairs inside of them
+        let matches = parse("tex", "\\begin{a} ( \\end{b} )")
+            .into_iter()
+            .flatten()
+            .map(|match_| (match_.token.opening(), match_.stack_height))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            matches,
+            vec![
+                ("\\begin", None),
+                ("(", Some(1)),
+                ("\\begin", None),
+                ("(", Some(1))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_latex_sized_delimiters() {
+        // `.` is an invisible delimiter, which still pairs with the other side
+        let pairs = parse("tex", "\\left( x \\right. \\left. y \\right]")
+            .into_iter()
+            .flatten()
+            .map(|match_| (match_.kind, match_.col, match_.stack_height))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pairs,
+            vec![
+                (Kind::Opening, 0, Some(0)),
+                (Kind::Closing, 9, Some(0)),
+                (Kind::Opening, 17, Some(0)),
+                (Kind::Closing, 26, Some(0))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_latex_math() {
+        assert_eq!(
+            texts("tex", "\\( a \\) \\[ b \\]"),
+            vec![vec![("\\(", 0), ("\\)", 5), ("\\[", 8), ("\\]", 13)]]
+        );
+        // Math continues onto the next lines until its closing
+        assert_eq!(
+            texts("tex", "\\(a\nb\\) \\["),
+            vec![vec![("\\(", 0)], vec![("\\)", 1), ("\\[", 4)]]
+        );
+    }
+
+    #[test]
+    fn test_latex_comments() {
+        // Delimiters after `%` are hidden, but not after `\%`
+        assert_eq!(
+            texts("tex", "( % ) [\n)"),
+            vec![vec![("(", 0), ("%", 2)], vec![(")", 0)]]
+        );
+        assert_eq!(
+            texts("tex", "\\% ( \\%) % )"),
+            vec![vec![("(", 3), (")", 7), ("%", 9)]]
+        );
+    }
+
+    #[test]
+    fn test_typst() {
+        assert_eq!(
+            texts("typst", "*strong* _emph_ snake_case"),
+            vec![vec![("*", 0), ("*", 7), ("_", 9), ("_", 14)]]
+        );
+        // Code entered by `#`, content blocks and math, with delimiters matched in each
+        assert_eq!(
+            texts("typst", "#f(x, (y))[*a*] $ f(x) $"),
+            vec![vec![
+                ("(", 2),
+                ("(", 6),
+                (")", 8),
+                (")", 9),
+                ("[", 10),
+                ("*", 11),
+                ("*", 13),
+                ("]", 14),
+                ("$", 16),
+                ("(", 19),
+                (")", 21),
+                ("$", 23)
+            ]]
+        );
+        // Brackets nested in a block are matched as delimiters beyond the depth of the spans,
+        // and only the outermost closing leaves code mode
+        let matches = parse("typst", "#f(((((((((x))))))))) *a*");
+        let (brackets, strong) = matches[0].split_at(matches[0].len() - 2);
+        assert!(brackets
+            .iter()
+            .all(|match_| matches!(match_.token, Token::Delimiter("(", ")"))));
+        assert_eq!(
+            brackets
+                .iter()
+                .map(|match_| match_.stack_height)
+                .collect::<Vec<_>>(),
+            (0..9).chain((0..9).rev()).map(Some).collect::<Vec<_>>()
+        );
+        assert_eq!(strong[0].token, Token::BlockSpan("strong", "*", "*"));
+        // Strings and comments in code blocks
+        assert_eq!(
+            texts("typst", "#{\n  let s = \"}\" // }\n  [*a*]\n}"),
+            vec![
+                vec![("{", 1)],
+                vec![("\"", 10), ("\"", 12), ("//", 14)],
+                vec![("[", 2), ("*", 3), ("*", 5), ("]", 6)],
+                vec![("}", 0)]
+            ]
+        );
+        // Nothing is matched in raw text
+        assert_eq!(
+            texts("typst", "= Title `*x*`\n```\n#{\n```"),
+            vec![
+                vec![("=", 0), ("`", 8), ("`", 12)],
+                vec![("```", 0)],
+                vec![],
+                vec![("```", 0)]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shell() {
+        assert_eq!(
+            texts("sh", "echo \"(\" $(ls) # )"),
+            vec![vec![("\"", 5), ("\"", 7), ("(", 10), (")", 13), ("#", 15)]]
+        );
+        assert_eq!(
+            texts("zsh", "x=('[' ${y})"),
+            vec![vec![
+                ("(", 2),
+                ("'", 3),
+                ("'", 5),
+                ("{", 8),
+                ("}", 10),
+                (")", 11)
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_csharp_alias() {
+        assert_eq!(
+            texts("cs", "var s = @\"(\" + \"\\\")\"; // {"),
+            vec![vec![
+                ("@\"", 8),
+                ("\"", 11),
+                ("\"", 15),
+                ("\"", 19),
+                ("//", 22)
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        // Plain JSON has no comments
+        assert_eq!(
+            texts("json", "[\"]\"] // ["),
+            vec![vec![("[", 0), ("\"", 1), ("\"", 3), ("]", 4), ("[", 9)]]
+        );
+    }
+
+    #[test]
+    fn test_jsonc() {
+        assert_eq!(
+            texts("jsonc", "{ \"a\": [\"]\"] } // {"),
+            vec![vec![
+                ("{", 0),
+                ("\"", 2),
+                ("\"", 4),
+                ("[", 7),
+                ("\"", 8),
+                ("\"", 10),
+                ("]", 11),
+                ("}", 13),
+                ("//", 15)
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_json5() {
+        assert_eq!(
+            texts("json5", "{ a: '}', /* [ */ b: [] }"),
+            vec![vec![
+                ("{", 0),
+                ("'", 5),
+                ("'", 7),
+                ("/*", 10),
+                ("*/", 15),
+                ("[", 21),
+                ("]", 22),
+                ("}", 24)
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_yaml() {
+        assert_eq!(
+            texts("yaml", "a: [b, '[', {c: d}] # ]"),
+            vec![vec![
+                ("[", 3),
+                ("'", 7),
+                ("'", 9),
+                ("{", 12),
+                ("}", 17),
+                ("]", 18),
+                ("#", 20)
+            ]]
+        );
+        // Quotes only open strings at the start of a scalar
+        assert_eq!(
+            texts("yaml", "msg: don't [x]"),
+            vec![vec![("[", 11), ("]", 13)]]
+        );
+        assert_eq!(
+            texts("yaml", "- !!str 'a' # b\n{\"c\":\"d\"}"),
+            vec![
+                vec![("'", 8), ("'", 10), ("#", 12)],
+                vec![
+                    ("{", 0),
+                    ("\"", 1),
+                    ("\"", 3),
+                    ("\"", 5),
+                    ("\"", 7),
+                    ("}", 8)
+                ]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_css() {
+        assert_eq!(
+            texts("css", "a[href='('] { content: \"}\"; } /* { */"),
+            vec![vec![
+                ("[", 1),
+                ("'", 7),
+                ("'", 9),
+                ("]", 10),
+                ("{", 12),
+                ("\"", 23),
+                ("\"", 25),
+                ("}", 28),
+                ("/*", 30),
+                ("*/", 35)
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_scss() {
+        assert_eq!(
+            texts("scss", "a { b: map-get($c, '}'); } // {"),
+            vec![vec![
+                ("{", 2),
+                ("(", 14),
+                ("'", 19),
+                ("'", 21),
+                (")", 22),
+                ("}", 25),
+                ("//", 27)
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_make() {
+        assert_eq!(
+            texts("make", "a: $(b) ${c} # ("),
+            vec![vec![("(", 4), (")", 6), ("{", 9), ("}", 11), ("#", 13)]]
+        );
+        // Apostrophes aren't strings
+        assert_eq!(
+            texts("make", "$(info it's) (a)"),
+            vec![vec![("(", 1), (")", 11), ("(", 13), (")", 15)]]
+        );
+    }
+
+    #[test]
+    fn test_cmake() {
+        assert_eq!(
+            texts("cmake", "set(A \"(\" [[ ) ]] ${B}) # ("),
+            vec![vec![
+                ("(", 3),
+                ("\"", 6),
+                ("\"", 8),
+                ("[[", 10),
+                ("]]", 15),
+                ("{", 19),
+                ("}", 21),
+                (")", 22),
+                ("#", 24)
+            ]]
+        );
+        // Bracket arguments and comments only close on the same number of `=`
+        assert_eq!(
+            texts("cmake", "f([=[ ]] ) ]=] ) #[==[ ]=] (\n]==] #("),
+            vec![
+                vec![("(", 1), ("[=[", 2), ("]=]", 11), (")", 15), ("#[==[", 17)],
+                vec![("]==]", 0), ("#", 5)]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dockerfile() {
+        assert_eq!(
+            texts("dockerfile", "CMD [\"echo\", \"]\"] # ["),
+            vec![vec![
+                ("[", 4),
+                ("\"", 5),
+                ("\"", 10),
+                ("\"", 13),
+                ("\"", 15),
+                ("]", 16),
+                ("#", 18)
+            ]]
+        );
+        // Quotes and comments only start at the start of a word
+        assert_eq!(
+            texts("dockerfile", "LABEL a=O'Brien (b) c='(' ${X#(}"),
+            vec![vec![
+                ("(", 16),
+                (")", 18),
+                ("'", 22),
+                ("'", 24),
+                ("{", 27),
+                ("(", 30),
+                ("}", 31)
+            ]]
+        );
+        assert_eq!(
+            texts("dockerfile", "RUN echo don't ( \\' ) # )"),
+            vec![vec![("(", 15), (")", 20), ("#", 22)]]
+        );
+    }
+
+    #[test]
+    fn test_hcl() {
+        assert_eq!(
+            texts("hcl", "a = { b = [\"}\"] } // {"),
+            vec![vec![
+                ("{", 4),
+                ("[", 10),
+                ("\"", 11),
+                ("\"", 13),
+                ("]", 14),
+                ("}", 16),
+                ("//", 18)
+            ]]
+        );
+        assert_eq!(
+            texts("terraform", "c = f(d) # ("),
+            vec![vec![("(", 5), (")", 7), ("#", 9)]]
+        );
+    }
+
+    #[test]
+    fn test_proto() {
+        assert_eq!(
+            texts("proto", "map<string, Foo> a = 1 [(b) = '[']; // {"),
+            vec![vec![
+                ("[", 23),
+                ("(", 24),
+                (")", 26),
+                ("'", 30),
+                ("'", 32),
+                ("]", 33),
+                ("//", 36)
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_graphql() {
+        assert_eq!(
+            texts("graphql", "query { a(b: \"(\") { c } } # {"),
+            vec![vec![
+                ("{", 6),
+                ("(", 9),
+                ("\"", 13),
+                ("\"", 15),
+                (")", 16),
+                ("{", 18),
+                ("}", 22),
+                ("}", 24),
+                ("#", 26)
+            ]]
+        );
+        // Backticks escape rather than backslashes, and doubled quotes are escaped quotes
+        assert_eq!(
+            texts("ps1", "\"C:\\Temp\\\" ( 'it''s (' \"a`\"(\" `( )"),
+            vec![vec![
+                ("\"", 0),
+                ("\"", 9),
+                ("(", 11),
+                ("'", 13),
+                ("'", 21),
+                ("\"", 23),
+                ("\"", 28),
+                (")", 33)
+            ]]
+        );
+        // Here-strings only close at the start of a line
+        assert_eq!(
+            texts("ps1", "@\"\n( \"@\n\"@ )"),
+            vec![vec![("@\"", 0)], vec![], vec![("\"@", 0), (")", 3)]]
+        );
+    }
+
+    #[test]
+    fn test_julia() {
+        assert_eq!(
+            texts("julia", "f(x') = '(' #= ( =# [1]"),
+            vec![vec![
+                ("(", 1),
+                (")", 4),
+                ("'", 8),
+                ("'", 10),
+                ("#=", 12),
+                ("=#", 17),
+                ("[", 20),
+                ("]", 22)
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_nim() {
+        assert_eq!(
+            texts("nim", "echo(\"(\", '[') #[ ( ]# # {"),
+            vec![vec![
+                ("(", 4),
+                ("\"", 5),
+                ("\"", 7),
+                ("'", 10),
+                ("'", 12),
+                (")", 13),
+                ("#[", 15),
+                ("]#", 20),
+                ("#", 23)
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_d() {
+        assert_eq!(
+            texts("d", "f(`(`, '[') /+ { +/ // ("),
+            vec![vec![
+                ("(", 1),
+                ("`", 2),
+                ("`", 4),
+                ("'", 7),
+                ("'", 9),
+                (")", 10),
+                ("/+", 12),
+                ("+/", 17),
+                ("//", 20)
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_powershell() {
+        assert_eq!(
+            texts("ps1", "$a = @{ b = '(' } <# } #> # ("),
+            vec![vec![
+                ("{", 6),
+                ("'", 12),
+                ("'", 14),
+                ("}", 16),
+                ("<#", 18),
+                ("#>", 23),
+                ("#", 26)
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_asm() {
+        assert_eq!(
+            texts("asm", "mov eax, [ebx + 4] ; ( '['"),
+            vec![vec![("[", 9), ("]", 17), (";", 19)]]
+        );
+    }
+
+    #[test]
+    fn test_fortran() {
+        assert_eq!(
+            texts("fortran", "a = b(1, '(') ! )"),
+            vec![vec![("(", 5), ("'", 9), ("'", 11), (")", 12), ("!", 14)]]
+        );
+    }
+
+    #[test]
+    fn test_ada() {
+        assert_eq!(
+            texts("ada", "X'First (A, '(') -- )"),
+            vec![vec![("(", 8), ("'", 12), ("'", 14), (")", 15), ("--", 17)]]
+        );
+    }
+
+    #[test]
+    fn test_verilog() {
+        assert_eq!(
+            texts("verilog", "assign a = {b[0], \"{\"}; // {"),
+            vec![vec![
+                ("{", 11),
+                ("[", 13),
+                ("]", 15),
+                ("\"", 18),
+                ("\"", 20),
+                ("}", 21),
+                ("//", 24)
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_vhdl() {
+        assert_eq!(
+            texts("vhdl", "a <= b('(') when clk'event -- ("),
+            vec![vec![("(", 6), ("'", 7), ("'", 9), (")", 10), ("--", 27)]]
+        );
+    }
+
+    #[test]
+    fn test_clojure_char_literals() {
+        assert_eq!(
+            parse("clojure", "(\\( \\\")"),
+            vec![vec![
+                Match::delimiter('(', 0, Some(0)),
+                Match::delimiter(')', 6, Some(0))
+            ]]
+        );
+    }
+
+    /// Gets the text and column of the matches on each line
+    fn texts(filetype: &str, lines: &str) -> Vec<Vec<(&'static str, usize)>> {
+        parse(filetype, lines)
+            .into_iter()
+            .map(|matches| {
+                matches
+                    .iter()
+                    .map(|match_| match match_.kind {
+                        Kind::Closing => (
+                            match_.token.closing().unwrap_or(match_.token.opening()),
+                            match_.col,
+                        ),
+                        _ => (match_.token.opening(), match_.col),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_markdown() {
+        assert_eq!(
+            texts("markdown", "**bold** and *italic*"),
+            vec![vec![("**", 0), ("**", 6), ("*", 13), ("*", 20)]]
+        );
+        assert_eq!(
+            texts("markdown", "```\n*a*\n```"),
+            vec![vec![("```", 0)], vec![], vec![("```", 0)]]
+        );
+    }
+
+    #[test]
+    fn test_markdown_intraword_emphasis() {
+        assert_eq!(texts("markdown", "* snake_case"), vec![vec![]]);
+        // `_` doesn't open or close inside of words, unlike `*`
+        assert_eq!(texts("markdown", "a_b_ a__b__"), vec![vec![]]);
+        assert_eq!(
+            texts("markdown", "a*b* _a_ (_b_)"),
+            vec![vec![
+                ("*", 1),
+                ("*", 3),
+                ("_", 5),
+                ("_", 7),
+                ("_", 10),
+                ("_", 12)
+            ]]
+        );
+        // The `_` inside the word is skipped, closing on the `_` at the end of the next word
+        assert_eq!(texts("markdown", "_a_b c_"), vec![vec![("_", 0), ("_", 6)]]);
+    }
+
+    #[test]
+    fn test_markdown_triple_emphasis() {
+        // `***` opens italic with bold nested in it
+        let spans = |lines: &str| {
+            parse("markdown", lines)
+                .into_iter()
+                .flatten()
+                .map(|match_| match match_.token {
+                    Token::BlockSpan(name, _, _) => (match_.kind, name, match_.col),
+                    token => panic!("unexpected token {token:?}"),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            spans("***a***"),
+            vec![
+                (Kind::Opening, "italic", 0),
+                (Kind::Opening, "bold", 1),
+                (Kind::Closing, "bold", 4),
+                (Kind::Closing, "italic", 6),
+            ]
+        );
+        assert_eq!(
+            spans("***a** b*"),
+            vec![
+                (Kind::Opening, "italic", 0),
+                (Kind::Opening, "bold", 1),
+                (Kind::Closing, "bold", 4),
+                (Kind::Closing, "italic", 8),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_markdown_code_spans() {
+        // Code spans only close on a run of the same number of backticks
+        assert_eq!(
+            texts("markdown", "``a ` b`` `c`"),
+            vec![vec![("``", 0), ("``", 7), ("`", 10), ("`", 12)]]
+        );
+        assert_eq!(
+            texts("markdown", "``a`b *c* ``` d``"),
+            vec![vec![("``", 0), ("``", 15)]]
+        );
+    }
+
+    #[test]
+    fn test_markdown_math() {
+        // `$` followed by a digit is a price rather than math
+        assert_eq!(
+            texts("markdown", "$5 and $x$"),
+            vec![vec![("$", 7), ("$", 9)]]
+        );
+        assert_eq!(
+            texts("markdown", "$a$5 b$ $$c$$"),
+            vec![vec![("$", 0), ("$", 6), ("$$", 8), ("$$", 11)]]
+        );
+    }
+
+    #[test]
+    fn test_markdown_links() {
+        // Parentheses only count as the destination of a link, directly after the `]`
+        assert_eq!(
+            texts("markdown", "(a) [b] (c)"),
+            vec![vec![("[", 4), ("]", 6)]]
+        );
+        assert_eq!(
+            texts("markdown", "[a](b (c)) (d)"),
+            vec![vec![
+                ("[", 0),
+                ("]", 2),
+                ("(", 3),
+                ("(", 6),
+                (")", 8),
+                (")", 9)
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_markdown_paragraphs() {
+        // Emphasis continues onto the next lines until the end of the paragraph
+        assert_eq!(
+            texts("markdown", "*a\nb*\n\n*c\n\nd*"),
+            vec![
+                vec![("*", 0)],
+                vec![("*", 1)],
+                vec![],
+                vec![("*", 0)],
+                vec![],
+                vec![]
+            ]
+        );
+        // Lines of whitespace are blank too
+        assert_eq!(
+            texts("markdown", "**a\n  \nb**"),
+            vec![vec![("**", 0)], vec![], vec![]]
+        );
+        // Code fences end the paragraph
+        assert_eq!(
+            texts("markdown", "*a\n```\nb*\n```"),
+            vec![vec![("*", 0)], vec![("```", 0)], vec![], vec![("```", 0)]]
+        );
+    }
 }